@@ -0,0 +1,439 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use cgmath::{Angle, Deg, EuclideanSpace, InnerSpace, Point2, Rad, Vector2};
+use crate::{csv, csv::Columns, data::project, geodesy};
+use pointing_utils::{GeoPos, LatLon, uom};
+use std::{collections::HashMap, error::Error, path::PathBuf};
+use uom::{si::f64, si::{length, velocity}};
+
+/// Approach hints are given within this distance (meters) from the runway threshold (or airport).
+const MAX_APPROACH_DISTANCE: f64 = 40_000.0;
+/// Maximum difference between the aircraft's track and the runway direction (or the bearing to the airport).
+const MAX_APPROACH_TRACK_ERROR: Deg<f64> = Deg(20.0);
+/// Maximum height (meters) above the runway at the threshold; grows with distance at `MAX_APPROACH_SLOPE`.
+const MAX_APPROACH_HEIGHT: f64 = 300.0;
+/// Twice the usual 3° glide slope, to allow for step-down approaches.
+const MAX_APPROACH_SLOPE: Deg<f64> = Deg(6.0);
+/// Maximum distance (meters) from the extended runway centerline at the threshold; grows with distance by 15%.
+const MAX_CENTERLINE_OFFSET: f64 = 500.0;
+/// Aircraft climbing faster (m/s) are not approaching.
+const MAX_APPROACH_VERTICAL_RATE: f64 = 2.5;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AirportKind {
+    Small,
+    Medium,
+    Large,
+    Heliport,
+    SeaplaneBase,
+    BalloonPort
+}
+
+impl std::str::FromStr for AirportKind {
+    type Err = String;
+
+    /// Parses OurAirports' airport type.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "small_airport" => Ok(AirportKind::Small),
+            "medium_airport" => Ok(AirportKind::Medium),
+            "large_airport" => Ok(AirportKind::Large),
+            "heliport" => Ok(AirportKind::Heliport),
+            "seaplane_base" => Ok(AirportKind::SeaplaneBase),
+            "balloonport" => Ok(AirportKind::BalloonPort),
+            _ => Err(format!("unknown airport type \"{}\"", s))
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RunwayEnd {
+    /// E.g., "09L".
+    pub ident: String,
+    pub lat_lon: LatLon,
+    /// Projected position (see `data::project`).
+    pub projected: Point2<f64>
+}
+
+/// Runway; ends whose position is unknown are absent.
+#[derive(Clone, Debug)]
+pub struct Runway {
+    pub low_end: Option<RunwayEnd>,
+    pub high_end: Option<RunwayEnd>
+}
+
+#[derive(Clone, Debug)]
+pub struct Airport {
+    /// ICAO code (or other identifier if the airport has none).
+    pub ident: String,
+    pub name: String,
+    pub kind: AirportKind,
+    pub lat_lon: LatLon,
+    /// Projected position (see `data::project`).
+    pub projected: Point2<f64>,
+    pub elevation: Option<f64::Length>,
+    pub runways: Vec<Runway>
+}
+
+#[derive(Clone, Debug)]
+pub struct Navaid {
+    pub ident: String,
+    pub name: String,
+    /// E.g., "VOR-DME", "NDB".
+    pub kind: String,
+    pub lat_lon: LatLon,
+    /// Projected position (see `data::project`).
+    pub projected: Point2<f64>
+}
+
+/// OurAirports CSV files (https://ourairports.com/data/).
+#[derive(Clone, Debug)]
+pub struct Files {
+    /// "airports.csv".
+    pub airports: PathBuf,
+    /// "runways.csv".
+    pub runways: Option<PathBuf>,
+    /// "navaids.csv".
+    pub navaids: Option<PathBuf>
+}
+
+/// Aircraft apparently approaching an airport.
+#[derive(Clone, Debug)]
+pub struct Approach<'a> {
+    pub airport: &'a Airport,
+    /// Runway end identifier, if the approach could be matched to a runway.
+    pub runway: Option<&'a str>,
+    /// Distance to the threshold (or airport).
+    pub distance: f64::Length
+}
+
+/// Airports (with runways) and navaids around the observer.
+pub struct Database {
+    pub airports: Vec<Airport>,
+    pub navaids: Vec<Navaid>
+}
+
+impl Database {
+    /// Loads airports and navaids within `max_distance` from `observer`; closed airports are skipped.
+    pub fn load(files: &Files, observer: &LatLon, max_distance: f64::Length) -> Result<Database, Box<dyn Error>> {
+        let max_dist = max_distance.get::<length::meter>();
+        let is_near = |lat_lon: &LatLon| -> Option<Point2<f64>> {
+            let projected = project(observer, lat_lon);
+            if projected.to_vec().magnitude() <= max_dist && is_near_side(observer, lat_lon) {
+                Some(projected)
+            } else {
+                None
+            }
+        };
+
+        let mut airports = vec![];
+        let mut airport_indices = HashMap::<String, usize>::new();
+        let mut reader = csv::Reader::open(&files.airports)?;
+        while let Some(record) = reader.next_record()? {
+            let (columns, record) = (reader.columns(), &record);
+            let kind = match columns.get(record, "type").map(|t| t.parse::<AirportKind>()) {
+                Some(Ok(kind)) => kind,
+                _ => continue // e.g., "closed"
+            };
+            let ident = match columns.get(record, "ident") { Some(ident) => ident, None => continue };
+            let lat_lon = match parse_lat_lon(columns, record, "latitude_deg", "longitude_deg") {
+                Some(lat_lon) => lat_lon,
+                None => continue
+            };
+            let projected = match is_near(&lat_lon) { Some(p) => p, None => continue };
+
+            airport_indices.insert(ident.to_string(), airports.len());
+            airports.push(Airport{
+                ident: columns.get(record, "icao_code").or(columns.get(record, "gps_code")).unwrap_or(ident).to_string(),
+                name: columns.get(record, "name").unwrap_or("").to_string(),
+                kind,
+                lat_lon,
+                projected,
+                elevation: columns.get(record, "elevation_ft")
+                    .and_then(|e| e.parse::<f64>().ok())
+                    .map(f64::Length::new::<length::foot>),
+                runways: vec![]
+            });
+        }
+
+        if let Some(path) = &files.runways {
+            let mut reader = csv::Reader::open(path)?;
+            while let Some(record) = reader.next_record()? {
+                let (columns, record) = (reader.columns(), &record);
+                if columns.get(record, "closed") == Some("1") { continue; }
+                let index = match columns.get(record, "airport_ident").and_then(|id| airport_indices.get(id)) {
+                    Some(index) => *index,
+                    None => continue
+                };
+
+                let runway_end = |prefix: &str| -> Option<RunwayEnd> {
+                    let lat_lon = parse_lat_lon(
+                        columns, record, &format!("{}_latitude_deg", prefix), &format!("{}_longitude_deg", prefix)
+                    )?;
+                    Some(RunwayEnd{
+                        ident: columns.get(record, &format!("{}_ident", prefix))?.to_string(),
+                        projected: project(observer, &lat_lon),
+                        lat_lon
+                    })
+                };
+                airports[index].runways.push(Runway{ low_end: runway_end("le"), high_end: runway_end("he") });
+            }
+        }
+
+        let mut navaids = vec![];
+        if let Some(path) = &files.navaids {
+            let mut reader = csv::Reader::open(path)?;
+            while let Some(record) = reader.next_record()? {
+                let (columns, record) = (reader.columns(), &record);
+                let lat_lon = match parse_lat_lon(columns, record, "latitude_deg", "longitude_deg") {
+                    Some(lat_lon) => lat_lon,
+                    None => continue
+                };
+                let projected = match is_near(&lat_lon) { Some(p) => p, None => continue };
+                navaids.push(Navaid{
+                    ident: columns.get(record, "ident").unwrap_or("").to_string(),
+                    name: columns.get(record, "name").unwrap_or("").to_string(),
+                    kind: columns.get(record, "type").unwrap_or("").to_string(),
+                    lat_lon,
+                    projected
+                });
+            }
+        }
+
+        Ok(Database{ airports, navaids })
+    }
+
+    /// Returns the airport (excluding heliports, seaplane bases and balloon ports) nearest to `projected`
+    /// (see `data::project`) and its distance (in projection).
+    pub fn nearest_airport(&self, projected: Point2<f64>) -> Option<(&Airport, f64::Length)> {
+        self.airports.iter()
+            .filter(|a| is_aerodrome(a.kind))
+            .map(|a| (a, (a.projected - projected).magnitude()))
+            .min_by(|(_, d1), (_, d2)| d1.total_cmp(d2))
+            .map(|(a, d)| (a, f64::Length::new::<length::meter>(d)))
+    }
+
+    /// Returns the nearest airport (and runway, if known) the aircraft seems to be approaching, judging by its track,
+    /// height above the airport and distance (`projected`: see `data::project`).
+    pub fn approach<'a>(
+        &'a self,
+        lat_lon: &LatLon,
+        projected: Point2<f64>,
+        altitude: f64::Length,
+        track: Deg<f64>,
+        vertical_rate: Option<f64::Velocity>
+    ) -> Option<Approach<'a>> {
+        if vertical_rate.is_some_and(|v| v.get::<velocity::meter_per_second>() > MAX_APPROACH_VERTICAL_RATE) {
+            return None;
+        }
+
+        let track_dir = direction(track);
+        let mut best: Option<Approach<'a>> = None;
+
+        // leave a margin for runway lengths and projection errors
+        let candidates = self.airports.iter().filter(|a| {
+            is_aerodrome(a.kind) && (a.projected - projected).magnitude() < 1.1 * MAX_APPROACH_DISTANCE + 5000.0
+        });
+        for airport in candidates {
+            let elevation = airport.elevation.unwrap_or(f64::Length::new::<length::meter>(0.0));
+            let height = (altitude - elevation).get::<length::meter>();
+            let aircraft_pos = geodesy::geodetic_to_ecef(&GeoPos{ lat_lon: lat_lon.clone(), elevation });
+
+            // `runway_end`: the far end of the runway whose threshold is `reference`
+            let check = |reference: &LatLon, runway_end: Option<&LatLon>| -> Option<f64> {
+                let frame = geodesy::LocalFrame::new(&GeoPos{ lat_lon: reference.clone(), elevation });
+                let horizontal = |enu: cgmath::Vector3<f64>| Vector2{ x: enu.x, y: enu.y };
+                // from the reference point to the aircraft
+                let offset = horizontal(frame.to_enu_point(&aircraft_pos));
+
+                let (distance, approach_dir) = match runway_end {
+                    Some(runway_end) => {
+                        let runway_dir = horizontal(frame.to_enu_point(
+                            &geodesy::geodetic_to_ecef(&GeoPos{ lat_lon: runway_end.clone(), elevation })
+                        )).normalize();
+                        let along = -offset.dot(runway_dir);
+                        let across = (offset.x * runway_dir.y - offset.y * runway_dir.x).abs();
+                        if along <= 0.0 || across > MAX_CENTERLINE_OFFSET + 0.15 * along { return None; }
+                        (along, runway_dir)
+                    },
+                    None => {
+                        let distance = offset.magnitude();
+                        if distance == 0.0 { return None; }
+                        (distance, -offset / distance)
+                    }
+                };
+
+                let max_height = MAX_APPROACH_HEIGHT + distance * Rad::from(MAX_APPROACH_SLOPE).0.tan();
+                let track_error = Deg::from(Rad(track_dir.dot(approach_dir).clamp(-1.0, 1.0).acos()));
+                if distance > MAX_APPROACH_DISTANCE || height > max_height || track_error > MAX_APPROACH_TRACK_ERROR {
+                    None
+                } else {
+                    Some(distance)
+                }
+            };
+
+            let mut candidate = |runway: Option<&'a str>, distance: Option<f64>| {
+                if let Some(distance) = distance {
+                    if best.as_ref().is_none_or(|b| distance < b.distance.get::<length::meter>()) {
+                        best = Some(Approach{ airport, runway, distance: f64::Length::new::<length::meter>(distance) });
+                    }
+                }
+            };
+
+            let mut has_runway_positions = false;
+            for runway in &airport.runways {
+                if let (Some(low), Some(high)) = (&runway.low_end, &runway.high_end) {
+                    has_runway_positions = true;
+                    for (threshold, far_end) in [(low, high), (high, low)] {
+                        candidate(Some(&threshold.ident), check(&threshold.lat_lon, Some(&far_end.lat_lon)));
+                    }
+                }
+            }
+            if !has_runway_positions {
+                candidate(None, check(&airport.lat_lon, None));
+            }
+        }
+
+        best
+    }
+}
+
+fn is_aerodrome(kind: AirportKind) -> bool {
+    matches!(kind, AirportKind::Small | AirportKind::Medium | AirportKind::Large)
+}
+
+/// Returns unit vector (East, North) pointing towards `azimuth`.
+fn direction(azimuth: Deg<f64>) -> Vector2<f64> {
+    let azimuth = Rad::from(azimuth).0;
+    Vector2{ x: azimuth.sin(), y: azimuth.cos() }
+}
+
+fn is_near_side(observer: &LatLon, lat_lon: &LatLon) -> bool {
+    observer.lat.sin() * lat_lon.lat.sin() + observer.lat.cos() * lat_lon.lat.cos() * (lat_lon.lon - observer.lon).cos() >= 0.0
+}
+
+fn parse_lat_lon(columns: &Columns, record: &[String], lat_column: &str, lon_column: &str) -> Option<LatLon> {
+    Some(LatLon{
+        lat: Deg(columns.get(record, lat_column)?.parse::<f64>().ok()?),
+        lon: Deg(columns.get(record, lon_column)?.parse::<f64>().ok()?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AIRPORTS: &str = r#""id","ident","type","name","latitude_deg","longitude_deg","elevation_ft","gps_code","icao_code"
+4185,"EPWA","large_airport","Warsaw Chopin Airport",52.165699,20.967100,362,"EPWA","EPWA"
+1,"PL-0001","closed","Old Field",52.0,21.0,,,
+2,"EPMO-1","medium_airport","Warsaw Modlin Airport",52.451099,20.6518,341,"EPMO",
+3,"KJFK","large_airport","John F Kennedy International Airport",40.639447,-73.779317,13,"KJFK","KJFK"
+4,"PL-0002","heliport","Hospital ""Roof, North""",52.2,21.0,,,
+"#;
+
+    const RUNWAYS: &str = r#""id","airport_ref","airport_ident","closed","le_ident","le_latitude_deg","le_longitude_deg","he_ident","he_latitude_deg","he_longitude_deg"
+1,4185,"EPWA",0,"11",52.1656,20.9384,"29",52.1589,20.9931
+2,4185,"EPWA",0,"15",52.1780,20.9630,"33",52.1553,20.9750
+3,4185,"EPWA",1,"01",52.1600,20.9600,"19",52.1700,20.9600
+4,2,"EPMO-1",0,"08",,,"26",52.4500,20.6700
+5,99,"XXXX",0,"09",52.0,21.0,"27",52.0,21.1
+"#;
+
+    const NAVAIDS: &str = r#""id","filename","ident","name","type","frequency_khz","latitude_deg","longitude_deg"
+1,"Warszawa_VOR-DME_PL","WAR","Warszawa","VOR-DME",113450,52.1797,20.9602
+2,"Far_NDB","FAR","Far","NDB",400,10.0,10.0
+"#;
+
+    fn observer() -> LatLon {
+        LatLon{ lat: Deg(52.0), lon: Deg(21.0) }
+    }
+
+    /// Writes `contents` to a temporary file (removed when dropped).
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &str) -> TempFile {
+            // tests run in parallel
+            static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+            let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let path = std::env::temp_dir().join(format!("plane-tracker-test-{}-{}-{}", std::process::id(), n, name));
+            std::fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn database() -> Database {
+        let airports = TempFile::new("airports.csv", AIRPORTS);
+        let runways = TempFile::new("runways.csv", RUNWAYS);
+        let navaids = TempFile::new("navaids.csv", NAVAIDS);
+        let files = Files{ airports: airports.0.clone(), runways: Some(runways.0.clone()), navaids: Some(navaids.0.clone()) };
+
+        Database::load(&files, &observer(), f64::Length::new::<length::kilometer>(100.0)).unwrap()
+    }
+
+    #[test]
+    fn loads_nearby_airports_and_navaids() {
+        let db = database();
+
+        let idents: Vec<&str> = db.airports.iter().map(|a| a.ident.as_str()).collect();
+        assert_eq!(vec!["EPWA", "EPMO", "PL-0002"], idents);
+
+        let epwa = &db.airports[0];
+        assert_eq!(AirportKind::Large, epwa.kind);
+        assert_eq!("Warsaw Chopin Airport", epwa.name);
+        assert!((epwa.elevation.unwrap().get::<length::foot>() - 362.0).abs() < 1.0e-9);
+        let runway_idents: Vec<&str> = epwa.runways.iter().map(|r| r.low_end.as_ref().unwrap().ident.as_str()).collect();
+        assert_eq!(vec!["11", "15"], runway_idents);
+
+        let epmo = &db.airports[1];
+        assert_eq!(1, epmo.runways.len());
+        assert!(epmo.runways[0].low_end.is_none());
+        assert_eq!("26", epmo.runways[0].high_end.as_ref().unwrap().ident);
+
+        let heliport = &db.airports[2];
+        assert_eq!(AirportKind::Heliport, heliport.kind);
+        assert_eq!("Hospital \"Roof, North\"", heliport.name);
+        assert!(heliport.elevation.is_none());
+
+        assert_eq!(1, db.navaids.len());
+        assert_eq!(("WAR", "VOR-DME"), (db.navaids[0].ident.as_str(), db.navaids[0].kind.as_str()));
+    }
+
+    #[test]
+    fn nearest_airport_skips_heliports() {
+        let db = database();
+        let (airport, _) = db.nearest_airport(project(&observer(), &LatLon{ lat: Deg(52.2), lon: Deg(21.0) })).unwrap();
+        assert_eq!("EPWA", airport.ident);
+    }
+
+    #[test]
+    fn approach() {
+        let db = database();
+        // 8 km before the threshold of runway 33, about 450 m above it
+        let lat_lon = LatLon{ lat: Deg(52.0869), lon: Deg(21.0111) };
+        let projected = project(&observer(), &lat_lon);
+        let altitude = f64::Length::new::<length::meter>(560.0);
+        let climbing = f64::Velocity::new::<velocity::meter_per_second>(10.0);
+
+        let approach = db.approach(&lat_lon, projected, altitude, Deg(342.0), None).unwrap();
+        assert_eq!("EPWA", approach.airport.ident);
+        assert_eq!(Some("33"), approach.runway);
+        assert!((approach.distance.get::<length::meter>() - 8000.0).abs() < 100.0);
+
+        assert!(db.approach(&lat_lon, projected, altitude, Deg(162.0), None).is_none());
+        assert!(db.approach(&lat_lon, projected, altitude, Deg(342.0), Some(climbing)).is_none());
+        assert!(db.approach(&lat_lon, projected, f64::Length::new::<length::meter>(3000.0), Deg(342.0), None).is_none());
+    }
+}
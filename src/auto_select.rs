@@ -0,0 +1,431 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use cgmath::Deg;
+use crate::country;
+use crate::data::{Aircraft, ModeSTransponderCode, extrapolate_position, get_distance, global_azimuth_elevation, to_global};
+use crate::visibility::Visibility;
+use pointing_utils::{GeoPos, uom};
+use std::collections::HashMap;
+use uom::si::length;
+
+/// Minimal time a target is kept before switching to a better one.
+const MIN_HOLD_TIME: std::time::Duration = std::time::Duration::from_secs(30);
+/// Aircraft whose position has not been updated for this long are not selected.
+const MAX_POSITION_AGE: std::time::Duration = std::time::Duration::from_secs(10);
+/// A closer aircraft replaces the current one only if its distance is smaller by this fraction.
+const DISTANCE_MARGIN: f64 = 0.2;
+/// A higher aircraft replaces the current one only if its elevation is greater by this value.
+const ELEVATION_MARGIN: Deg<f64> = Deg(5.0);
+/// The current target is kept until it is outside the sky region by this value.
+const REGION_MARGIN: Deg<f64> = Deg(2.0);
+/// Aircraft predicted to enter the sky region later than this are not selected.
+const REGION_LOOK_AHEAD: std::time::Duration = std::time::Duration::from_secs(180);
+/// Time step of predicting entry into the sky region.
+const REGION_PREDICTION_STEP: std::time::Duration = std::time::Duration::from_secs(2);
+
+#[derive(Clone, Debug)]
+pub struct SkyRegion {
+    /// Azimuth range (measured from North towards East); may wrap around North (`az_min` > `az_max`).
+    pub az_min: Deg<f64>,
+    pub az_max: Deg<f64>,
+    pub el_min: Deg<f64>,
+    pub el_max: Deg<f64>
+}
+
+impl SkyRegion {
+    fn contains(&self, azimuth: Deg<f64>, elevation: Deg<f64>, margin: Deg<f64>) -> bool {
+        let az_min = self.az_min - margin;
+        let az_max = self.az_max + margin;
+        let in_az = if self.az_min.0 <= self.az_max.0 {
+            azimuth.0 >= az_min.0 && azimuth.0 <= az_max.0
+        } else {
+            azimuth.0 >= az_min.0 || azimuth.0 <= az_max.0
+        };
+
+        in_az && elevation.0 >= (self.el_min - margin).0 && elevation.0 <= (self.el_max + margin).0
+    }
+}
+
+impl std::str::FromStr for SkyRegion {
+    type Err = String;
+
+    /// Parses "<az. min>;<az. max>;<el. min>;<el. max>" (degrees).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s.split(';').map(|v| v.trim().parse::<f64>()).collect::<Result<Vec<f64>, _>>()
+            .map_err(|e| format!("invalid sky region \"{}\": {}", s, e))?;
+        if values.len() != 4 { return Err(format!("invalid sky region \"{}\": expected 4 values", s)); }
+        if values[2] > values[3] { return Err(format!("invalid sky region \"{}\": el. min > el. max", s)); }
+
+        Ok(SkyRegion{ az_min: Deg(values[0]), az_max: Deg(values[1]), el_min: Deg(values[2]), el_max: Deg(values[3]) })
+    }
+}
+
+impl std::fmt::Display for SkyRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{};{};{};{}", self.az_min.0, self.az_max.0, self.el_min.0, self.el_max.0)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Policy {
+    /// Closest aircraft above the horizon mask (aircraft hidden by the mask are skipped, however close).
+    Closest,
+    HighestElevation,
+    /// ICAO addresses, callsigns or address block criteria (see `country::Criterion`), in order of priority.
+    Watchlist(Vec<String>),
+    /// Selects aircraft in the order of entering the region (those inside first, then the one predicted
+    /// to enter next).
+    SkyRegion(SkyRegion)
+}
+
+pub struct AutoSelector {
+    pub policy: Policy,
+    /// Current target and time of its selection.
+    current: Option<(ModeSTransponderCode, std::time::Instant)>,
+    /// Aircraft selected elsewhere (e.g., by user); auto-selection is suspended until it drops out.
+    manual: Option<ModeSTransponderCode>,
+    /// False until the first call of `choose` (whose selection is not considered manual).
+    started: bool,
+    /// Time of entering the sky region by aircraft currently inside it.
+    region_entries: HashMap<ModeSTransponderCode, std::time::Instant>
+}
+
+/// Returns true if `aircraft` has enough up-to-date data to be tracked.
+fn is_eligible(aircraft: &Aircraft, now: std::time::Instant) -> bool {
+    aircraft.lat_lon.as_ref().is_some_and(|(_, t)| now.saturating_duration_since(*t) <= MAX_POSITION_AGE)
+        && aircraft.altitude.is_some()
+        && aircraft.track.is_some()
+        && aircraft.ground_speed.is_some()
+}
+
+/// Returns true if `aircraft` is no longer tracked or its position has gone stale (if the position is unknown:
+/// if no message has been received recently).
+fn has_dropped_out(aircraft: Option<&Aircraft>, now: std::time::Instant) -> bool {
+    match aircraft {
+        None => true,
+        Some(aircraft) => {
+            let t_last = aircraft.lat_lon.as_ref().map_or(aircraft.t_last_update, |(_, t)| *t);
+            now.saturating_duration_since(t_last) > MAX_POSITION_AGE
+        }
+    }
+}
+
+/// Returns time after which `aircraft` (currently outside `region`) is predicted to enter it and be visible;
+/// `None` if not within `REGION_LOOK_AHEAD`.
+fn time_to_region_entry(
+    region: &SkyRegion,
+    aircraft: &Aircraft,
+    observer: &GeoPos,
+    visibility: &Visibility,
+    now: std::time::Instant,
+    margin: Deg<f64>
+) -> Option<std::time::Duration> {
+    let mut dt = REGION_PREDICTION_STEP;
+    while dt <= REGION_LOOK_AHEAD {
+        let position = extrapolate_position(aircraft, now + dt)?;
+        let (azimuth, true_elevation) = global_azimuth_elevation(observer, &to_global(&position));
+        let elevation = visibility.apparent_elevation(true_elevation);
+        if visibility.is_visible(azimuth, elevation) && region.contains(azimuth, elevation, margin) {
+            return Some(dt);
+        }
+        dt += REGION_PREDICTION_STEP;
+    }
+
+    None
+}
+
+fn watchlist_priority(watchlist: &[String], aircraft: &Aircraft) -> Option<usize> {
+    let id = aircraft.id.to_string();
+    watchlist.iter().position(|entry| {
+        entry.parse::<country::Criterion>().is_ok_and(|c| c.matches(aircraft.id))
+            || entry.eq_ignore_ascii_case(&id)
+            || aircraft.callsign.as_ref().is_some_and(|c| c.trim().eq_ignore_ascii_case(entry.trim()))
+    })
+}
+
+impl AutoSelector {
+    pub fn new(policy: Policy) -> AutoSelector {
+        AutoSelector{ policy, current: None, manual: None, started: false, region_entries: HashMap::new() }
+    }
+
+    /// Returns score of `aircraft` (the higher the better); `None` if it cannot be selected.
+    /// `is_current`: whether `aircraft` is the current target (which gets the benefit of hysteresis).
+    /// Aircraft hidden below the horizon mask are never selected.
+    fn score(
+        &self,
+        aircraft: &Aircraft,
+        observer: &GeoPos,
+        visibility: &Visibility,
+        now: std::time::Instant,
+        is_current: bool
+    ) -> Option<f64> {
+        if !is_eligible(aircraft, now) { return None; }
+
+        let position = visibility.apparent_position(observer, aircraft, true)?;
+
+        if let Policy::SkyRegion(region) = &self.policy {
+            let margin = if is_current { REGION_MARGIN } else { Deg(0.0) };
+            if position.visible && region.contains(position.azimuth, position.elevation, margin) {
+                if is_current { return Some(f64::INFINITY); } // keep until it leaves the region
+
+                // aircraft inside the region come before those approaching it; the earlier the entry, the better
+                return self.region_entries.get(&aircraft.id).map(|t| now.saturating_duration_since(*t).as_secs_f64());
+            }
+
+            // the sooner the predicted entry, the better
+            return time_to_region_entry(region, aircraft, observer, visibility, now, margin).map(|dt| -dt.as_secs_f64());
+        }
+
+        if !position.visible { return None; }
+
+        match &self.policy {
+            Policy::Closest => {
+                let distance = get_distance(observer, aircraft, true).get::<length::meter>();
+                Some(-distance * if is_current { 1.0 - DISTANCE_MARGIN } else { 1.0 })
+            },
+
+            Policy::HighestElevation => {
+                Some(position.elevation.0 + if is_current { ELEVATION_MARGIN.0 } else { 0.0 })
+            },
+
+            Policy::Watchlist(watchlist) => watchlist_priority(watchlist, aircraft).map(|p| -(p as f64)),
+
+            Policy::SkyRegion(_) => unreachable!()
+        }
+    }
+
+    fn update_region_entries(
+        &mut self,
+        aircraft: &HashMap<ModeSTransponderCode, Aircraft>,
+        observer: &GeoPos,
+        visibility: &Visibility,
+        now: std::time::Instant
+    ) {
+        let region = match &self.policy {
+            Policy::SkyRegion(region) => region,
+            _ => return
+        };
+
+        let inside: Vec<ModeSTransponderCode> = aircraft
+            .values()
+            .filter(|a| is_eligible(a, now))
+            .filter(|a| visibility
+                .apparent_position(observer, a, true)
+                .is_some_and(|p| p.visible && region.contains(p.azimuth, p.elevation, Deg(0.0))))
+            .map(|a| a.id)
+            .collect();
+
+        self.region_entries.retain(|id, _| inside.contains(id));
+        for id in inside {
+            self.region_entries.entry(id).or_insert(now);
+        }
+    }
+
+    /// Returns the aircraft which should be selected (`None`: no aircraft); `selected`: currently selected aircraft.
+    /// An aircraft selected elsewhere is kept until it drops out.
+    pub fn choose(
+        &mut self,
+        aircraft: &HashMap<ModeSTransponderCode, Aircraft>,
+        selected: Option<ModeSTransponderCode>,
+        observer: &GeoPos,
+        visibility: &Visibility,
+        now: std::time::Instant
+    ) -> Option<ModeSTransponderCode> {
+        self.update_region_entries(aircraft, observer, visibility, now);
+
+        // the selection might have been changed elsewhere (e.g., by user)
+        if self.current.map(|c| c.0) != selected {
+            self.current = selected.map(|id| (id, now));
+            if self.started { self.manual = selected; }
+        }
+        self.started = true;
+
+        if let Some(id) = self.manual {
+            if !has_dropped_out(aircraft.get(&id), now) { return selected; }
+            self.manual = None;
+        }
+
+        let current_score = self.current
+            .and_then(|(id, _)| aircraft.get(&id))
+            .and_then(|a| self.score(a, observer, visibility, now, true));
+
+        if current_score.is_some() && now.saturating_duration_since(self.current.unwrap().1) < MIN_HOLD_TIME {
+            return selected;
+        }
+
+        let best = aircraft
+            .values()
+            .filter(|a| Some(a.id) != selected)
+            .filter_map(|a| self.score(a, observer, visibility, now, false).map(|score| (a.id, score)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        let chosen = match (current_score, best) {
+            (Some(current_score), Some((id, score))) => if score > current_score { Some(id) } else { selected },
+            (Some(_), None) => selected,
+            (None, best) => best.map(|(id, _)| id)
+        };
+
+        if chosen != selected {
+            self.current = chosen.map(|id| (id, now));
+        }
+
+        chosen
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+    Off,
+    Closest,
+    HighestElevation,
+    Watchlist,
+    SkyRegion
+}
+
+impl Mode {
+    pub const ALL: [Mode; 5] = [Mode::Off, Mode::Closest, Mode::HighestElevation, Mode::Watchlist, Mode::SkyRegion];
+}
+
+impl std::fmt::Display for Mode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Mode::Off => "off",
+            Mode::Closest => "closest",
+            Mode::HighestElevation => "highest-elevation",
+            Mode::Watchlist => "watchlist",
+            Mode::SkyRegion => "sky-region"
+        })
+    }
+}
+
+impl std::str::FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Mode::ALL.iter().find(|m| m.to_string() == s).copied().ok_or_else(|| format!("unknown auto-select mode \"{}\"", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::State;
+    use pointing_utils::LatLon;
+    use uom::si::{f64, velocity};
+
+    /// Kilometers per degree of latitude.
+    const KM_PER_DEG: f64 = 111.2;
+
+    fn observer() -> GeoPos {
+        GeoPos{ lat_lon: LatLon{ lat: Deg(52.0), lon: Deg(21.0) }, elevation: f64::Length::new::<length::meter>(0.0) }
+    }
+
+    /// Returns aircraft `north_km` north of the observer at 10 km altitude, flying along `track` at 250 m/s.
+    fn aircraft(id: u32, north_km: f64, track: f64, t: std::time::Instant) -> Aircraft {
+        Aircraft{
+            id: ModeSTransponderCode::new(id),
+            state: State::Normal,
+            callsign: None,
+            registry_entry: None,
+            lat_lon: Some((LatLon{ lat: Deg(52.0 + north_km / KM_PER_DEG), lon: Deg(21.0) }, t)),
+            estimated_lat_lon: None,
+            track: Some(Deg(track)),
+            altitude: Some(f64::Length::new::<length::meter>(10_000.0)),
+            estimated_altitude: None,
+            ground_speed: Some(f64::Velocity::new::<velocity::meter_per_second>(250.0)),
+            vertical_rate: None,
+            squawk: None,
+            alert: false,
+            emergency: false,
+            spi: false,
+            on_ground: None,
+            rssi: None,
+            sources: HashMap::new(),
+            position_source: None,
+            track_filter: None,
+            trail: Default::default(),
+            t_last_update: t
+        }
+    }
+
+    fn table(aircraft: Vec<Aircraft>) -> HashMap<ModeSTransponderCode, Aircraft> {
+        aircraft.into_iter().map(|a| (a.id, a)).collect()
+    }
+
+    #[test]
+    fn sky_region_selects_next_entrant() {
+        let now = std::time::Instant::now();
+        let region = SkyRegion{ az_min: Deg(170.0), az_max: Deg(190.0), el_min: Deg(10.0), el_max: Deg(60.0) };
+        let mut selector = AutoSelector::new(Policy::SkyRegion(region));
+        let approaching = || aircraft(0x000001, 30.0, 180.0, now);
+        let receding = || aircraft(0x000002, 40.0, 0.0, now);
+
+        let all = table(vec![approaching(), receding()]);
+        assert_eq!(
+            Some(ModeSTransponderCode::new(0x000001)),
+            selector.choose(&all, None, &observer(), &Visibility::default(), now)
+        );
+
+        // an aircraft already inside the region takes precedence
+        let mut selector = AutoSelector::new(selector.policy.clone());
+        let all = table(vec![approaching(), receding(), aircraft(0x000003, -20.0, 90.0, now)]);
+        assert_eq!(
+            Some(ModeSTransponderCode::new(0x000003)),
+            selector.choose(&all, None, &observer(), &Visibility::default(), now)
+        );
+    }
+
+    #[test]
+    fn manual_selection_is_kept_until_it_drops_out() {
+        let t0 = std::time::Instant::now();
+        let mut selector = AutoSelector::new(Policy::Closest);
+        let (near, far) = (ModeSTransponderCode::new(0x000001), ModeSTransponderCode::new(0x000002));
+        let both = |t| table(vec![aircraft(0x000001, 5.0, 90.0, t), aircraft(0x000002, 50.0, 90.0, t)]);
+
+        assert_eq!(Some(near), selector.choose(&both(t0), None, &observer(), &Visibility::default(), t0));
+
+        // selected by user
+        let t1 = t0 + std::time::Duration::from_secs(1);
+        assert_eq!(Some(far), selector.choose(&both(t1), Some(far), &observer(), &Visibility::default(), t1));
+
+        // kept after the hold time
+        let t2 = t0 + 2 * MIN_HOLD_TIME;
+        assert_eq!(Some(far), selector.choose(&both(t2), Some(far), &observer(), &Visibility::default(), t2));
+
+        // no longer tracked
+        let t3 = t2 + std::time::Duration::from_secs(1);
+        let only_near = table(vec![aircraft(0x000001, 5.0, 90.0, t3)]);
+        assert_eq!(Some(near), selector.choose(&only_near, None, &observer(), &Visibility::default(), t3));
+    }
+
+    #[test]
+    fn manual_selection_without_position_drops_out() {
+        let t0 = std::time::Instant::now();
+        let mut selector = AutoSelector::new(Policy::Closest);
+        let (near, no_position) = (ModeSTransponderCode::new(0x000001), ModeSTransponderCode::new(0x000002));
+        let mut choose = |t_no_position, selected, now| {
+            let mut without_position = aircraft(0x000002, 0.0, 90.0, t_no_position);
+            without_position.lat_lon = None;
+            let aircraft = table(vec![aircraft(0x000001, 5.0, 90.0, now), without_position]);
+            selector.choose(&aircraft, selected, &observer(), &Visibility::default(), now)
+        };
+
+        assert_eq!(Some(near), choose(t0, None, t0));
+
+        // selected by user; kept while it is being updated
+        let t1 = t0 + std::time::Duration::from_secs(1);
+        assert_eq!(Some(no_position), choose(t1, Some(no_position), t1));
+        let t2 = t1 + MAX_POSITION_AGE;
+        assert_eq!(Some(no_position), choose(t2, Some(no_position), t2));
+
+        // no longer updated (but not yet removed by the tracker)
+        let t3 = t2 + MAX_POSITION_AGE + std::time::Duration::from_secs(1);
+        assert_eq!(Some(near), choose(t2, Some(no_position), t3));
+    }
+}
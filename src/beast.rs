@@ -0,0 +1,206 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use std::io::Read;
+
+const ESCAPE: u8 = 0x1A;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FrameKind {
+    ModeAc,
+    ModeSShort,
+    ModeSLong
+}
+
+impl FrameKind {
+    fn from_type_byte(value: u8) -> Option<FrameKind> {
+        match value {
+            b'1' => Some(FrameKind::ModeAc),
+            b'2' => Some(FrameKind::ModeSShort),
+            b'3' => Some(FrameKind::ModeSLong),
+            _ => None
+        }
+    }
+
+    fn payload_len(&self) -> usize {
+        match self {
+            FrameKind::ModeAc => 2,
+            FrameKind::ModeSShort => 7,
+            FrameKind::ModeSLong => 14
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BeastFrame {
+    pub kind: FrameKind,
+    /// 48-bit value of the receiver's 12 MHz counter.
+    pub timestamp: u64,
+    /// Raw signal level (0-255, proportional to signal amplitude).
+    pub signal: u8,
+    pub data: Vec<u8>
+}
+
+impl BeastFrame {
+    /// Returns signal level in dBFS; `None` if the receiver did not report it (signal byte is 0).
+    pub fn rssi(&self) -> Option<f64> {
+        if self.signal == 0 { return None; }
+        let amplitude = self.signal as f64 / 255.0;
+        Some(10.0 * (amplitude * amplitude).log10())
+    }
+}
+
+/// Splits a byte stream into Beast frames, undoing the escaping of 0x1A bytes.
+pub struct BeastReader<R: Read> {
+    reader: std::io::BufReader<R>
+}
+
+impl<R: Read> BeastReader<R> {
+    pub fn new(reader: R) -> BeastReader<R> {
+        BeastReader{ reader: std::io::BufReader::new(reader) }
+    }
+
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        let mut buf = [0u8; 1];
+        match self.reader.read(&mut buf)? {
+            0 => Ok(None),
+            _ => Ok(Some(buf[0]))
+        }
+    }
+
+    /// Reads an unescaped byte; returns `Err(type_byte)` if an unexpected frame start has been encountered.
+    fn read_unescaped(&mut self) -> std::io::Result<Option<Result<u8, u8>>> {
+        match self.read_byte()? {
+            None => Ok(None),
+            Some(ESCAPE) => match self.read_byte()? {
+                None => Ok(None),
+                Some(ESCAPE) => Ok(Some(Ok(ESCAPE))),
+                Some(b) => Ok(Some(Err(b)))
+            },
+            Some(b) => Ok(Some(Ok(b)))
+        }
+    }
+
+    /// Returns the next Mode A/C or Mode S frame, skipping any other (e.g., status) frames and garbage;
+    /// returns `None` at end of stream.
+    pub fn next_frame(&mut self) -> std::io::Result<Option<BeastFrame>> {
+        // type byte of a frame whose starting escape byte has already been consumed
+        let mut pending_type: Option<u8> = None;
+
+        'outer: loop {
+            let type_byte = match pending_type.take() {
+                Some(b) => b,
+                None => {
+                    match self.read_byte()? {
+                        None => return Ok(None),
+                        Some(ESCAPE) => (),
+                        Some(_) => continue
+                    }
+                    match self.read_byte()? {
+                        None => return Ok(None),
+                        Some(b) => b
+                    }
+                }
+            };
+
+            let kind = match FrameKind::from_type_byte(type_byte) {
+                Some(kind) => kind,
+                None => continue // status frame, escaped 0x1A outside of a frame, or garbage; resynchronize
+            };
+
+            // 6 bytes of timestamp, 1 byte of signal level, then payload
+            let mut contents = Vec::with_capacity(7 + kind.payload_len());
+            while contents.len() < 7 + kind.payload_len() {
+                match self.read_unescaped()? {
+                    None => return Ok(None),
+                    Some(Ok(b)) => contents.push(b),
+                    Some(Err(b)) => {
+                        // truncated frame; the escape byte we have just read starts a new one
+                        pending_type = Some(b);
+                        continue 'outer;
+                    }
+                }
+            }
+
+            let timestamp = contents[..6].iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+
+            return Ok(Some(BeastFrame{
+                kind,
+                timestamp,
+                signal: contents[6],
+                data: contents[7..].to_vec()
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(input: &[u8]) -> Vec<BeastFrame> {
+        let mut reader = BeastReader::new(input);
+        let mut frames = vec![];
+        while let Some(frame) = reader.next_frame().unwrap() { frames.push(frame); }
+        frames
+    }
+
+    const SHORT_FRAME: &[u8] = &[
+        0x1A, b'2', 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0xFF, 0x5D, 0x4C, 0xA1, 0xD3, 0x00, 0x00, 0x00
+    ];
+
+    #[test]
+    fn reads_frame() {
+        let frames = frames(SHORT_FRAME);
+        assert_eq!(1, frames.len());
+        assert_eq!(FrameKind::ModeSShort, frames[0].kind);
+        assert_eq!(0x000102030405, frames[0].timestamp);
+        assert_eq!(0xFF, frames[0].signal);
+        assert_eq!(vec![0x5D, 0x4C, 0xA1, 0xD3, 0x00, 0x00, 0x00], frames[0].data);
+    }
+
+    #[test]
+    fn unescapes_doubled_escape_bytes() {
+        let input = [
+            0x1A, b'2', 0x00, 0x00, 0x1A, 0x1A, 0x00, 0x00, 0x00, 0x1A, 0x1A, 0x5D, 0x1A, 0x1A, 0xA1, 0xD3, 0x00, 0x00, 0x1A, 0x1A
+        ];
+        let frames = frames(&input);
+        assert_eq!(1, frames.len());
+        assert_eq!(0x00001A000000, frames[0].timestamp);
+        assert_eq!(0x1A, frames[0].signal);
+        assert_eq!(vec![0x5D, 0x1A, 0xA1, 0xD3, 0x00, 0x00, 0x1A], frames[0].data);
+    }
+
+    #[test]
+    fn resynchronizes_after_garbage() {
+        let mut input = vec![0x00, 0x55, 0x1A, 0x1A, 0x1A, b'9', 0x01, 0x02];
+        input.extend_from_slice(SHORT_FRAME);
+        // truncated frame followed by a complete one
+        input.extend_from_slice(&SHORT_FRAME[..6]);
+        input.extend_from_slice(SHORT_FRAME);
+
+        let frames = frames(&input);
+        assert_eq!(2, frames.len());
+        assert!(frames.iter().all(|f| f.data == SHORT_FRAME[9..]));
+    }
+
+    #[test]
+    fn skips_status_frames() {
+        let mut input = vec![0x1A, b'4', 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x20];
+        input.extend_from_slice(SHORT_FRAME);
+        assert_eq!(1, frames(&input).len());
+    }
+
+    #[test]
+    fn signal_level() {
+        let mut frame = frames(SHORT_FRAME).remove(0);
+        assert!(frame.rssi().unwrap().abs() < 1.0e-9);
+        frame.signal = 0;
+        assert_eq!(None, frame.rssi());
+    }
+}
@@ -0,0 +1,224 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use cgmath::Deg;
+use crate::{config::Overrides, data_receiver::{DataSource, InputFormat}};
+use pointing_utils::{GeoPos, LatLon, uom};
+use uom::{si::f64, si::length};
+
+pub const USAGE: &str = "\
+Usage: plane-tracker [OPTIONS]
+
+Options given on the command line take precedence over the configuration file and are not stored in it.
+
+  --config <FILE>                 configuration file to use (default: plane-tracker.cfg in the user's config directory)
+  --observer <LAT>,<LON>,<ELEV>   observer location (degrees, degrees, meters)
+  --server [<LABEL>=]<HOST:PORT>[/<FORMAT>]
+                                  data source (FORMAT: SBS, Beast or AVR; default: SBS); may be given several
+                                  times; replaces the configured sources
+  --data-sender-address <HOST>    address on which to serve data of the selected aircraft (default: localhost;
+                                  use 0.0.0.0 to accept remote clients)
+  --data-sender-port <PORT>       port on which to serve data of the selected aircraft
+  --auto-select <MODE>            automatic target selection (off, closest, highest-elevation, watchlist,
+                                  sky-region; watchlist and sky region are read from the configuration file)
+  --connect                       connect to the enabled sources on start (always done in headless mode)
+  --record                        record messages received from data sources
+  --recording-dir <DIR>           directory of recordings (default: working directory)
+  --headless                      run without GUI (always the case if built without the `gui` feature)
+  --help                          print this message
+";
+
+#[derive(Default)]
+pub struct Options {
+    pub headless: bool,
+    pub record: bool,
+    pub help: bool,
+    pub connect: bool,
+    pub config_path: Option<std::path::PathBuf>,
+    pub recording_dir: Option<std::path::PathBuf>,
+    pub overrides: Overrides
+}
+
+/// Parses "<lat>,<lon>,<elevation>".
+fn parse_observer(s: &str) -> Result<GeoPos, String> {
+    let values: Vec<&str> = s.split(',').collect();
+    if values.len() != 3 { return Err(format!("invalid observer location \"{}\"", s)); }
+
+    let parse = |value: &str| value.trim().parse::<f64>().map_err(|e| format!("invalid observer location \"{}\": {}", s, e));
+
+    let lat = parse(values[0])?;
+    let lon = parse(values[1])?;
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(format!("observer location out of range: \"{}\"", s));
+    }
+
+    Ok(GeoPos{
+        lat_lon: LatLon{ lat: Deg(lat), lon: Deg(lon) },
+        elevation: f64::Length::new::<length::meter>(parse(values[2])?)
+    })
+}
+
+/// Parses "[<label>=]<address>[/<format>]".
+fn parse_server(s: &str) -> Result<DataSource, String> {
+    let (label, rest) = match s.split_once('=') {
+        Some((label, rest)) => (Some(label), rest),
+        None => (None, s)
+    };
+    let (address, format) = match rest.split_once('/') {
+        Some((address, format)) => (address, format.parse::<InputFormat>()?),
+        None => (rest, InputFormat::Sbs)
+    };
+
+    if address.is_empty() || label.is_some_and(|l| l.is_empty()) {
+        return Err(format!("invalid server \"{}\"", s));
+    }
+    if s.contains(';') { return Err(format!("server must not contain \";\": \"{}\"", s)); }
+
+    Ok(DataSource{ label: label.unwrap_or(address).into(), address: address.into(), format, enabled: true })
+}
+
+/// Parses program arguments (excluding the program name).
+pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value of \"{}\"", arg));
+
+        match arg.as_str() {
+            "--headless" => options.headless = true,
+            "--record" => options.record = true,
+            "--connect" => options.connect = true,
+            "--help" | "-h" => options.help = true,
+            "--config" => options.config_path = Some(value()?.into()),
+            "--recording-dir" => options.recording_dir = Some(value()?.into()),
+            "--observer" => options.overrides.observer_location = Some(parse_observer(&value()?)?),
+
+            "--server" => {
+                let source = parse_server(&value()?)?;
+                let sources = options.overrides.sources.get_or_insert_with(Vec::new);
+                if sources.iter().any(|s| s.label == source.label) {
+                    return Err(format!("duplicated server label \"{}\"", source.label));
+                }
+                sources.push(source);
+            },
+
+            "--auto-select" => options.overrides.auto_select_mode = Some(value()?.parse()?),
+
+            "--data-sender-address" => options.overrides.data_sender_address = Some(value()?),
+
+            "--data-sender-port" => {
+                let port = value()?;
+                options.overrides.data_sender_port =
+                    Some(port.parse::<u16>().map_err(|e| format!("invalid port \"{}\": {}", port, e))?);
+            },
+
+            _ => return Err(format!("unknown option \"{}\"", arg))
+        }
+    }
+
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plane_tracker::auto_select;
+
+    fn parse_args(args: &[&str]) -> Result<Options, String> {
+        parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn no_options() {
+        let options = parse_args(&[]).unwrap();
+        assert!(!options.headless && !options.record && !options.help && !options.connect);
+        assert!(options.config_path.is_none() && options.recording_dir.is_none());
+        assert!(options.overrides.observer_location.is_none() && options.overrides.sources.is_none());
+    }
+
+    #[test]
+    fn flags() {
+        let options = parse_args(&["--headless", "--record", "--connect"]).unwrap();
+        assert!(options.headless && options.record && options.connect && !options.help);
+
+        assert!(parse_args(&["--help"]).unwrap().help);
+        assert!(parse_args(&["-h"]).unwrap().help);
+    }
+
+    #[test]
+    fn options_with_values() {
+        let options = parse_args(&[
+            "--config", "/tmp/pt.cfg",
+            "--recording-dir", "/tmp/rec",
+            "--observer", "52.1, -21.5,110",
+            "--auto-select", "closest",
+            "--data-sender-address", "0.0.0.0",
+            "--data-sender-port", "45500"
+        ]).unwrap();
+
+        assert_eq!(Some(std::path::PathBuf::from("/tmp/pt.cfg")), options.config_path);
+        assert_eq!(Some(std::path::PathBuf::from("/tmp/rec")), options.recording_dir);
+        let observer = options.overrides.observer_location.unwrap();
+        assert_eq!(LatLon{ lat: Deg(52.1), lon: Deg(-21.5) }, observer.lat_lon);
+        assert_eq!(110.0, observer.elevation.get::<length::meter>());
+        assert_eq!(Some(auto_select::Mode::Closest), options.overrides.auto_select_mode);
+        assert_eq!(Some("0.0.0.0"), options.overrides.data_sender_address.as_deref());
+        assert_eq!(Some(45500), options.overrides.data_sender_port);
+    }
+
+    #[test]
+    fn servers() {
+        let options = parse_args(&[
+            "--server", "localhost:30003",
+            "--server", "remote=example.org:30005/Beast"
+        ]).unwrap();
+        let sources = options.overrides.sources.unwrap();
+        assert_eq!(2, sources.len());
+        assert_eq!(("localhost:30003", "localhost:30003", InputFormat::Sbs), (
+            sources[0].label.as_str(), sources[0].address.as_str(), sources[0].format
+        ));
+        assert_eq!(("remote", "example.org:30005", InputFormat::Beast), (
+            sources[1].label.as_str(), sources[1].address.as_str(), sources[1].format
+        ));
+        assert!(sources.iter().all(|s| s.enabled));
+
+        assert!(parse_args(&["--server", "a=host:1", "--server", "a=host:2"]).is_err());
+        assert!(parse_args(&["--server", "host:1/XYZ"]).is_err());
+        assert!(parse_args(&["--server", "=host:1"]).is_err());
+        assert!(parse_args(&["--server", "label="]).is_err());
+        assert!(parse_args(&["--server", "host;1"]).is_err());
+    }
+
+    #[test]
+    fn missing_values() {
+        for option in [
+            "--config", "--recording-dir", "--observer", "--server", "--auto-select", "--data-sender-address",
+            "--data-sender-port"
+        ] {
+            assert_eq!(Err(format!("missing value of \"{}\"", option)), parse_args(&[option]).map(|_| ()));
+        }
+    }
+
+    #[test]
+    fn unknown_option() {
+        assert_eq!(Err("unknown option \"--foo\"".to_string()), parse_args(&["--foo"]).map(|_| ()));
+        assert!(parse_args(&["--headless", "extra"]).is_err());
+    }
+
+    #[test]
+    fn invalid_values() {
+        for observer in [
+            "52.1,21.5", "52.1,21.5,110,1", "north,21.5,110", "52.1,21.5,", "91,21.5,110", "52.1,181,110"
+        ] {
+            assert!(parse_args(&["--observer", observer]).is_err(), "{}", observer);
+        }
+        assert!(parse_args(&["--auto-select", "nearest"]).is_err());
+        assert!(parse_args(&["--data-sender-port", "65536"]).is_err());
+        assert!(parse_args(&["--data-sender-port", "port"]).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use chrono::TimeZone;
+
+/// Time source of the tracker: wall-clock time or (during playback) time of the recording.
+#[derive(Clone, Debug, Default)]
+pub struct Clock {
+    playback: Option<PlaybackTime>
+}
+
+#[derive(Clone, Debug)]
+struct PlaybackTime {
+    /// Recording time (local) corresponding to `t`.
+    t_recorded: chrono::NaiveDateTime,
+    /// Clock time at `t_wall`.
+    t: std::time::Instant,
+    t_wall: std::time::Instant,
+    /// Rate of advancing between recorded timestamps, relative to wall-clock time (0 if paused).
+    rate: f64
+}
+
+impl PlaybackTime {
+    fn now(&self) -> std::time::Instant {
+        self.t + self.t_wall.elapsed().mul_f64(self.rate)
+    }
+}
+
+impl Clock {
+    pub fn now(&self) -> std::time::Instant {
+        match &self.playback {
+            None => std::time::Instant::now(),
+            Some(playback) => playback.now()
+        }
+    }
+
+    pub fn utc(&self) -> chrono::DateTime<chrono::Utc> {
+        match &self.playback {
+            None => chrono::Utc::now(),
+            Some(playback) => {
+                let t_recorded = playback.t_recorded
+                    + chrono::Duration::from_std(playback.now() - playback.t).unwrap_or(chrono::Duration::zero());
+                match chrono::Local.from_local_datetime(&t_recorded).earliest() {
+                    Some(t) => t.with_timezone(&chrono::Utc),
+                    None => chrono::Utc.from_utc_datetime(&t_recorded)
+                }
+            }
+        }
+    }
+
+    pub fn is_playback(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    /// Switches to (or synchronizes with) time `t_recorded` of the recording being played.
+    pub fn set_recorded_time(&mut self, t_recorded: chrono::NaiveDateTime, rate: f64) {
+        let t = match &self.playback {
+            None => std::time::Instant::now(),
+            Some(playback) => match (t_recorded - playback.t_recorded).to_std() {
+                Ok(dt) => playback.t + dt,
+                // moved backwards (e.g., after seeking)
+                Err(_) => (playback.t_recorded - t_recorded).to_std().ok()
+                    .and_then(|dt| playback.t.checked_sub(dt))
+                    .unwrap_or(playback.t)
+            }
+        };
+
+        self.playback = Some(PlaybackTime{ t_recorded, t, t_wall: std::time::Instant::now(), rate });
+    }
+
+    /// Sets the rate of advancing between recorded timestamps (0 if paused); no effect when not playing back.
+    pub fn set_rate(&mut self, rate: f64) {
+        if let Some(playback) = &mut self.playback {
+            let now = playback.now();
+            playback.t_recorded += chrono::Duration::from_std(now - playback.t).unwrap_or(chrono::Duration::zero());
+            playback.t = now;
+            playback.t_wall = std::time::Instant::now();
+            playback.rate = rate;
+        }
+    }
+
+    /// Switches back to wall-clock time.
+    pub fn set_real_time(&mut self) {
+        self.playback = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(s: &str) -> chrono::NaiveDateTime {
+        chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f").unwrap()
+    }
+
+    #[test]
+    fn follows_recorded_time() {
+        let mut clock = Clock::default();
+        assert!(!clock.is_playback());
+
+        clock.set_recorded_time(recorded("2024-01-01 10:00:00.0"), 0.0);
+        let t0 = clock.now();
+        clock.set_recorded_time(recorded("2024-01-01 10:00:30.0"), 0.0);
+        assert_eq!(std::time::Duration::from_secs(30), clock.now() - t0);
+
+        // after seeking backwards
+        clock.set_recorded_time(recorded("2024-01-01 10:00:10.0"), 0.0);
+        assert_eq!(std::time::Duration::from_secs(10), clock.now() - t0);
+
+        clock.set_real_time();
+        assert!(!clock.is_playback());
+    }
+
+    #[test]
+    fn stands_still_when_paused() {
+        let mut clock = Clock::default();
+        clock.set_recorded_time(recorded("2024-01-01 10:00:00.0"), 10.0);
+        clock.set_rate(0.0);
+        let (t, utc) = (clock.now(), clock.utc());
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert_eq!(t, clock.now());
+        assert_eq!(utc, clock.utc());
+    }
+
+    #[test]
+    fn advances_at_rate() {
+        let mut clock = Clock::default();
+        clock.set_recorded_time(recorded("2024-01-01 10:00:00.0"), 10.0);
+        let t = clock.now();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert!(clock.now() - t >= std::time::Duration::from_millis(200));
+    }
+}
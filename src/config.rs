@@ -6,54 +6,141 @@
 // (see the LICENSE file for details).
 //
 
+// settings used only by the GUI are not read in a headless-only build
+#![cfg_attr(not(feature = "gui"), allow(dead_code))]
+
 use cgmath::Deg;
-use gtk::glib;
+use crate::data_receiver::{DataSource, InputFormat};
+#[cfg(feature = "gui")]
+use crate::gui::map_layers;
+#[cfg(feature = "gui")]
 use gtk4 as gtk;
+use plane_tracker::{airports, auto_select, data, kalman, transit, visibility};
 use pointing_utils::{GeoPos, LatLon, uom};
 use std::error::Error;
 use uom::{si::f64, si::length};
 
+const DEF_DATA_SENDER_ADDRESS: &str = "localhost";
 const DEF_DATA_SENDER_PORT: u16 = 45500;
 
 mod groups {
     pub const UI: &str = "UI";
     pub const MAIN: &str = "Main";
+    pub const SOURCES: &str = "Sources";
+    pub const AUTO_SELECT: &str = "AutoSelect";
+    pub const TRANSITS: &str = "Transits";
+    pub const VISIBILITY: &str = "Visibility";
+    pub const TRAILS: &str = "Trails";
+    pub const MAP_LAYERS: &str = "MapLayers";
+    pub const AIRPORTS: &str = "Airports";
 }
 
 mod keys {
     // group: MAIN
     pub const OBSERVER_LOCATION: &str = "ObserverLocation";
+    /// Superseded by group SOURCES; read only if the latter is absent.
     pub const SERVER_ADDRRESS: &str = "ServerAddress";
+    /// Superseded by group SOURCES; read only if the latter is absent.
+    pub const INPUT_FORMAT: &str = "InputFormat";
     pub const FILTER_OOO_MSGS: &str = "FilterOoOMessages";
     pub const INTERPOLATE_POSITIONS: &str = "InterpolatePositions";
+    /// "off" or a track filter model.
+    pub const TRACK_FILTER: &str = "TrackFilter";
+    /// Address (host name or IP) on which the data sender listens; e.g., "0.0.0.0" to accept remote clients.
+    pub const DATA_SENDER_ADDRESS: &str = "DataSenderAddress";
     pub const DATA_SENDER_PORT: &str = "DataSenderPort";
+    /// Path of aircraft registry CSV file (if relative, then to the configuration file's directory).
+    pub const REGISTRY_DATABASE: &str = "RegistryDatabase";
+
+    // group: SOURCES
+    pub const SOURCE_PREFIX: &str = "Source";
+
+    // group: AUTO_SELECT
+    pub const AUTO_SELECT_MODE: &str = "Mode";
+    /// ICAO addresses, callsigns, "country:<code or name>" or "military", separated by ';'.
+    pub const WATCHLIST: &str = "Watchlist";
+    /// "<az. min>;<az. max>;<el. min>;<el. max>" (degrees).
+    pub const SKY_REGION: &str = "SkyRegion";
+
+    // group: TRANSITS
+    pub const TRANSITS_ENABLED: &str = "Enabled";
+    /// Degrees from a disc's edge.
+    pub const MAX_SEPARATION: &str = "MaxSeparation";
+    /// Seconds.
+    pub const LOOK_AHEAD: &str = "LookAhead";
+    pub const TRANSIT_ALERT: &str = "Alert";
+
+    // group: VISIBILITY
+    pub const REFRACTION: &str = "Refraction";
+    /// Hectopascals.
+    pub const PRESSURE: &str = "Pressure";
+    /// Degrees Celsius.
+    pub const TEMPERATURE: &str = "Temperature";
+    /// Path of horizon mask file (if relative, then to the configuration file's directory). Aircraft below the mask
+    /// are skipped by all auto-selection modes (including "closest").
+    pub const HORIZON_MASK: &str = "HorizonMask";
+
+    // group: TRAILS
+    pub const TRAILS_SHOWN: &str = "Shown";
+    /// Seconds.
+    pub const TRAIL_DURATION: &str = "Duration";
+    pub const TRAIL_MAX_POINTS: &str = "MaxPoints";
+    pub const TRAIL_ALTITUDE_COLORS: &str = "ColorByAltitude";
+
+    // group: MAP_LAYERS
+    /// "<path>;<color (#RRGGBB)>;<shown>"; relative paths are relative to the configuration file's directory.
+    pub const MAP_LAYER_PREFIX: &str = "Layer";
+
+    // group: AIRPORTS
+    /// Paths of OurAirports CSV files (if relative, then to the configuration file's directory).
+    pub const AIRPORTS_FILE: &str = "Airports";
+    pub const RUNWAYS_FILE: &str = "Runways";
+    pub const NAVAIDS_FILE: &str = "Navaids";
+    /// Kilometers from the observer.
+    pub const AIRPORTS_MAX_DISTANCE: &str = "MaxDistance";
+    pub const AIRPORTS_SHOWN: &str = "Shown";
 
     // group: UI
     pub const MAIN_WINDOW_POS_SIZE: &str = "MainWindowPosSize";
     pub const MAIN_WINDOW_MAXIMIZED: &str = "MainWindowMaximized";
     pub const TEXT_SCALE: &str = "TextScale";
+    pub const SKY_VIEW_SHOWN: &str = "SkyViewShown";
+    pub const AIRCRAFT_LIST_SHOWN: &str = "AircraftListShown";
+}
+
+/// Values (e.g., given on the command line) which take precedence over the configuration file; not stored.
+#[derive(Default)]
+pub struct Overrides {
+    pub observer_location: Option<GeoPos>,
+    pub sources: Option<Vec<DataSource>>,
+    pub data_sender_address: Option<String>,
+    pub data_sender_port: Option<u16>,
+    pub auto_select_mode: Option<auto_select::Mode>
 }
 
 pub struct Configuration {
-    key_file: glib::KeyFile
+    key_file: glib::KeyFile,
+    file_path: std::path::PathBuf,
+    overrides: Overrides
 }
 
 impl Configuration {
     pub fn store(&self) -> Result<(), glib::error::Error> {
-        self.key_file.save_to_file(config_file_path())
+        self.key_file.save_to_file(&self.file_path)
     }
 
-    pub fn new() -> Configuration {
+    /// Loads configuration from `file_path` (if `None`, from the default location).
+    pub fn new(file_path: Option<std::path::PathBuf>, overrides: Overrides) -> Configuration {
         let key_file = glib::KeyFile::new();
-        let file_path = config_file_path();
+        let file_path = file_path.unwrap_or_else(config_file_path);
         if key_file.load_from_file(
             file_path.clone(),
             glib::KeyFileFlags::NONE
         ).is_err() {
-            println!("WARNING: Failed to load configuration from {}.", file_path.to_str().unwrap());
+            println!("WARNING: Failed to load configuration from {}.", file_path.to_string_lossy());
         }
 
-        Configuration{ key_file }
+        Configuration{ key_file, file_path, overrides }
     }
 
     pub fn text_scale(&self) -> Result<f64, Box<dyn Error>> {
@@ -70,6 +157,22 @@ impl Configuration {
         self.key_file.set_double(groups::UI, keys::TEXT_SCALE, value);
     }
 
+    pub fn sky_view_shown(&self) -> Option<bool> {
+        self.key_file.boolean(groups::UI, keys::SKY_VIEW_SHOWN).ok()
+    }
+
+    pub fn set_sky_view_shown(&self, value: bool) {
+        self.key_file.set_boolean(groups::UI, keys::SKY_VIEW_SHOWN, value);
+    }
+
+    pub fn aircraft_list_shown(&self) -> Option<bool> {
+        self.key_file.boolean(groups::UI, keys::AIRCRAFT_LIST_SHOWN).ok()
+    }
+
+    pub fn set_aircraft_list_shown(&self, value: bool) {
+        self.key_file.set_boolean(groups::UI, keys::AIRCRAFT_LIST_SHOWN, value);
+    }
+
     pub fn filter_ooo_messages(&self) -> Result<bool, Box<dyn Error>> {
         Ok(self.key_file.boolean(groups::MAIN, keys::FILTER_OOO_MSGS)?)
     }
@@ -78,15 +181,55 @@ impl Configuration {
         self.key_file.set_boolean(groups::MAIN, keys::FILTER_OOO_MSGS, value);
     }
 
-    pub fn server_address(&self) -> Result<String, Box<dyn Error>> {
-        Ok(self.key_file.string(groups::MAIN, keys::SERVER_ADDRRESS)?.as_str().into())
+    /// Returns the list of configured data sources.
+    pub fn sources(&self) -> Vec<DataSource> {
+        if let Some(sources) = &self.overrides.sources { return sources.clone(); }
+
+        let mut sources = vec![];
+        for i in 0.. {
+            let key = format!("{}{}", keys::SOURCE_PREFIX, i);
+            let value = match self.key_file.string(groups::SOURCES, &key) {
+                Ok(value) => value,
+                Err(_) => break
+            };
+            match parse_source(value.as_str()) {
+                Ok(source) => sources.push(source),
+                Err(e) => println!("WARNING: invalid configuration value for {}/{}: {} ({})", groups::SOURCES, key, value, e)
+            }
+        }
+
+        if sources.is_empty() {
+            if let Ok(address) = self.key_file.string(groups::MAIN, keys::SERVER_ADDRRESS) {
+                let format = self.key_file.string(groups::MAIN, keys::INPUT_FORMAT)
+                    .ok()
+                    .and_then(|f| f.as_str().parse::<InputFormat>().ok())
+                    .unwrap_or(InputFormat::Sbs);
+                sources.push(DataSource{ label: address.as_str().into(), address: address.as_str().into(), format, enabled: true });
+            }
+        }
+
+        sources
     }
 
-    pub fn set_server_address(&self, server_address: &str) {
-        self.key_file.set_string(groups::MAIN, keys::SERVER_ADDRRESS, server_address);
+    /// Labels and addresses must not contain ';'. Discards the overridden sources, if any.
+    pub fn set_sources(&mut self, sources: &[DataSource]) {
+        self.overrides.sources = None;
+        let _ = self.key_file.remove_group(groups::SOURCES);
+        for (i, source) in sources.iter().enumerate() {
+            assert!(!source.label.contains(';') && !source.address.contains(';'));
+            self.key_file.set_string(
+                groups::SOURCES,
+                &format!("{}{}", keys::SOURCE_PREFIX, i),
+                &format!("{};{};{};{}", source.label, source.address, source.format, source.enabled)
+            );
+        }
     }
 
     pub fn observer_location(&self) -> Result<GeoPos, Box<dyn Error>> {
+        if let Some(location) = &self.overrides.observer_location {
+            return Ok(GeoPos{ lat_lon: location.lat_lon.clone(), elevation: location.elevation });
+        }
+
         let ll_str = self.key_file.string(groups::MAIN, keys::OBSERVER_LOCATION)?;
         let values: Vec<&str> = ll_str.split(';').collect();
         if values.len() != 3 { return Err("too few values".into()); }
@@ -99,10 +242,12 @@ impl Configuration {
         })
     }
 
+    #[cfg(feature = "gui")]
     pub fn main_window_pos(&self) -> Option<gtk::gdk::Rectangle> {
         self.read_rect(groups::UI, keys::MAIN_WINDOW_POS_SIZE)
     }
 
+    #[cfg(feature = "gui")]
     pub fn set_main_window_pos(&self, pos_size: gtk::gdk::Rectangle) {
         self.store_rect(groups::UI, keys::MAIN_WINDOW_POS_SIZE, pos_size);
     }
@@ -115,10 +260,12 @@ impl Configuration {
         self.key_file.set_boolean(groups::UI, keys::MAIN_WINDOW_MAXIMIZED, value);
     }
 
+    #[cfg(feature = "gui")]
     fn store_rect(&self, group: &str, key: &str, rect: gtk::gdk::Rectangle) {
         self.key_file.set_string(group, key, &format!("{};{};{};{}", rect.x(), rect.y(), rect.width(), rect.height()));
     }
 
+    #[cfg(feature = "gui")]
     fn read_rect(&self, group: &str, key: &str) -> Option<gtk::gdk::Rectangle> {
         let rect_str = match self.key_file.string(group, key) {
             Ok(s) => s,
@@ -153,11 +300,248 @@ impl Configuration {
         self.key_file.boolean(groups::MAIN, keys::INTERPOLATE_POSITIONS).ok()
     }
 
+    /// Returns the track filter model (`None` if the filter is disabled).
+    pub fn track_filter(&self) -> Option<kalman::Model> {
+        let value = self.key_file.string(groups::MAIN, keys::TRACK_FILTER).ok()?;
+        if value.as_str() == "off" { return None; }
+        match value.as_str().parse::<kalman::Model>() {
+            Ok(model) => Some(model),
+            Err(e) => {
+                println!("WARNING: invalid configuration value for {}/{} ({}); track filter disabled.", groups::MAIN, keys::TRACK_FILTER, e);
+                None
+            }
+        }
+    }
+
+    pub fn auto_select_mode(&self) -> auto_select::Mode {
+        if let Some(mode) = self.overrides.auto_select_mode { return mode; }
+
+        self.key_file.string(groups::AUTO_SELECT, keys::AUTO_SELECT_MODE)
+            .ok()
+            .and_then(|m| m.as_str().parse::<auto_select::Mode>().ok())
+            .unwrap_or(auto_select::Mode::Off)
+    }
+
+    /// Discards the overridden mode, if any.
+    pub fn set_auto_select_mode(&mut self, mode: auto_select::Mode) {
+        self.overrides.auto_select_mode = None;
+        self.key_file.set_string(groups::AUTO_SELECT, keys::AUTO_SELECT_MODE, &mode.to_string());
+    }
+
+    pub fn watchlist(&self) -> Vec<String> {
+        self.key_file.string(groups::AUTO_SELECT, keys::WATCHLIST)
+            .map(|w| w.as_str().split(';').map(|e| e.trim().to_string()).filter(|e| !e.is_empty()).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn sky_region(&self) -> Result<auto_select::SkyRegion, Box<dyn Error>> {
+        Ok(self.key_file.string(groups::AUTO_SELECT, keys::SKY_REGION)?.as_str().parse::<auto_select::SkyRegion>()?)
+    }
+
+    /// Returns the auto-selection policy corresponding to the configured mode (`None` if off or incomplete).
+    pub fn auto_select_policy(&self) -> Option<auto_select::Policy> {
+        match self.auto_select_mode() {
+            auto_select::Mode::Off => None,
+            auto_select::Mode::Closest => Some(auto_select::Policy::Closest),
+            auto_select::Mode::HighestElevation => Some(auto_select::Policy::HighestElevation),
+
+            auto_select::Mode::Watchlist => {
+                let watchlist = self.watchlist();
+                if watchlist.is_empty() {
+                    println!("WARNING: {}/{} is empty; auto-selection disabled.", groups::AUTO_SELECT, keys::WATCHLIST);
+                    None
+                } else {
+                    Some(auto_select::Policy::Watchlist(watchlist))
+                }
+            },
+
+            auto_select::Mode::SkyRegion => match self.sky_region() {
+                Ok(region) => Some(auto_select::Policy::SkyRegion(region)),
+                Err(e) => {
+                    println!("WARNING: invalid {}/{} ({}); auto-selection disabled.", groups::AUTO_SELECT, keys::SKY_REGION, e);
+                    None
+                }
+            }
+        }
+    }
+
+    /// Returns transit prediction settings (`None` if disabled).
+    pub fn transit_settings(&self) -> Option<transit::Settings> {
+        if !self.key_file.boolean(groups::TRANSITS, keys::TRANSITS_ENABLED).unwrap_or(true) { return None; }
+
+        let max_separation = self.key_file.double(groups::TRANSITS, keys::MAX_SEPARATION).unwrap_or(1.0);
+        let look_ahead = self.key_file.double(groups::TRANSITS, keys::LOOK_AHEAD).unwrap_or(180.0);
+        if max_separation < 0.0 || look_ahead < 0.0 {
+            println!("WARNING: {} settings must not be negative; transit prediction disabled.", groups::TRANSITS);
+            return None;
+        }
+
+        Some(transit::Settings{
+            max_separation: Deg(max_separation),
+            look_ahead: std::time::Duration::from_secs_f64(look_ahead)
+        })
+    }
+
+    pub fn transit_alert(&self) -> Option<bool> {
+        self.key_file.boolean(groups::TRANSITS, keys::TRANSIT_ALERT).ok()
+    }
+
+    pub fn trails_shown(&self) -> Option<bool> {
+        self.key_file.boolean(groups::TRAILS, keys::TRAILS_SHOWN).ok()
+    }
+
+    pub fn set_trails_shown(&self, value: bool) {
+        self.key_file.set_boolean(groups::TRAILS, keys::TRAILS_SHOWN, value);
+    }
+
+    pub fn trail_altitude_colors(&self) -> Option<bool> {
+        self.key_file.boolean(groups::TRAILS, keys::TRAIL_ALTITUDE_COLORS).ok()
+    }
+
+    pub fn trail_limits(&self) -> data::TrailLimits {
+        let default = data::TrailLimits::default();
+        let max_age = self.key_file.double(groups::TRAILS, keys::TRAIL_DURATION).unwrap_or(default.max_age.as_secs_f64());
+        let max_points = self.key_file.integer(groups::TRAILS, keys::TRAIL_MAX_POINTS).unwrap_or(default.max_points as i32);
+        if max_age < 0.0 || max_points < 0 {
+            println!("WARNING: {} settings must not be negative; using defaults.", groups::TRAILS);
+            return default;
+        }
+
+        data::TrailLimits{ max_age: std::time::Duration::from_secs_f64(max_age), max_points: max_points as usize }
+    }
+
+    pub fn registry_database(&self) -> Option<std::path::PathBuf> {
+        self.key_file.string(groups::MAIN, keys::REGISTRY_DATABASE).ok().map(|path|
+            self.file_path.parent().unwrap_or(std::path::Path::new("")).join(path.as_str())
+        )
+    }
+
+    /// Returns `None` if the airports file is not set.
+    pub fn airport_files(&self) -> Option<airports::Files> {
+        let path = |key: &str| self.key_file.string(groups::AIRPORTS, key).ok().map(|path|
+            self.file_path.parent().unwrap_or(std::path::Path::new("")).join(path.as_str())
+        );
+
+        Some(airports::Files{
+            airports: path(keys::AIRPORTS_FILE)?,
+            runways: path(keys::RUNWAYS_FILE),
+            navaids: path(keys::NAVAIDS_FILE)
+        })
+    }
+
+    /// Airports and navaids farther from the observer are not loaded.
+    pub fn airports_max_distance(&self) -> f64::Length {
+        const DEFAULT_KM: f64 = 500.0;
+        f64::Length::new::<length::kilometer>(
+            self.key_file.double(groups::AIRPORTS, keys::AIRPORTS_MAX_DISTANCE).unwrap_or(DEFAULT_KM)
+        )
+    }
+
+    pub fn airports_shown(&self) -> Option<bool> {
+        self.key_file.boolean(groups::AIRPORTS, keys::AIRPORTS_SHOWN).ok()
+    }
+
+    pub fn set_airports_shown(&self, value: bool) {
+        self.key_file.set_boolean(groups::AIRPORTS, keys::AIRPORTS_SHOWN, value);
+    }
+
+    /// Returns settings of map layers `Layer0`, `Layer1` etc. (up to the first one missing).
+    #[cfg(feature = "gui")]
+    pub fn map_layers(&self) -> Vec<map_layers::LayerSettings> {
+        let mut layers = vec![];
+        for i in 0.. {
+            let key = format!("{}{}", keys::MAP_LAYER_PREFIX, i);
+            let value = match self.key_file.string(groups::MAP_LAYERS, &key) {
+                Ok(value) => value,
+                Err(_) => break
+            };
+            match parse_map_layer(value.as_str()) {
+                Ok(mut layer) => {
+                    layer.path = self.file_path.parent().unwrap_or(std::path::Path::new("")).join(layer.path);
+                    layers.push(layer);
+                },
+                Err(e) => println!("WARNING: invalid configuration value for {}/{}: {} ({})", groups::MAP_LAYERS, key, value, e)
+            }
+        }
+
+        layers
+    }
+
+    /// Returns refraction settings and horizon mask (loading the latter from file).
+    pub fn visibility(&self) -> visibility::Visibility {
+        let atmosphere = if self.key_file.boolean(groups::VISIBILITY, keys::REFRACTION).unwrap_or(false) {
+            let default = visibility::Atmosphere::default();
+            Some(visibility::Atmosphere{
+                pressure_hpa: self.key_file.double(groups::VISIBILITY, keys::PRESSURE).unwrap_or(default.pressure_hpa),
+                temperature_c: self.key_file.double(groups::VISIBILITY, keys::TEMPERATURE).unwrap_or(default.temperature_c)
+            })
+        } else {
+            None
+        };
+
+        let horizon_mask = match self.key_file.string(groups::VISIBILITY, keys::HORIZON_MASK) {
+            Ok(path) => {
+                let path = self.file_path.parent().unwrap_or(std::path::Path::new("")).join(path.as_str());
+                match visibility::HorizonMask::load(&path) {
+                    Ok(mask) => Some(mask),
+                    Err(e) => {
+                        println!("WARNING: Failed to load horizon mask from {}: {}.", path.to_string_lossy(), e);
+                        None
+                    }
+                }
+            },
+            Err(_) => None
+        };
+
+        visibility::Visibility{ atmosphere, horizon_mask }
+    }
+
+    pub fn data_sender_address(&self) -> String {
+        if let Some(address) = &self.overrides.data_sender_address { return address.clone(); }
+
+        self.key_file.string(groups::MAIN, keys::DATA_SENDER_ADDRESS)
+            .map(|a| a.as_str().to_string())
+            .unwrap_or(DEF_DATA_SENDER_ADDRESS.to_string())
+    }
+
     pub fn data_sender_port(&self) -> u16 {
+        if let Some(port) = self.overrides.data_sender_port { return port; }
+
         self.key_file.integer(groups::MAIN, keys::DATA_SENDER_PORT).map(|i| i as u16).unwrap_or(DEF_DATA_SENDER_PORT)
     }
 }
 
+/// Parses "<label>;<address>;<format>;<enabled>".
+fn parse_source(s: &str) -> Result<DataSource, Box<dyn Error>> {
+    let values: Vec<&str> = s.split(';').collect();
+    if values.len() != 4 { return Err("expected 4 values".into()); }
+    Ok(DataSource{
+        label: values[0].into(),
+        address: values[1].into(),
+        format: values[2].parse::<InputFormat>()?,
+        enabled: values[3].parse::<bool>()?
+    })
+}
+
+/// Parses "<path>;<color (#RRGGBB)>;<shown>".
+#[cfg(feature = "gui")]
+fn parse_map_layer(s: &str) -> Result<map_layers::LayerSettings, Box<dyn Error>> {
+    let values: Vec<&str> = s.split(';').collect();
+    if values.len() != 3 { return Err("expected 3 values".into()); }
+
+    let hex = values[1].trim().strip_prefix('#').ok_or("color must start with '#'")?;
+    if hex.len() != 6 { return Err("expected color as #RRGGBB".into()); }
+    let channel = |i: usize| -> Result<f64, Box<dyn Error>> {
+        Ok(u8::from_str_radix(hex.get(2 * i..2 * i + 2).ok_or("invalid color")?, 16)? as f64 / 255.0)
+    };
+
+    Ok(map_layers::LayerSettings{
+        path: values[0].into(),
+        color: (channel(0)?, channel(1)?, channel(2)?),
+        shown: values[2].trim().parse::<bool>()?
+    })
+}
+
 fn config_file_path() -> std::path::PathBuf {
     std::path::Path::new(
         &dirs::config_dir().or(Some(std::path::Path::new("").to_path_buf())).unwrap()
@@ -0,0 +1,145 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use std::{collections::HashMap, error::Error, io::BufRead};
+
+/// Indices of CSV columns by (lowercase) header name.
+pub(crate) struct Columns(HashMap<String, usize>);
+
+impl Columns {
+    /// Returns `None` if the column is absent or the value is empty.
+    pub(crate) fn get<'a>(&self, record: &'a [String], name: &str) -> Option<&'a str> {
+        let value = record.get(*self.0.get(name)?)?.trim();
+        if value.is_empty() { None } else { Some(value) }
+    }
+
+    /// Returns the name of the first of `names` which is present.
+    pub(crate) fn find<'a>(&self, names: &[&'a str]) -> Option<&'a str> {
+        names.iter().find(|name| self.0.contains_key(**name)).copied()
+    }
+}
+
+/// Reads records of an RFC 4180 CSV file with a header line one by one (fields may be quoted; quoted fields may
+/// contain commas, line breaks and doubled quotes).
+pub(crate) struct Reader<R: BufRead> {
+    input: R,
+    columns: Columns
+}
+
+impl Reader<std::io::BufReader<std::fs::File>> {
+    pub(crate) fn open(path: &std::path::Path) -> Result<Self, Box<dyn Error>> {
+        let file = std::fs::File::open(path).map_err(|e| format!("{}: {}", path.to_string_lossy(), e))?;
+        Reader::new(std::io::BufReader::new(file))
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    pub(crate) fn new(mut input: R) -> Result<Self, Box<dyn Error>> {
+        let header = read_record(&mut input)?.ok_or("no CSV header")?;
+        let columns = Columns(header.into_iter().enumerate().map(|(i, name)| (name.trim().to_lowercase(), i)).collect());
+
+        Ok(Reader{ input, columns })
+    }
+
+    pub(crate) fn columns(&self) -> &Columns {
+        &self.columns
+    }
+
+    /// Returns `None` at the end of input.
+    pub(crate) fn next_record(&mut self) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+        read_record(&mut self.input)
+    }
+}
+
+fn read_record<R: BufRead>(input: &mut R) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            if quoted { return Err("unterminated quoted field".into()); }
+            if record.is_empty() && field.is_empty() { return Ok(None); }
+            record.push(field);
+            return Ok(Some(record));
+        }
+
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (quoted, c) {
+                (true, '"') => if chars.peek() == Some(&'"') { chars.next(); field.push('"'); } else { quoted = false; },
+                (true, c) => field.push(c),
+                (false, '"') => quoted = true,
+                (false, ',') => record.push(std::mem::take(&mut field)),
+                (false, '\r') => (),
+                (false, '\n') => {
+                    record.push(std::mem::take(&mut field));
+                    return Ok(Some(record));
+                },
+                (false, c) => field.push(c)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(input: &str) -> (Reader<&[u8]>, Vec<Vec<String>>) {
+        let mut reader = Reader::new(input.as_bytes()).unwrap();
+        let mut records = vec![];
+        while let Some(record) = reader.next_record().unwrap() { records.push(record); }
+        (reader, records)
+    }
+
+    #[test]
+    fn quoted_fields() {
+        let (reader, records) = records(
+            "Name, \"Value\"\r\n\"a, b\",\"say \"\"hi\"\"\"\n\"two\nlines\",\"\"\nplain,last"
+        );
+
+        assert_eq!(3, records.len());
+        let columns = reader.columns();
+        assert_eq!(Some("a, b"), columns.get(&records[0], "name"));
+        assert_eq!(Some("say \"hi\""), columns.get(&records[0], "value"));
+        assert_eq!(Some("two\nlines"), columns.get(&records[1], "name"));
+        assert_eq!(None, columns.get(&records[1], "value"));
+        assert_eq!(Some("last"), columns.get(&records[2], "value"));
+    }
+
+    #[test]
+    fn short_rows() {
+        let (reader, records) = records("a,b,c\n1\n1,2\n");
+
+        assert_eq!(vec![vec!["1".to_string()], vec!["1".to_string(), "2".to_string()]], records);
+        let columns = reader.columns();
+        assert_eq!(Some("1"), columns.get(&records[0], "a"));
+        assert_eq!(None, columns.get(&records[0], "b"));
+        assert_eq!(Some("2"), columns.get(&records[1], "b"));
+        assert_eq!(None, columns.get(&records[1], "c"));
+        assert_eq!(None, columns.get(&records[1], "d"));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Reader::new("".as_bytes()).is_err());
+
+        let mut reader = Reader::new("a,b\n\"1,2\n".as_bytes()).unwrap();
+        assert!(reader.next_record().is_err());
+    }
+
+    #[test]
+    fn find_column() {
+        let reader = Reader::new("ICAO24,Reg\n".as_bytes()).unwrap();
+        assert_eq!(Some("icao24"), reader.columns().find(&["icao", "icao24", "reg"]));
+        assert_eq!(None, reader.columns().find(&["typecode"]));
+    }
+}
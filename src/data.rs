@@ -7,28 +7,42 @@
 //
 
 use cgmath::{Basis3, Deg, EuclideanSpace, InnerSpace, Point2, Point3, Rotation, Rotation3, Vector3, Rad};
-use crate::{config, data_sender::send_data, gui};
+use crate::{geodesy, kalman, registry};
 use pointing_utils::{EARTH_RADIUS_M, GeoPos, LatLon, uom};
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
-use uom::{si::f64, si::{length, velocity}};
+use std::collections::{HashMap, VecDeque};
+use uom::{si::f64, si::{length, time, velocity}};
 
-const GC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
-const MAX_DURATION_WITHOUT_UPDATE: std::time::Duration = std::time::Duration::from_secs(60);
-const NORTH_POLE: Vector3<f64> = Vector3{ x: 0.0, y: 0.0, z: 1.0 };
 
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub struct ModeSTransponderCode(u32); // value <= 0x00FFFFFF
 
+impl ModeSTransponderCode {
+    pub fn new(value: u32) -> ModeSTransponderCode {
+        assert!(value <= 0x00FFFFFF);
+        ModeSTransponderCode(value)
+    }
+
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+}
+
+impl std::fmt::Display for ModeSTransponderCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:06X}", self.0)
+    }
+}
+
 impl std::str::FromStr for ModeSTransponderCode {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if s.len() != 6 {
-            Err(format!("invalid input length ({})", s.len()).into())
+            Err(format!("invalid input length ({})", s.len()))
         } else if s.chars().find(
             |c| !['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'A', 'B', 'C', 'D', 'E', 'F'].contains(c)
         ).is_some() {
-            Err(format!("input contains invalid character(s)"))
+            Err("input contains invalid character(s)".to_string())
         } else {
             match u32::from_str_radix(s, 16) {
                 Ok(value) => Ok(ModeSTransponderCode(value)),
@@ -38,6 +52,44 @@ impl std::str::FromStr for ModeSTransponderCode {
     }
 }
 
+/// Mode A code (4 octal digits) stored as a 12-bit value.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Squawk(u16); // value <= 0o7777
+
+impl Squawk {
+    pub fn from_bits(value: u16) -> Squawk {
+        assert!(value <= 0o7777);
+        Squawk(value)
+    }
+
+    pub fn is_emergency(&self) -> bool {
+        [0o7500, 0o7600, 0o7700].contains(&self.0)
+    }
+}
+
+impl std::str::FromStr for Squawk {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 4 {
+            Err(format!("invalid input length ({})", s.len()))
+        } else if s.chars().any(|c| !('0'..='7').contains(&c)) {
+            Err("input contains invalid character(s)".to_string())
+        } else {
+            match u16::from_str_radix(s, 8) {
+                Ok(value) => Ok(Squawk(value)),
+                Err(e) => Err(format!("{}", e))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Squawk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04o}", self.0)
+    }
+}
+
 #[derive(Debug)]
 pub enum SbsMessage {
     EsIdentificationAndCategory{
@@ -45,6 +97,14 @@ pub enum SbsMessage {
         callsign: String
     },
 
+    EsSurfacePosition{
+        id: ModeSTransponderCode,
+        altitude: Option<f64::Length>,
+        ground_speed: Option<f64::Velocity>,
+        track: Option<Deg<f64>>,
+        lat_lon: Option<LatLon>
+    },
+
     EsAirbornePosition{
         id: ModeSTransponderCode,
         altitude: Option<f64::Length>,
@@ -54,12 +114,44 @@ pub enum SbsMessage {
     EsAirborneVelocity{
         id: ModeSTransponderCode,
         ground_speed: f64::Velocity,
-        track: Deg<f64>
+        track: Deg<f64>,
+        /// Positive when climbing.
+        vertical_rate: Option<f64::Velocity>
     },
 
     SurveillanceAltitude{
         id: ModeSTransponderCode,
         altitude: f64::Length
+    },
+
+    SurveillanceId{
+        id: ModeSTransponderCode,
+        altitude: Option<f64::Length>,
+        squawk: Squawk,
+        /// Squawk has changed.
+        alert: Option<bool>,
+        emergency: Option<bool>,
+        /// Special Position Identification ("ident") pressed.
+        spi: Option<bool>,
+        on_ground: Option<bool>
+    },
+
+    AirToAir{
+        id: ModeSTransponderCode,
+        altitude: Option<f64::Length>,
+        on_ground: Option<bool>
+    },
+
+    AllCallReply{
+        id: ModeSTransponderCode,
+        on_ground: Option<bool>
+    },
+
+    /// Not an actual SBS message; generated for frames received in Beast format.
+    SignalLevel{
+        id: ModeSTransponderCode,
+        /// dBFS.
+        rssi: f64
     }
 }
 
@@ -67,13 +159,40 @@ impl SbsMessage {
     pub fn id(&self) -> ModeSTransponderCode {
         match self {
             SbsMessage::EsIdentificationAndCategory{ id, .. } => *id,
+            SbsMessage::EsSurfacePosition{ id, .. } => *id,
             SbsMessage::EsAirbornePosition{ id, .. } => *id,
             SbsMessage::EsAirborneVelocity{ id, .. } => *id,
             SbsMessage::SurveillanceAltitude{ id, .. } => *id,
+            SbsMessage::SurveillanceId{ id, .. } => *id,
+            SbsMessage::AirToAir{ id, .. } => *id,
+            SbsMessage::AllCallReply{ id, .. } => *id,
+            SbsMessage::SignalLevel{ id, .. } => *id,
         }
     }
 }
 
+/// Reported position, kept in `Aircraft::trail`.
+#[derive(Clone, Debug)]
+pub struct TrailPoint {
+    pub lat_lon: LatLon,
+    pub altitude: Option<f64::Length>,
+    pub t: std::time::Instant
+}
+
+/// Limits of the position history kept for each aircraft.
+#[derive(Clone, Debug)]
+pub struct TrailLimits {
+    pub max_age: std::time::Duration,
+    /// If 0, no history is kept.
+    pub max_points: usize
+}
+
+impl Default for TrailLimits {
+    fn default() -> TrailLimits {
+        TrailLimits{ max_age: std::time::Duration::from_secs(300), max_points: 500 }
+    }
+}
+
 #[derive(PartialEq)]
 pub enum State {
     Normal,
@@ -84,168 +203,178 @@ pub struct Aircraft {
     pub id: ModeSTransponderCode,
     pub state: State,
     pub callsign: Option<String>,
+    /// Registration, type etc. (if found in the registry database).
+    pub registry_entry: Option<registry::Entry>,
     pub lat_lon: Option<(LatLon, std::time::Instant)>, // contains time of last update
     pub estimated_lat_lon: Option<(LatLon, std::time::Instant)>, // contains time of last estimation
     pub track: Option<Deg<f64>>,
     pub altitude: Option<f64::Length>,
+    pub estimated_altitude: Option<(f64::Length, std::time::Instant)>, // contains time of last estimation
     pub ground_speed: Option<f64::Velocity>,
+    /// Positive when climbing.
+    pub vertical_rate: Option<f64::Velocity>,
+    pub squawk: Option<Squawk>,
+    pub alert: bool,
+    pub emergency: bool,
+    pub spi: bool,
+    pub on_ground: Option<bool>,
+    /// Signal level (dBFS) of the last received frame.
+    pub rssi: Option<f64>,
+    /// Labels of data sources which have received messages from this aircraft, with time of last message.
+    pub sources: HashMap<String, std::time::Instant>,
+    /// Label of data source which has provided `lat_lon`.
+    pub position_source: Option<String>,
+    /// If set, used (instead of dead reckoning) to estimate position and to reject inconsistent messages.
+    pub track_filter: Option<kalman::TrackFilter>,
+    /// Recent reported positions (oldest first).
+    pub trail: VecDeque<TrailPoint>,
     pub t_last_update: std::time::Instant, // time of last update of any field
 }
 
 impl Aircraft {
     pub fn update_interpolated_position(&mut self, now: std::time::Instant) {
+        if let Some(filter) = &self.track_filter {
+            if let Some(estimate) = filter.estimate(now) {
+                self.estimated_lat_lon = Some((estimate.lat_lon, now));
+                self.estimated_altitude = estimate.altitude.map(|altitude| (altitude, now));
+            }
+            return;
+        }
+
+        self.update_interpolated_altitude(now);
+
         match &self.estimated_lat_lon {
             None => {
-                match (&self.lat_lon, self.track, self.ground_speed) {
-                    (Some((lat_lon, t_last)), Some(track), Some(ground_speed)) => {
-                        self.estimated_lat_lon = Some((estimate_position(lat_lon, track, ground_speed, now - *t_last), now));
-                    },
-                    _ => ()
+                if let (Some((lat_lon, t_last)), Some(track), Some(ground_speed)) = (&self.lat_lon, self.track, self.ground_speed) {
+                    let dt = now.saturating_duration_since(*t_last);
+                    self.estimated_lat_lon = Some((estimate_position(lat_lon, track, ground_speed, dt), now));
                 }
             },
 
             Some((est_lat_lon, t_last)) => {
+                let dt = now.saturating_duration_since(*t_last);
                 self.estimated_lat_lon =
-                    Some((estimate_position(est_lat_lon, self.track.unwrap(), self.ground_speed.unwrap(), now - *t_last), now));
+                    Some((estimate_position(est_lat_lon, self.track.unwrap(), self.ground_speed.unwrap(), dt), now));
             },
         }
     }
 
+    /// Extrapolates altitude using the vertical rate.
+    fn update_interpolated_altitude(&mut self, now: std::time::Instant) {
+        if self.on_ground == Some(true) {
+            self.estimated_altitude = None;
+            return;
+        }
+
+        self.estimated_altitude = match (&self.estimated_altitude, self.altitude, self.vertical_rate) {
+            (Some((altitude, t_last)), _, Some(vertical_rate)) => {
+                let dt = now.saturating_duration_since(*t_last).as_secs_f64();
+                Some((*altitude + vertical_rate * f64::Time::new::<time::second>(dt), now))
+            },
+            (None, Some(altitude), Some(_)) => Some((altitude, now)),
+            _ => None
+        };
+    }
+
     pub fn estimated_lat_lon(&self) -> Option<&LatLon> {
         self.estimated_lat_lon.as_ref().map(|ell| &ell.0)
     }
-}
-
-pub struct DataReceiver {
-    pub server_address: String,
-    pub worker: Option<std::thread::JoinHandle<()>>, // always `Some`
-    pub stream: std::net::TcpStream // stream providing SBS messages
-}
 
-pub struct ProgramData {
-    pub observer_location: GeoPos,
-    pub aircraft: HashMap<ModeSTransponderCode, Aircraft>,
-    pub gui: Option<gui::GuiData>, // always set once GUI is initialized,
-    pub config: config::Configuration,
-    /// Last garbage collection of `aircraft`.
-    t_last_gc: std::time::Instant,
-    pub data_receiver: Option<DataReceiver>,
-    pub recording: bool,
-    /// Maximal (non-interpolated) distance seen so far.
-    pub max_distance: Option<f64::Length>,
-    pub max_num_aircraft: usize,
-    pub data_senders: Vec<std::net::TcpStream>,
-}
+    pub fn estimated_altitude(&self) -> Option<f64::Length> {
+        self.estimated_altitude.map(|ea| ea.0)
+    }
 
-impl ProgramData {
-    pub fn new() -> ProgramData {
-        let config = config::Configuration::new();
+    /// Returns the estimated altitude if `interpolated` is true and it is available, the reported one otherwise.
+    pub fn current_altitude(&self, interpolated: bool) -> Option<f64::Length> {
+        if interpolated { self.estimated_altitude().or(self.altitude) } else { self.altitude }
+    }
 
-        ProgramData{
-            observer_location: config.observer_location().unwrap_or(
-                GeoPos{
-                    lat_lon: LatLon{ lat: Deg(0.0), lon: Deg(0.0) },
-                    elevation: f64::Length::new::<length::meter>(0.0)
-                }
-            ),
-            aircraft: HashMap::new(),
-            max_num_aircraft: 0,
-            gui: None,
-            config,
-            t_last_gc: std::time::Instant::now(),
-            data_receiver: None,
-            recording: false,
-            max_distance: None,
-            data_senders: vec![]
-        }
+    /// Returns the track filter's estimate at `t`; `None` if there is no filter or no position yet.
+    pub fn track_estimate(&self, t: std::time::Instant) -> Option<kalman::Estimate> {
+        self.track_filter.as_ref()?.estimate(t)
     }
 
-    pub fn update(&mut self, msg: SbsMessage) {
-        let mut important_data_changed = false;
-
-        let entry = self.aircraft.entry(msg.id()).or_insert(Aircraft{
-            id: msg.id(),
-            state: State::Normal,
-            callsign: None,
-            lat_lon: None,
-            estimated_lat_lon: None,
-            altitude: None,
-            track: None,
-            ground_speed: None,
-            t_last_update: std::time::Instant::now()
-        });
-
-        match msg {
-            SbsMessage::EsIdentificationAndCategory{ callsign, .. } => {
-                entry.callsign = Some(callsign);
-            },
+    /// Returns true if either the emergency flag is set or an emergency squawk is being transmitted.
+    pub fn in_emergency(&self) -> bool {
+        self.emergency || self.squawk.is_some_and(|s| s.is_emergency())
+    }
 
-            SbsMessage::EsAirbornePosition{ lat_lon, altitude, .. } => {
-                if let Some(lat_lon) = lat_lon {
-                    if self.config.filter_ooo_messages().unwrap_or(true)
-                        && entry.lat_lon.is_some()
-                        && entry.track.is_some()
-                        && entry.altitude.is_some()
-                        && aircraft_moved_backwards(entry, &lat_lon) {
-
-                        return;
-                    }
-
-                    entry.lat_lon = Some((lat_lon, std::time::Instant::now()));
-                    if entry.estimated_lat_lon.is_some() {
-                        entry.estimated_lat_lon = entry.lat_lon.clone();
-                    }
+    /// Returns false if the position is a duplicate or (if `filter_ooo_messages` is true) stale, i.e., already
+    /// superseded by a more recent one from another source.
+    pub(crate) fn is_fresh_position(&self, lat_lon: &LatLon, source: &str, filter_ooo_messages: bool) -> bool {
+        match (&self.lat_lon, &self.position_source) {
+            (Some((current, _)), Some(current_source)) => {
+                if current.lat == lat_lon.lat && current.lon == lat_lon.lon {
+                    false // duplicate
+                } else if filter_ooo_messages
+                    && current_source != source
+                    && self.track.is_some()
+                    && self.track_filter.is_none() {
+
+
+                    !aircraft_moved_backwards(self, lat_lon)
+                } else {
+                    true
                 }
-
-                entry.altitude = altitude;
-                important_data_changed = true;
-            },
-
-            SbsMessage::EsAirborneVelocity{ ground_speed, track, .. } => {
-                entry.ground_speed = Some(ground_speed);
-                entry.track = Some(track);
-                important_data_changed = true;
             },
 
-            SbsMessage::SurveillanceAltitude{ altitude, .. } => {
-                entry.altitude = Some(altitude);
-                important_data_changed = true;
-            }
+            _ => true
         }
-        entry.t_last_update = std::time::Instant::now();
+    }
 
-        if entry.lat_lon.is_some() && entry.altitude.is_some() {
-            let distance = get_distance(&self.observer_location, entry, false);
-            self.max_distance = Some(self.max_distance.unwrap_or(meters(0.0)).max(distance));
+    pub(crate) fn set_position(&mut self, lat_lon: LatLon, source: &str, trail_limits: &TrailLimits, now: std::time::Instant) {
+        if trail_limits.max_points > 0 {
+            self.trail.push_back(TrailPoint{ lat_lon: lat_lon.clone(), altitude: self.altitude, t: now });
+            self.prune_trail(trail_limits, now);
         }
-
-        if important_data_changed && entry.state == State::Selected {
-            send_data(entry, &self.observer_location, &mut self.data_senders);
+        self.position_source = Some(source.into());
+        self.lat_lon = Some((lat_lon, now));
+        if self.estimated_lat_lon.is_some() {
+            self.estimated_lat_lon = match self.track_estimate(now) {
+                Some(estimate) => Some((estimate.lat_lon, now)),
+                None => self.lat_lon.clone()
+            };
         }
+    }
 
-        let num_displayed_aircraft = self.aircraft
-            .iter()
-            .filter(|(_, aircraft)| { aircraft.lat_lon.is_some() && aircraft.track.is_some() })
-            .count();
-        self.max_num_aircraft = self.max_num_aircraft.max(num_displayed_aircraft);
-
-        let gui = self.gui.as_ref().unwrap();
+    /// Removes trail points exceeding `limits`.
+    pub(crate) fn prune_trail(&mut self, limits: &TrailLimits, now: std::time::Instant) {
+        while self.trail.len() > limits.max_points
+            || self.trail.front().is_some_and(|p| now.saturating_duration_since(p.t) > limits.max_age) {
 
-        gui.status_bar_fields.num_aircraft.set_text(
-            &format!("Aircraft: {} (max: {})", num_displayed_aircraft, self.max_num_aircraft)
-        );
+            self.trail.pop_front();
+        }
+    }
 
-        if let Some(d) = self.max_distance {
-            gui.status_bar_fields.max_distance.set_text(
-                &format!("Max distance: {:.1} km", d.get::<length::kilometer>())
-            );
+    /// Feeds the track filter (if any) with the reported altitude; an altitude rejected by the filter is not stored.
+    pub(crate) fn set_altitude(&mut self, altitude: Option<f64::Length>, now: std::time::Instant) {
+        if let (Some(filter), Some(altitude)) = (&mut self.track_filter, altitude) {
+            if !filter.update_altitude(altitude, now) { return; }
+        }
+        self.altitude = altitude;
+        if self.estimated_altitude.is_some() {
+            self.estimated_altitude = self.track_estimate(now)
+                .and_then(|estimate| estimate.altitude)
+                .or(altitude)
+                .map(|altitude| (altitude, now));
         }
     }
 
-    pub fn garbage_collect(&mut self) {
-        if self.t_last_gc.elapsed() < GC_INTERVAL { return; }
-        self.aircraft.retain(|_, aircraft| { aircraft.t_last_update.elapsed() <= MAX_DURATION_WITHOUT_UPDATE });
-        self.t_last_gc = std::time::Instant::now();
+    /// Feeds the track filter (if any) with the reported velocity.
+    pub(crate) fn set_velocity(
+        &mut self,
+        track: Deg<f64>,
+        ground_speed: f64::Velocity,
+        vertical_rate: Option<f64::Velocity>,
+        now: std::time::Instant
+    ) {
+        self.track = Some(track);
+        self.ground_speed = Some(ground_speed);
+        self.vertical_rate = vertical_rate;
+        if let Some(filter) = &mut self.track_filter {
+            filter.update_velocity(track, ground_speed, now);
+            if let Some(vertical_rate) = vertical_rate { filter.update_vertical_rate(vertical_rate, now); }
+        }
     }
 }
 
@@ -279,12 +408,12 @@ fn to_xyz_unit(lat_lon: &LatLon) -> Point3<f64> {
     }
 }
 
+/// Returns position in the global (ECEF, WGS84) frame (meters); see `geodesy::geodetic_to_ecef`.
 pub fn to_global(position: &GeoPos) -> Point3<f64> {
-    let r = EARTH_RADIUS_M + position.elevation.get::<length::meter>();
-    r * to_xyz_unit(&position.lat_lon)
+    geodesy::geodetic_to_ecef(position)
 }
 
-fn meters(value: f64) -> f64::Length {
+pub(crate) fn meters(value: f64) -> f64::Length {
     f64::Length::new::<length::meter>(value)
 }
 
@@ -294,45 +423,22 @@ fn estimate_position(
     ground_speed: f64::Velocity,
     duration: std::time::Duration
 ) -> LatLon {
-    let pos = to_xyz_unit(start);
-
-    let r = pos.to_vec();
-    let n_pole = Point3{ x: 0.0, y: 0.0, z: 1.0 };
-    let p = (n_pole - pos).normalize();
-    let track_rot = Basis3::from_axis_angle(r, -track);
-    let q = track_rot.rotate_vector(p);
-    let forward_angle = Rad(ground_speed.get::<velocity::meter_per_second>() * duration.as_secs_f64() / EARTH_RADIUS_M);
-    let forward_rot = Basis3::from_axis_angle(Vector3::cross(q, r), -forward_angle);
-
-    let est_p = forward_rot.rotate_point(pos);
-
-    let lat = Deg::from(Rad(est_p.z.asin()));
-    let lon = Deg::from(Rad(f64::atan2(est_p.y, est_p.x)));
-
-    LatLon{ lat, lon }
-}
-
-/// Assumes level flight; returns unit vector in global frame.
-fn get_travel_dir(aircraft: &Aircraft) -> Vector3<f64> {
-    let r = to_xyz_unit(&aircraft.lat_lon.as_ref().unwrap().0).to_vec();
-    let s = NORTH_POLE.cross(r);
-    let to_north = r.cross(s).normalize();
+    let distance = meters(ground_speed.get::<velocity::meter_per_second>() * duration.as_secs_f64());
 
-    let rot = Basis3::from_axis_angle(r, -aircraft.track.unwrap());
-
-    rot.rotate_vector(to_north)
+    geodesy::geodesic_direct(start, track, distance).0
 }
 
-fn aircraft_moved_backwards(aircraft: &Aircraft, new_pos: &LatLon) -> bool {
-    let old_xyz = to_xyz_unit(
-        match aircraft.estimated_lat_lon.as_ref() {
-            Some((lat_lon, _)) => &lat_lon,
-            None => &aircraft.lat_lon.as_ref().unwrap().0
-        }
-    );
-    let new_xyz = to_xyz_unit(new_pos);
+/// Returns true if `new_pos` lies behind the last (estimated) position of `aircraft` with respect to its track.
+pub(crate) fn aircraft_moved_backwards(aircraft: &Aircraft, new_pos: &LatLon) -> bool {
+    let old_pos = match aircraft.estimated_lat_lon.as_ref() {
+        Some((lat_lon, _)) => lat_lon,
+        None => &aircraft.lat_lon.as_ref().unwrap().0
+    };
+    let frame = geodesy::LocalFrame::new(&GeoPos{ lat_lon: old_pos.clone(), elevation: meters(0.0) });
+    let offset = frame.to_enu_point(&to_global(&GeoPos{ lat_lon: new_pos.clone(), elevation: meters(0.0) }));
+    let track = Rad::from(aircraft.track.unwrap()).0;
 
-    (new_xyz - old_xyz).dot(get_travel_dir(aircraft)) < 0.0
+    offset.x * track.sin() + offset.y * track.cos() < 0.0
 }
 
 pub fn get_distance(observer: &GeoPos, aircraft: &Aircraft, interpolated: bool) -> f64::Length {
@@ -343,6 +449,45 @@ pub fn get_distance(observer: &GeoPos, aircraft: &Aircraft, interpolated: bool)
     };
 
     let obs_pos = to_global(observer);
-    let aircraft_pos = to_global(&GeoPos{ lat_lon, elevation: aircraft.altitude.unwrap() });
+    let aircraft_pos = to_global(&GeoPos{ lat_lon, elevation: aircraft.current_altitude(interpolated).unwrap() });
     meters((obs_pos - aircraft_pos).magnitude())
 }
+
+/// Returns azimuth (measured from North towards East) and elevation of `aircraft` as seen by `observer`;
+/// `None` if position or altitude is unknown.
+pub fn azimuth_elevation(observer: &GeoPos, aircraft: &Aircraft, interpolated: bool) -> Option<(Deg<f64>, Deg<f64>)> {
+    let lat_lon = if interpolated && aircraft.estimated_lat_lon().is_some() {
+        aircraft.estimated_lat_lon().unwrap().clone()
+    } else {
+        aircraft.lat_lon.as_ref()?.0.clone()
+    };
+
+    Some(global_azimuth_elevation(observer, &to_global(&GeoPos{ lat_lon, elevation: aircraft.current_altitude(interpolated)? })))
+}
+
+/// Returns azimuth (measured from North towards East) and elevation of `target` (global frame, see `to_global`)
+/// as seen by `observer`.
+pub fn global_azimuth_elevation(observer: &GeoPos, target: &Point3<f64>) -> (Deg<f64>, Deg<f64>) {
+    geodesy::LocalFrame::new(observer).azimuth_elevation(target)
+}
+
+/// Returns position of `aircraft` at `t`, predicted by the track filter or (if there is none) extrapolated
+/// from the last (estimated) position along its track at its ground speed and vertical rate (level flight
+/// if unknown); `None` if position, altitude, track or ground speed is unknown.
+pub fn extrapolate_position(aircraft: &Aircraft, t: std::time::Instant) -> Option<GeoPos> {
+    if let Some(estimate) = aircraft.track_estimate(t) {
+        return Some(GeoPos{ lat_lon: estimate.lat_lon, elevation: estimate.altitude.or(aircraft.altitude)? });
+    }
+
+    let (lat_lon, t_pos) = aircraft.estimated_lat_lon.as_ref().or(aircraft.lat_lon.as_ref())?;
+    let altitude = match (aircraft.estimated_altitude, aircraft.vertical_rate) {
+        (Some((altitude, t_alt)), Some(vertical_rate)) =>
+            altitude + vertical_rate * f64::Time::new::<time::second>(t.saturating_duration_since(t_alt).as_secs_f64()),
+        _ => aircraft.altitude?
+    };
+
+    Some(GeoPos{
+        lat_lon: estimate_position(lat_lon, aircraft.track?, aircraft.ground_speed?, t.saturating_duration_since(*t_pos)),
+        elevation: altitude
+    })
+}
@@ -6,165 +6,378 @@
 // (see the LICENSE file for details).
 //
 
-use cgmath::Deg;
-use crate::{data, data::ProgramData};
-use gtk4 as gtk;
-use gtk::{glib, glib::clone};
-use pointing_utils::{LatLon, uom};
-use std::{cell::RefCell, error::Error, rc::Rc, io::prelude::*};
-use uom::{si::f64, si::{length, velocity}};
+use crate::program_data::{DataReceiver, ProgramData};
+use glib::clone;
+use plane_tracker::{beast, data, mode_s, sbs};
+use pointing_utils::LatLon;
+use std::{cell::RefCell, rc::Rc, io::prelude::*, sync::{Arc, Mutex, mpsc}};
+
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+const INITIAL_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+const MAX_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(60);
+
+static NEXT_RECEIVER_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+#[derive(Clone, Debug)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    /// Connection has failed or has been lost; next attempt will be made after `delay`.
+    Retrying{ error: String, delay: std::time::Duration }
+}
 
-mod msg_type {
-    pub const ES_IDENTIFICATION_AND_CATEGORY: i32 = 1;
-    pub const ES_AIRBORNE_POSITION_MESSAGE: i32 = 3;
-    pub const ES_AIRBORNE_VELOCITY_MESSAGE: i32 = 4;
-    pub const SURVEILLANCE_ALT_MESSAGE: i32 = 5;
+impl std::fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionState::Connecting => write!(f, "connecting"),
+            ConnectionState::Connected => write!(f, "connected"),
+            ConnectionState::Retrying{ error, delay } => write!(f, "retrying in {} s ({})", delay.as_secs(), error)
+        }
+    }
 }
 
-fn feet(value: f64) -> f64::Length {
-    f64::Length::new::<length::foot>(value)
+#[derive(Clone, Debug)]
+pub struct DataSource {
+    /// Unique among configured sources.
+    pub label: String,
+    pub address: String,
+    pub format: InputFormat,
+    /// Whether to connect to this source.
+    pub enabled: bool
 }
 
-fn knots(value: f64) -> f64::Velocity {
-    f64::Velocity::new::<velocity::knot>(value)
+pub enum ReceiverEvent {
+    Message(data::SbsMessage),
+    StateChanged(ConnectionState)
 }
 
-pub fn data_receiver(
-    stream: std::net::TcpStream,
-    rec_output: Option<std::fs::File>,
-    sender: gtk::glib::Sender<data::SbsMessage>
-) {
-    let buf_reader = std::io::BufReader::new(stream);
-    let mut buf_writer = if let Some(recording) = rec_output { Some(std::io::BufWriter::new(recording)) } else { None };
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InputFormat {
+    /// Newline-delimited SBS text (e.g., dump1090's port 30003).
+    Sbs,
+    /// Beast binary protocol (e.g., dump1090's port 30005).
+    Beast,
+    /// Newline-delimited raw frames as hex text (e.g., dump1090's port 30002).
+    Avr
+}
 
-    for line in buf_reader.lines() {
-        if let Ok(line) = line {
-            if let Some(w) = &mut buf_writer {
-                let _ = w.write(format!(
-                    "{};{}\n",
-                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.6f"),
-                    line
-                ).as_bytes()); //TODO: handle errors
-            }
+impl InputFormat {
+    #[cfg(feature = "gui")]
+    pub const ALL: [InputFormat; 3] = [InputFormat::Sbs, InputFormat::Beast, InputFormat::Avr];
+}
 
-            match parse_sbs_message(&line) {
-                Ok(m) => if let Some(m) = m { sender.send(m).unwrap(); },
-                Err(e) => println!("Error parsing SBS1 message \"{}\": {}.", line, e)
-            }
+impl std::fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            InputFormat::Sbs => "SBS",
+            InputFormat::Beast => "Beast",
+            InputFormat::Avr => "AVR"
+        })
+    }
+}
+
+impl std::str::FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "SBS" => Ok(InputFormat::Sbs),
+            "Beast" => Ok(InputFormat::Beast),
+            "AVR" => Ok(InputFormat::Avr),
+            _ => Err(format!("unknown input format \"{}\"", s))
         }
     }
 }
 
-pub fn on_data_received(program_data_rc: &Rc<RefCell<ProgramData>>, msg: data::SbsMessage) {
-    program_data_rc.borrow_mut().update(msg);
+fn write_recording_line(writer: &mut Option<std::io::BufWriter<std::fs::File>>, line: &str) {
+    if let Some(w) = writer {
+        let _ = w.write(format!(
+            "{};{}\n",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.6f"),
+            line
+        ).as_bytes()); //TODO: handle errors
+    }
+}
+
+/// Receives data from `stream` until it is closed or fails; returns an error if the receiving end of `sender`
+/// has been dropped.
+fn receive(
+    stream: std::net::TcpStream,
+    format: InputFormat,
+    decoder: &mut mode_s::Decoder,
+    buf_writer: &mut Option<std::io::BufWriter<std::fs::File>>,
+    sender: &glib::Sender<ReceiverEvent>
+) -> Result<(), mpsc::SendError<ReceiverEvent>> {
+    match format {
+        InputFormat::Sbs => receive_sbs(stream, buf_writer, sender),
+        InputFormat::Beast => receive_beast(stream, decoder, buf_writer, sender),
+        InputFormat::Avr => receive_avr(stream, decoder, buf_writer, sender)
+    }
+}
+
+fn stop_requested(stop_receiver: &mpsc::Receiver<()>) -> bool {
+    matches!(stop_receiver.try_recv(), Err(mpsc::TryRecvError::Disconnected))
 }
 
-/// Returns `None` for unsupported message types.
-fn parse_sbs_message(msg: &str) -> Result<Option<data::SbsMessage>, Box<dyn Error>> {
-    let fields: Vec<&str> = msg.split(',').collect();
+fn connect(server_address: &str) -> std::io::Result<std::net::TcpStream> {
+    let mut last_error = std::io::Error::new(std::io::ErrorKind::NotFound, "address could not be resolved");
+    for address in std::net::ToSocketAddrs::to_socket_addrs(server_address)? {
+        match std::net::TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Connects to `server_address` and receives data, reconnecting (with exponential backoff) whenever
+/// the connection fails. Finishes once the sending end of `stop_receiver` or the receiving end of `sender`
+/// is dropped.
+///
+/// `reference` is used for local decoding of CPR-encoded positions from raw frames.
+fn data_receiver(
+    server_address: String,
+    format: InputFormat,
+    reference: LatLon,
+    rec_output: Option<std::fs::File>,
+    current_stream: Arc<Mutex<Option<std::net::TcpStream>>>,
+    stop_receiver: mpsc::Receiver<()>,
+    sender: glib::Sender<ReceiverEvent>
+) {
+    let mut buf_writer = if let Some(recording) = rec_output { Some(std::io::BufWriter::new(recording)) } else { None };
+    let mut decoder = mode_s::Decoder::new(reference);
+    let mut retry_delay = INITIAL_RETRY_DELAY;
+
+    loop {
+        if sender.send(ReceiverEvent::StateChanged(ConnectionState::Connecting)).is_err() { break; }
 
-    if fields.is_empty() { return Err("empty message".into()); }
+        let error = match connect(&server_address) {
+            Ok(stream) => {
+                {
+                    let mut current_stream = current_stream.lock().unwrap();
+                    // checked while holding the lock, so that `stop` cannot miss the stream we are about to read
+                    if stop_requested(&stop_receiver) { break; }
+                    *current_stream = Some(stream.try_clone().unwrap());
+                }
 
-    if fields[0] != "MSG" { return Ok(None); }
+                if sender.send(ReceiverEvent::StateChanged(ConnectionState::Connected)).is_err() { break; }
+                retry_delay = INITIAL_RETRY_DELAY;
 
-    if fields.len() < 5 { return Err(format!("too few fields ({})", fields.len()).into()); }
+                let result = receive(stream, format, &mut decoder, &mut buf_writer, &sender);
 
-    let msg_type = fields[1].parse::<i32>();
+                current_stream.lock().unwrap().take();
+                if result.is_err() { break; }
+                "connection closed".to_string()
+            },
 
-    if let Err(e) = msg_type { return Err(Box::new(e)); }
+            Err(e) => e.to_string()
+        };
 
-    if fields[4].is_empty() {
-        return Err(format!("MSG,{} has empty field 5", msg_type::ES_IDENTIFICATION_AND_CATEGORY).into());
+        if stop_requested(&stop_receiver) { break; }
+
+        let state = ConnectionState::Retrying{ error, delay: retry_delay };
+        if sender.send(ReceiverEvent::StateChanged(state)).is_err() { break; }
+        if let Err(mpsc::RecvTimeoutError::Disconnected) = stop_receiver.recv_timeout(retry_delay) { break; }
+        retry_delay = (2 * retry_delay).min(MAX_RETRY_DELAY);
     }
+}
 
-    let id = fields[4].parse::<data::ModeSTransponderCode>()?;
+fn receive_sbs(
+    stream: std::net::TcpStream,
+    buf_writer: &mut Option<std::io::BufWriter<std::fs::File>>,
+    sender: &glib::Sender<ReceiverEvent>
+) -> Result<(), mpsc::SendError<ReceiverEvent>> {
+    let buf_reader = std::io::BufReader::new(stream);
 
-    match msg_type.unwrap() {
-        msg_type::ES_IDENTIFICATION_AND_CATEGORY => {
-            if fields.len() < 11 {
-                return Err(format!(
-                    "MSG,{} has too few fields ({})",
-                    msg_type::ES_IDENTIFICATION_AND_CATEGORY,
-                    fields.len()).into()
-                );
+    for line in buf_reader.lines() {
+        if let Ok(line) = line {
+            write_recording_line(buf_writer, &line);
+
+            match sbs::parse_sbs_message(&line) {
+                Ok(m) => if let Some(m) = m { sender.send(ReceiverEvent::Message(m))?; },
+                Err(e) => println!("Error parsing SBS1 message \"{}\": {}.", line, e)
             }
+        }
+    }
+
+    Ok(())
+}
 
-            if fields[10].is_empty() {
-                return Err(format!("MSG,{} has empty field 10", msg_type::ES_IDENTIFICATION_AND_CATEGORY).into());
+fn receive_beast(
+    stream: std::net::TcpStream,
+    decoder: &mut mode_s::Decoder,
+    buf_writer: &mut Option<std::io::BufWriter<std::fs::File>>,
+    sender: &glib::Sender<ReceiverEvent>
+) -> Result<(), mpsc::SendError<ReceiverEvent>> {
+    let mut reader = beast::BeastReader::new(stream);
+
+    while let Ok(Some(frame)) = reader.next_frame() {
+        if frame.kind == beast::FrameKind::ModeAc { continue; }
+
+        if buf_writer.is_some() {
+            // recorded in AVR format with MLAT timestamp
+            let hex: String = frame.data.iter().map(|b| format!("{:02X}", b)).collect();
+            write_recording_line(buf_writer, &format!("@{:012X}{};", frame.timestamp, hex));
+        }
+
+        if let Some(msg) = decoder.decode(&frame.data) {
+            let id = msg.id();
+            sender.send(ReceiverEvent::Message(msg))?;
+            if let Some(rssi) = frame.rssi() {
+                sender.send(ReceiverEvent::Message(data::SbsMessage::SignalLevel{ id, rssi }))?;
             }
+        }
+    }
 
-            return Ok(Some(data::SbsMessage::EsIdentificationAndCategory{
-                id,
-                callsign: fields[10].into()
-            }));
-        },
+    Ok(())
+}
 
-        msg_type::ES_AIRBORNE_POSITION_MESSAGE => {
-            let altitude = match fields[11].parse::<u32>() {
-                Ok(value) => Some(feet(value as f64)),
-                _ => None
-            };
-            let lat = fields[14].parse::<f64>();
-            let lon = fields[15].parse::<f64>();
-
-            let lat_lon = match (lat, lon) {
-                (Ok(lat), Ok(lon)) => Some(LatLon{ lat: Deg(lat), lon: Deg(lon) }),
-                (Err(_), Err(_)) => None,
-                (Ok(_), Err(e)) | (Err(e), Ok(_)) => return Err(Box::new(e))
-            };
-
-            return Ok(Some(data::SbsMessage::EsAirbornePosition{id, altitude, lat_lon}));
-        },
+fn receive_avr(
+    stream: std::net::TcpStream,
+    decoder: &mut mode_s::Decoder,
+    buf_writer: &mut Option<std::io::BufWriter<std::fs::File>>,
+    sender: &glib::Sender<ReceiverEvent>
+) -> Result<(), mpsc::SendError<ReceiverEvent>> {
+    let buf_reader = std::io::BufReader::new(stream);
 
-        msg_type::ES_AIRBORNE_VELOCITY_MESSAGE => {
-            let ground_speed = knots(fields[12].parse::<f64>()?);
-            let track = Deg(fields[13].parse::<f64>()?);
+    for line in buf_reader.lines() {
+        if let Ok(line) = line {
+            write_recording_line(buf_writer, &line);
 
-            return Ok(Some(data::SbsMessage::EsAirborneVelocity{ id, ground_speed, track }));
-        },
+            match sbs::parse_avr_frame(&line) {
+                Ok(frame) => if let Some(m) = decoder.decode(&frame) { sender.send(ReceiverEvent::Message(m))?; },
+                Err(e) => println!("Error parsing AVR frame \"{}\": {}.", line, e)
+            }
+        }
+    }
+
+    Ok(())
+}
 
-        msg_type::SURVEILLANCE_ALT_MESSAGE => {
-            let altitude = feet(fields[11].parse::<u32>()? as f64);
-            return Ok(Some(data::SbsMessage::SurveillanceAltitude{ id, altitude }));
+pub fn on_receiver_event(program_data_rc: &Rc<RefCell<ProgramData>>, receiver_id: u64, event: ReceiverEvent) {
+    let mut pd = program_data_rc.borrow_mut();
+
+    // ignore events still queued by an already stopped receiver
+    let receiver = match pd.data_receivers.iter_mut().find(|r| r.id == receiver_id) {
+        Some(receiver) => receiver,
+        None => return
+    };
+
+    match event {
+        ReceiverEvent::Message(msg) => {
+            let label = receiver.source.label.clone();
+            pd.update(msg, &label);
         },
 
-        _ => ()
+        ReceiverEvent::StateChanged(state) => {
+            receiver.state = state;
+            show_connection_states(&pd);
+        }
     }
+}
 
-    Ok(None)
+fn show_connection_states(pd: &ProgramData) {
+    let text = pd.data_receivers
+        .iter()
+        .map(|r| format!("{} ({}, {}): {}", r.source.label, r.source.address, r.source.format, r.state))
+        .collect::<Vec<String>>()
+        .join(" | ");
+
+    let text = if text.is_empty() { "stopped" } else { &text };
+    #[cfg(feature = "gui")]
+    if let Some(gui) = pd.gui.as_ref() {
+        gui.status_bar_fields.connection_state.set_text(text);
+        return;
+    }
+    println!("{}", text);
 }
 
-pub fn start(
-    server_address: String,
+fn start(
+    source: DataSource,
     rec_output: Option<std::fs::File>,
     program_data_rc: &Rc<RefCell<ProgramData>>
 ) {
-    let stream = std::net::TcpStream::connect(&server_address).unwrap();
+    let id = NEXT_RECEIVER_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 
     let (sender_worker, receiver_main) = glib::MainContext::channel(glib::Priority::DEFAULT);
-    receiver_main.attach(None, clone!(@weak program_data_rc => @default-panic, move |msg| {
-        on_data_received(&program_data_rc, msg);
+    receiver_main.attach(None, clone!(@weak program_data_rc => @default-panic, move |event| {
+        on_receiver_event(&program_data_rc, id, event);
         glib::ControlFlow::Continue
     }));
 
-    let stream2 = stream.try_clone().unwrap();
-    let worker = Some(std::thread::spawn(move || {
-        data_receiver(stream2, rec_output, sender_worker);
-    }));
+    let reference = program_data_rc.borrow().tracker.observer_location.lat_lon.clone();
+    let stream = Arc::new(Mutex::new(None));
+    let (stop_sender, stop_receiver) = mpsc::channel();
+    let stream2 = Arc::clone(&stream);
+    let server_address = source.address.clone();
+    let format = source.format;
+    // not joined; see `stop_all`
+    std::thread::spawn(move || {
+        data_receiver(server_address, format, reference, rec_output, stream2, stop_receiver, sender_worker);
+    });
 
-    program_data_rc.borrow_mut().data_receiver = Some(data::DataReceiver{ server_address, worker, stream });
+    let mut pd = program_data_rc.borrow_mut();
+    pd.data_receivers.push(DataReceiver{
+        id,
+        source,
+        state: ConnectionState::Connecting,
+        stream,
+        stop_sender
+    });
+    show_connection_states(&pd);
 }
 
-/// Returns server address if receiver was running.
-pub fn stop(program_data_rc: &Rc<RefCell<ProgramData>>) -> Option<String> {
+fn get_recording_file_name(source_label: &str, dir: Option<&std::path::Path>) -> std::path::PathBuf {
+    let label: String = source_label.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    let file_name = format!("rec-{}_{}.csv", chrono::Local::now().format("%Y-%m-%d_%H%M%S"), label);
+    match dir {
+        Some(dir) => dir.join(file_name),
+        None => file_name.into()
+    }
+}
+
+/// Starts receivers of the enabled `sources` (recording their messages if recording is on; a source whose
+/// recording file cannot be created is received without recording).
+pub fn start_all(sources: Vec<DataSource>, program_data_rc: &Rc<RefCell<ProgramData>>) {
+    for source in sources.into_iter().filter(|s| s.enabled) {
+        let pd = program_data_rc.borrow();
+        let rec_output = if pd.recording {
+            let path = get_recording_file_name(&source.label, pd.recording_dir.as_deref());
+            match std::fs::File::create(&path) {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    println!("WARNING: Cannot create recording file {}: {}; not recording.", path.to_string_lossy(), e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        drop(pd);
+
+        start(source, rec_output, program_data_rc);
+    }
+}
+
+/// Stops all receivers; returns their sources.
+///
+/// The workers are not waited for (one may be inside a connection attempt, which cannot be interrupted);
+/// each finishes on its own, and events it still sends are ignored.
+pub fn stop_all(program_data_rc: &Rc<RefCell<ProgramData>>) -> Vec<DataSource> {
     let mut pd = program_data_rc.borrow_mut();
-    if let Some(data_receiver) = &mut pd.data_receiver {
-        data_receiver.stream.shutdown(std::net::Shutdown::Both).unwrap();
-        data_receiver.worker.take().unwrap().join().unwrap();
-        let addr = data_receiver.server_address.clone();
-        pd.data_receiver = None;
-        Some(addr)
-    } else {
-        None
+    let receivers = std::mem::take(&mut pd.data_receivers);
+
+    let mut sources = vec![];
+    for receiver in receivers {
+        drop(receiver.stop_sender);
+        if let Some(stream) = receiver.stream.lock().unwrap().as_ref() {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        }
+        sources.push(receiver.source);
     }
+
+    show_connection_states(&pd);
+
+    sources
 }
@@ -1,50 +1,389 @@
-use crate::data::Aircraft;
-use gtk4 as gtk;
-use gtk::glib;
-use pointing_utils::{
-    GeoPos, TargetInfoMessage, to_global, to_global_velocity, to_local_point, to_local_vec, uom::si::velocity
-};
-use std::io::Write;
-
-pub fn start_listener(port: u16, sender_worker: glib::Sender<std::net::TcpStream>) {
+use cgmath::{Deg, InnerSpace, Point3, Rad, Vector3};
+use crate::data::{Aircraft, ModeSTransponderCode, State};
+use crate::geodesy::{LocalFrame, geodetic_to_ecef, horizontal_velocity_ecef};
+use crate::registry::Entry;
+use crate::visibility::Visibility;
+use pointing_utils::{GeoPos, TargetInfoMessage, uom::si::{length, velocity}};
+use std::{error::Error, io::prelude::*, sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}, mpsc}};
+
+/// Max. number of data messages waiting to be written to a client; further data messages are dropped
+/// (responses to commands are always queued).
+const MAX_QUEUED_MESSAGES: usize = 64;
+
+/// Line received from a client.
+pub struct Request {
+    pub client_id: u64,
+    pub line: String
+}
+
+/// Command sent by a client (one per line; keywords are case-insensitive).
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// "LIST"; response: an "AIRCRAFT" line for each aircraft, then "END".
+    List,
+    /// "SELECT ICAO <6 hex digits>"; response: "OK" or "ERROR <reason>".
+    SelectById(ModeSTransponderCode),
+    /// "SELECT CALLSIGN <callsign>"; response: "OK" or "ERROR <reason>".
+    SelectByCallsign(String),
+    /// "DESELECT"; response: "OK".
+    Deselect,
+    /// "OBSERVER"; response: "OBSERVER <lat. (deg)> <lon. (deg)> <elevation (m)>".
+    Observer,
+    /// "SUBSCRIBE ALL"; response: "OK", then an "AIRCRAFT" line after each update of any aircraft.
+    SubscribeAll,
+    /// "UNSUBSCRIBE"; response: "OK".
+    Unsubscribe
+}
+
+impl std::str::FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let keyword = |i: usize| words.get(i).map(|w| w.to_ascii_uppercase());
+
+        match (keyword(0).as_deref(), keyword(1).as_deref(), words.len()) {
+            (Some("LIST"), None, _) => Ok(Command::List),
+            (Some("SELECT"), Some("ICAO"), 3) => Ok(Command::SelectById(words[2].to_ascii_uppercase().parse()?)),
+            (Some("SELECT"), Some("CALLSIGN"), 3) => Ok(Command::SelectByCallsign(words[2].to_ascii_uppercase())),
+            (Some("DESELECT"), None, _) => Ok(Command::Deselect),
+            (Some("OBSERVER"), None, _) => Ok(Command::Observer),
+            (Some("SUBSCRIBE"), Some("ALL"), 2) => Ok(Command::SubscribeAll),
+            (Some("UNSUBSCRIBE"), None, _) => Ok(Command::Unsubscribe),
+            _ => Err(format!("unknown command \"{}\"", s))
+        }
+    }
+}
+
+/// Message queued for writing to a client.
+enum Outgoing {
+    /// Aircraft data; may be dropped if the client is lagging.
+    Data(String),
+    /// Response to a command.
+    Response(String)
+}
+
+/// Data sender client; messages are written by a dedicated thread, so that a slow client cannot stall the caller.
+pub struct Client {
+    /// Unique among all clients connected during program run.
+    pub id: u64,
+    pub address: Option<std::net::SocketAddr>,
+    sender: mpsc::Sender<Outgoing>,
+    /// Number of data messages waiting to be written.
+    queued_data: Arc<AtomicUsize>,
+    /// Set once the connection has been closed or has failed.
+    closed: Arc<AtomicBool>,
+    /// If true, the client receives updates of all aircraft.
+    pub subscribed_all: bool
+}
+
+impl Client {
+    /// Lines received from the client are passed to `requests`, each followed by calling `on_request`.
+    pub fn new(
+        id: u64,
+        stream: std::net::TcpStream,
+        requests: mpsc::Sender<Request>,
+        on_request: Arc<dyn Fn() + Send + Sync>
+    ) -> Client {
+        let address = stream.peer_addr().ok();
+        let closed = Arc::new(AtomicBool::new(false));
+        let queued_data = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = mpsc::channel::<Outgoing>();
+
+        // reads requests until the client closes the connection
+        if let Ok(stream) = stream.try_clone() {
+            let closed = Arc::clone(&closed);
+            std::thread::spawn(move || {
+                for line in std::io::BufReader::new(stream).lines() {
+                    let line = match line {
+                        Ok(line) => line,
+                        Err(_) => break
+                    };
+                    if line.trim().is_empty() { continue; }
+                    if requests.send(Request{ client_id: id, line }).is_err() { break; }
+                    on_request();
+                }
+                closed.store(true, Ordering::Relaxed);
+            });
+        }
+
+        {
+            let closed = Arc::clone(&closed);
+            let queued_data = Arc::clone(&queued_data);
+            let mut stream = stream;
+            std::thread::spawn(move || {
+                // finishes once the client (and so `sender`) is dropped
+                for message in receiver {
+                    let result = match &message {
+                        Outgoing::Data(data) => {
+                            let result = stream.write_all(data.as_bytes());
+                            queued_data.fetch_sub(1, Ordering::Relaxed);
+                            result
+                        },
+                        Outgoing::Response(response) => stream.write_all(response.as_bytes())
+                    };
+                    if result.is_err() { break; }
+                }
+                closed.store(true, Ordering::Relaxed);
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+            });
+        }
+
+        Client{ id, address, sender, queued_data, closed, subscribed_all: false }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Queues data `message` for sending (skips it if the client is lagging); returns false if the client
+    /// has disconnected.
+    pub fn send(&self, message: &str) -> bool {
+        if self.is_closed() { return false; }
+
+        if self.queued_data.load(Ordering::Relaxed) >= MAX_QUEUED_MESSAGES { return true; }
+        self.queued_data.fetch_add(1, Ordering::Relaxed);
+        self.sender.send(Outgoing::Data(message.into())).is_ok()
+    }
+
+    /// Queues `response` to a command for sending (never skipped); returns false if the client has disconnected.
+    pub fn respond(&self, response: &str) -> bool {
+        if self.is_closed() { return false; }
+
+        self.sender.send(Outgoing::Response(response.into())).is_ok()
+    }
+}
+
+/// Accepts client connections on `address`:`port` in a background thread; `on_connected` is called from that thread.
+/// Fails if the port cannot be bound (e.g., is already in use).
+pub fn start_listener(
+    address: &str,
+    port: u16,
+    on_connected: impl Fn(std::net::TcpStream) + Send + 'static
+) -> Result<(), Box<dyn Error>> {
+    let listener = std::net::TcpListener::bind((address, port))
+        .map_err(|e| format!("cannot listen on {}:{}: {}", address, port, e))?;
+
     std::thread::spawn(move || {
-        let listener = std::net::TcpListener::bind(format!("localhost:{}", port)).unwrap();
-        loop {
-            let (stream, _) = listener.accept().unwrap();
-            sender_worker.send(stream).unwrap();
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => on_connected(stream),
+                Err(e) => println!("WARNING: Failed to accept data sender client: {}.", e)
+            }
         }
     });
+
+    Ok(())
+}
+
+/// Returns "AIRCRAFT <ICAO address> <callsign> <lat. (deg)> <lon. (deg)> <altitude (m)> <track (deg)>
+/// <ground speed (m/s)> <selected (0/1)> <visible (0/1)> <registration> <type> <category> <operator>";
+/// unknown values are given as "-", spaces in registry values are replaced with "_".
+/// `visible`: whether the aircraft is above the horizon mask.
+pub fn aircraft_line(aircraft: &Aircraft, visible: Option<bool>) -> String {
+    fn or_dash<T: std::fmt::Display>(value: Option<T>) -> String {
+        value.map_or("-".into(), |v| v.to_string())
+    }
+
+    let lat_lon = aircraft.estimated_lat_lon().or(aircraft.lat_lon.as_ref().map(|ll| &ll.0));
+    let registry_value = |value: fn(&Entry) -> &Option<String>| {
+        or_dash(aircraft.registry_entry.as_ref().and_then(|e| value(e).as_ref()).map(|v| v.replace(' ', "_")))
+    };
+
+    format!(
+        "AIRCRAFT {} {} {} {} {} {} {} {} {} {} {} {} {}\n",
+        aircraft.id,
+        or_dash(aircraft.callsign.as_ref().map(|c| c.trim())),
+        or_dash(lat_lon.map(|ll| format!("{:.6}", ll.lat.0))),
+        or_dash(lat_lon.map(|ll| format!("{:.6}", ll.lon.0))),
+        or_dash(aircraft.altitude.map(|a| format!("{:.0}", a.get::<length::meter>()))),
+        or_dash(aircraft.track.map(|t| format!("{:.1}", t.0))),
+        or_dash(aircraft.ground_speed.map(|v| format!("{:.1}", v.get::<velocity::meter_per_second>()))),
+        if aircraft.state == State::Selected { 1 } else { 0 },
+        or_dash(visible.map(|v| if v { 1 } else { 0 })),
+        registry_value(|e| &e.registration),
+        registry_value(|e| &e.type_designator),
+        registry_value(|e| &e.category),
+        registry_value(|e| &e.operator)
+    )
+}
+
+/// Converts East-North-Up coordinates to the observer's local frame used by `TargetInfoMessage`
+/// (X points north, Y points west, Z points up).
+fn to_message_frame(enu: &Vector3<f64>) -> Vector3<f64> {
+    Vector3{ x: enu.y, y: -enu.x, z: enu.z }
+}
+
+/// Raises `enu` position to the apparent elevation (keeping azimuth and range).
+fn apply_refraction(enu: &Vector3<f64>, visibility: &Visibility) -> Vector3<f64> {
+    let range = enu.magnitude();
+    let horizontal = enu.x.hypot(enu.y);
+    // at the zenith refraction is zero (and azimuth undefined)
+    if horizontal <= f64::EPSILON * range { return *enu; }
+    let elevation = visibility.apparent_elevation(Deg::from(Rad(f64::atan2(enu.z, horizontal))));
+    let scale = range * Rad::from(elevation).0.cos() / horizontal;
+
+    Vector3{ x: enu.x * scale, y: enu.y * scale, z: range * Rad::from(elevation).0.sin() }
 }
 
-pub fn send_data(aircraft: &Aircraft, observer: &GeoPos, streams: &mut [std::net::TcpStream]) {
+/// Sends data of `aircraft` to all `clients` (if enough data is known and the aircraft is visible, i.e., above
+/// the horizon mask); removes the disconnected ones. The position is corrected for refraction (if enabled
+/// in `visibility`).
+pub fn send_data(
+    aircraft: &Aircraft,
+    observer: &GeoPos,
+    visibility: &Visibility,
+    now: std::time::Instant,
+    clients: &mut Vec<Client>
+) {
+    if let Some(message) = target_info(aircraft, observer, visibility, now) {
+        let message = message.to_string();
+        clients.retain(|client| client.send(&message));
+    }
+}
+
+/// Returns `None` if not enough data of `aircraft` is known, or if it is not visible (so that the mount does not
+/// keep slewing to a target it cannot see).
+fn target_info(
+    aircraft: &Aircraft,
+    observer: &GeoPos,
+    visibility: &Visibility,
+    now: std::time::Instant
+) -> Option<TargetInfoMessage> {
+    if aircraft.lat_lon.is_none() || aircraft.altitude.is_none() || aircraft.track.is_none() || aircraft.ground_speed.is_none() {
+        return None;
+    }
+    if !visibility.apparent_position(observer, aircraft, true)?.visible { return None; }
+
     let aircraft_geo_pos = GeoPos{
         lat_lon: match &aircraft.estimated_lat_lon {
             Some(ell) => ell.0.clone(),
             None => aircraft.lat_lon.as_ref().unwrap().0.clone()
         },
-        elevation: *aircraft.altitude.as_ref().unwrap()
+        elevation: aircraft.current_altitude(true).unwrap()
     };
-    let aircraft_pos = to_global(&aircraft_geo_pos);
-    let observer_pos = to_global(observer);
-    let position = to_local_point(&observer_pos, &aircraft_pos);
-    let velocity = to_local_vec(
-        &observer_pos,
-        &to_global_velocity(
+    let frame = LocalFrame::new(observer);
+    let position = to_message_frame(&apply_refraction(
+        &frame.to_enu_point(&geodetic_to_ecef(&aircraft_geo_pos)),
+        visibility
+    ));
+    let estimate = aircraft.track_estimate(now);
+    let velocity = to_message_frame(&frame.to_enu_vec(&match &estimate {
+        Some(estimate) => estimate.velocity_ecef(),
+        None => horizontal_velocity_ecef(
             &aircraft_geo_pos,
             *aircraft.track.as_ref().unwrap(),
-            aircraft.ground_speed.as_ref().unwrap().get::<velocity::meter_per_second>()
-        )
-    );
+            *aircraft.ground_speed.as_ref().unwrap()
+        ) + LocalFrame::new(&aircraft_geo_pos).up
+            * aircraft.vertical_rate.map_or(0.0, |v| v.get::<velocity::meter_per_second>())
+    }));
 
-    let message = TargetInfoMessage{
-        position,
+    Some(TargetInfoMessage{
+        position: Point3{ x: position.x, y: position.y, z: position.z },
         velocity,
-        track: *aircraft.track.as_ref().unwrap(),
+        track: estimate.map_or(*aircraft.track.as_ref().unwrap(), |e| e.track),
         altitude: aircraft_geo_pos.elevation
-    };
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_frame() {
+        use pointing_utils::{LatLon, uom::si::f64};
+
+        let position = |lat: f64, lon: f64, elevation: f64| GeoPos{
+            lat_lon: LatLon{ lat: Deg(lat), lon: Deg(lon) },
+            elevation: f64::Length::new::<length::meter>(elevation)
+        };
+        let observer = position(52.0, 21.0, 100.0);
+        let target = position(52.1, 21.2, 10_000.0);
+
+        let local = to_message_frame(&LocalFrame::new(&observer).to_enu_point(&geodetic_to_ecef(&target)));
+
+        // WGS84 geodetic -> ECEF -> ENU, computed independently (in double precision)
+        let expected = Vector3{ x: 11163.1564, y: -13726.3908, z: 9875.5253 };
+        assert!((local - expected).magnitude() < 0.01, "{:?}", local);
+    }
+
+    #[test]
+    fn refraction() {
+        let visibility = Visibility{ atmosphere: Some(Default::default()), horizon_mask: None };
+
+        let zenith = Vector3{ x: 0.0, y: 0.0, z: 10_000.0 };
+        assert_eq!(zenith, apply_refraction(&zenith, &visibility));
+
+        // raised, keeping azimuth and range
+        let low = Vector3{ x: 30_000.0, y: 40_000.0, z: 1_000.0 };
+        let refracted = apply_refraction(&low, &visibility);
+        assert!(refracted.z > low.z);
+        assert!((refracted.magnitude() - low.magnitude()).abs() < 1.0e-6);
+        assert!((refracted.y / refracted.x - low.y / low.x).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn masked_aircraft_is_not_sent() {
+        use pointing_utils::{LatLon, uom::si::f64};
+
+        let now = std::time::Instant::now();
+        let observer = GeoPos{
+            lat_lon: LatLon{ lat: Deg(52.0), lon: Deg(21.0) },
+            elevation: f64::Length::new::<length::meter>(0.0)
+        };
+        // ca. 20 km north of the observer at 10 km altitude, i.e., at ca. 26° elevation
+        let aircraft = Aircraft{
+            id: ModeSTransponderCode::new(0x48AF01),
+            state: State::Selected,
+            callsign: None,
+            registry_entry: None,
+            lat_lon: Some((LatLon{ lat: Deg(52.18), lon: Deg(21.0) }, now)),
+            estimated_lat_lon: None,
+            track: Some(Deg(90.0)),
+            altitude: Some(f64::Length::new::<length::meter>(10_000.0)),
+            estimated_altitude: None,
+            ground_speed: Some(f64::Velocity::new::<velocity::meter_per_second>(250.0)),
+            vertical_rate: None,
+            squawk: None,
+            alert: false,
+            emergency: false,
+            spi: false,
+            on_ground: None,
+            rssi: None,
+            sources: Default::default(),
+            position_source: None,
+            track_filter: None,
+            trail: Default::default(),
+            t_last_update: now
+        };
+
+        let mut visibility = Visibility{ atmosphere: None, horizon_mask: Some("0 20\n180 20".parse().unwrap()) };
+        assert!(visibility.apparent_position(&observer, &aircraft, true).unwrap().visible);
+        assert!(target_info(&aircraft, &observer, &visibility, now).is_some());
+
+        visibility.horizon_mask = Some("0 40\n180 30".parse().unwrap());
+        assert!(!visibility.apparent_position(&observer, &aircraft, true).unwrap().visible);
+        assert!(target_info(&aircraft, &observer, &visibility, now).is_none());
+    }
+
+    #[test]
+    fn responses_are_not_dropped() {
+        let listener = std::net::TcpListener::bind(("localhost", 0)).unwrap();
+        let stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server_stream, _) = listener.accept().unwrap();
+        let (requests, _) = mpsc::channel();
+        let client = Client::new(1, server_stream, requests, Arc::new(|| ()));
+
+        // more than can be queued before the writer thread catches up
+        for _ in 0..10 * MAX_QUEUED_MESSAGES {
+            assert!(client.send("AIRCRAFT\n"));
+        }
+        assert!(client.respond("END\n"));
+        drop(client);
 
-    // TODO: ignore disconnected
-    for stream in streams {
-        stream.write(message.to_string().as_bytes()).unwrap();
+        let lines: Vec<String> = std::io::BufReader::new(stream).lines().map(|l| l.unwrap()).collect();
+        assert_eq!(Some("END"), lines.last().map(|l| l.as_str()));
+        assert!(lines[..lines.len() - 1].iter().all(|l| l == "AIRCRAFT"));
     }
 }
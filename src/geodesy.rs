@@ -0,0 +1,241 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use cgmath::{Deg, InnerSpace, Point3, Rad, Vector3};
+use pointing_utils::{GeoPos, LatLon, uom};
+use uom::{si::f64, si::length};
+
+/// WGS84 semi-major axis (meters).
+pub const WGS84_A: f64 = 6_378_137.0;
+/// WGS84 flattening.
+pub const WGS84_F: f64 = 1.0 / 298.257_223_563;
+/// WGS84 semi-minor axis (meters).
+pub const WGS84_B: f64 = WGS84_A * (1.0 - WGS84_F);
+/// WGS84 first eccentricity squared.
+const WGS84_E2: f64 = WGS84_F * (2.0 - WGS84_F);
+
+const MAX_VINCENTY_ITERATIONS: usize = 100;
+
+/// Returns Earth-centered, Earth-fixed coordinates (meters): the X axis points to lat. 0°, lon. 0°,
+/// the Z axis to the North Pole. Elevation is treated as height above the WGS84 ellipsoid.
+pub fn geodetic_to_ecef(position: &GeoPos) -> Point3<f64> {
+    let lat = Rad::from(position.lat_lon.lat).0;
+    let lon = Rad::from(position.lat_lon.lon).0;
+    let h = position.elevation.get::<length::meter>();
+
+    // prime vertical radius of curvature
+    let n = WGS84_A / (1.0 - WGS84_E2 * lat.sin().powi(2)).sqrt();
+
+    Point3{
+        x: (n + h) * lat.cos() * lon.cos(),
+        y: (n + h) * lat.cos() * lon.sin(),
+        z: (n * (1.0 - WGS84_E2) + h) * lat.sin()
+    }
+}
+
+/// Inverse of `geodetic_to_ecef`; accurate to well below a millimeter for positions near the Earth's surface.
+pub fn ecef_to_geodetic(position: &Point3<f64>) -> GeoPos {
+    let p = position.x.hypot(position.y);
+    let lon = f64::atan2(position.y, position.x);
+
+    let mut lat = f64::atan2(position.z, p * (1.0 - WGS84_E2));
+    let mut h = 0.0;
+    for _ in 0..5 {
+        let n = WGS84_A / (1.0 - WGS84_E2 * lat.sin().powi(2)).sqrt();
+        h = if lat.cos().abs() > 1.0e-9 {
+            p / lat.cos() - n
+        } else {
+            position.z.abs() - n * (1.0 - WGS84_E2)
+        };
+        lat = f64::atan2(position.z, p * (1.0 - WGS84_E2 * n / (n + h)));
+    }
+
+    GeoPos{
+        lat_lon: LatLon{ lat: Deg::from(Rad(lat)), lon: Deg::from(Rad(lon)) },
+        elevation: f64::Length::new::<length::meter>(h)
+    }
+}
+
+/// East-North-Up frame tangent to the WGS84 ellipsoid at the observer.
+#[derive(Clone, Debug)]
+pub struct LocalFrame {
+    /// Observer's position (ECEF).
+    pub origin: Point3<f64>,
+    pub east: Vector3<f64>,
+    pub north: Vector3<f64>,
+    /// Normal to the ellipsoid.
+    pub up: Vector3<f64>
+}
+
+impl LocalFrame {
+    pub fn new(observer: &GeoPos) -> LocalFrame {
+        let lat = Rad::from(observer.lat_lon.lat).0;
+        let lon = Rad::from(observer.lat_lon.lon).0;
+
+        LocalFrame{
+            origin: geodetic_to_ecef(observer),
+            east: Vector3{ x: -lon.sin(), y: lon.cos(), z: 0.0 },
+            north: Vector3{ x: -lat.sin() * lon.cos(), y: -lat.sin() * lon.sin(), z: lat.cos() },
+            up: Vector3{ x: lat.cos() * lon.cos(), y: lat.cos() * lon.sin(), z: lat.sin() }
+        }
+    }
+
+    /// Converts ECEF position to East-North-Up coordinates (meters).
+    pub fn to_enu_point(&self, position: &Point3<f64>) -> Vector3<f64> {
+        self.to_enu_vec(&(*position - self.origin))
+    }
+
+    /// Converts ECEF vector (e.g., velocity) to East-North-Up components.
+    pub fn to_enu_vec(&self, v: &Vector3<f64>) -> Vector3<f64> {
+        Vector3{ x: v.dot(self.east), y: v.dot(self.north), z: v.dot(self.up) }
+    }
+
+    /// Converts East-North-Up vector to ECEF components.
+    pub fn from_enu_vec(&self, v: &Vector3<f64>) -> Vector3<f64> {
+        v.x * self.east + v.y * self.north + v.z * self.up
+    }
+
+    /// Returns azimuth (measured from North towards East) and elevation of ECEF `position`.
+    pub fn azimuth_elevation(&self, position: &Point3<f64>) -> (Deg<f64>, Deg<f64>) {
+        let enu = self.to_enu_point(position).normalize();
+        let azimuth = Deg::from(Rad(f64::atan2(enu.x, enu.y)));
+        let elevation = Deg::from(Rad(enu.z.asin()));
+
+        (if azimuth.0 < 0.0 { azimuth + Deg(360.0) } else { azimuth }, elevation)
+    }
+}
+
+/// Returns ECEF velocity (m/s) corresponding to horizontal motion with `track` and `ground_speed` at `position`.
+pub fn horizontal_velocity_ecef(position: &GeoPos, track: Deg<f64>, ground_speed: f64::Velocity) -> Vector3<f64> {
+    let speed = ground_speed.get::<uom::si::velocity::meter_per_second>();
+    let track = Rad::from(track).0;
+
+    LocalFrame::new(position).from_enu_vec(&Vector3{ x: speed * track.sin(), y: speed * track.cos(), z: 0.0 })
+}
+
+/// Solves the direct geodesic problem on the WGS84 ellipsoid (Vincenty's formulae): returns the point reached
+/// by traveling `distance` from `start` with initial `azimuth`, and the azimuth at that point.
+pub fn geodesic_direct(start: &LatLon, azimuth: Deg<f64>, distance: f64::Length) -> (LatLon, Deg<f64>) {
+    let s = distance.get::<length::meter>();
+    let alpha1 = Rad::from(azimuth).0;
+    let (sin_alpha1, cos_alpha1) = alpha1.sin_cos();
+
+    let tan_u1 = (1.0 - WGS84_F) * Rad::from(start.lat).0.tan();
+    let cos_u1 = 1.0 / (1.0 + tan_u1 * tan_u1).sqrt();
+    let sin_u1 = tan_u1 * cos_u1;
+
+    let sigma1 = f64::atan2(tan_u1, cos_alpha1);
+    let sin_alpha = cos_u1 * sin_alpha1;
+    let cos2_alpha = 1.0 - sin_alpha * sin_alpha;
+    let u2 = cos2_alpha * (WGS84_A * WGS84_A - WGS84_B * WGS84_B) / (WGS84_B * WGS84_B);
+    let a = 1.0 + u2 / 16384.0 * (4096.0 + u2 * (-768.0 + u2 * (320.0 - 175.0 * u2)));
+    let b = u2 / 1024.0 * (256.0 + u2 * (-128.0 + u2 * (74.0 - 47.0 * u2)));
+
+    let mut sigma = s / (WGS84_B * a);
+    let mut cos_2sigma_m;
+    let mut iteration = 0;
+    loop {
+        cos_2sigma_m = (2.0 * sigma1 + sigma).cos();
+        let (sin_sigma, cos_sigma) = sigma.sin_cos();
+        let delta_sigma = b * sin_sigma * (cos_2sigma_m + b / 4.0 * (
+            cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))
+            - b / 6.0 * cos_2sigma_m * (-3.0 + 4.0 * sin_sigma.powi(2)) * (-3.0 + 4.0 * cos_2sigma_m.powi(2))
+        ));
+        let new_sigma = s / (WGS84_B * a) + delta_sigma;
+        let converged = (new_sigma - sigma).abs() < 1.0e-12;
+        sigma = new_sigma;
+        iteration += 1;
+        if converged || iteration >= MAX_VINCENTY_ITERATIONS { break; }
+    }
+
+    let (sin_sigma, cos_sigma) = sigma.sin_cos();
+    let x = sin_u1 * sin_sigma - cos_u1 * cos_sigma * cos_alpha1;
+    let lat2 = f64::atan2(
+        sin_u1 * cos_sigma + cos_u1 * sin_sigma * cos_alpha1,
+        (1.0 - WGS84_F) * (sin_alpha * sin_alpha + x * x).sqrt()
+    );
+    let lambda = f64::atan2(sin_sigma * sin_alpha1, cos_u1 * cos_sigma - sin_u1 * sin_sigma * cos_alpha1);
+    let c = WGS84_F / 16.0 * cos2_alpha * (4.0 + WGS84_F * (4.0 - 3.0 * cos2_alpha));
+    let l = lambda - (1.0 - c) * WGS84_F * sin_alpha * (
+        sigma + c * sin_sigma * (cos_2sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2)))
+    );
+    let alpha2 = Deg::from(Rad(f64::atan2(sin_alpha, -x)));
+
+    let mut lon2 = start.lon + Deg::from(Rad(l));
+    if lon2 > Deg(180.0) { lon2 -= Deg(360.0); }
+    if lon2 < Deg(-180.0) { lon2 += Deg(360.0); }
+
+    (LatLon{ lat: Deg::from(Rad(lat2)), lon: lon2 }, Deg(alpha2.0.rem_euclid(360.0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deg(degrees: f64, minutes: f64, seconds: f64) -> Deg<f64> {
+        Deg(degrees.signum() * (degrees.abs() + minutes / 60.0 + seconds / 3600.0))
+    }
+
+    fn meters(value: f64) -> f64::Length {
+        f64::Length::new::<length::meter>(value)
+    }
+
+    #[test]
+    fn ecef_round_trip() {
+        for (lat, lon, h) in [(52.0, 21.0, 100.0), (-33.9, 151.2, 11_000.0), (0.0, -180.0, 0.0), (89.999, 45.0, 500.0)] {
+            let position = GeoPos{ lat_lon: LatLon{ lat: Deg(lat), lon: Deg(lon) }, elevation: meters(h) };
+            let converted = ecef_to_geodetic(&geodetic_to_ecef(&position));
+            assert!((converted.lat_lon.lat.0 - lat).abs() < 1.0e-9);
+            assert!((converted.lat_lon.lon.0 - lon).abs() < 1.0e-9 || (converted.lat_lon.lon.0 - lon).abs() > 359.999);
+            assert!((converted.elevation.get::<length::meter>() - h).abs() < 1.0e-3);
+        }
+    }
+
+    #[test]
+    fn ecef_axes() {
+        let p = geodetic_to_ecef(&GeoPos{ lat_lon: LatLon{ lat: Deg(0.0), lon: Deg(90.0) }, elevation: meters(0.0) });
+        assert!(p.x.abs() < 1.0e-6 && (p.y - WGS84_A).abs() < 1.0e-6 && p.z.abs() < 1.0e-6);
+
+        let p = geodetic_to_ecef(&GeoPos{ lat_lon: LatLon{ lat: Deg(90.0), lon: Deg(0.0) }, elevation: meters(0.0) });
+        assert!(p.x.abs() < 1.0e-6 && p.y.abs() < 1.0e-6 && (p.z - WGS84_B).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn geodesic_direct_flinders_peak_to_buninyong() {
+        // example from Vincenty's paper (1975)
+        let start = LatLon{ lat: deg(-37.0, 57.0, 3.72030), lon: deg(144.0, 25.0, 29.52440) };
+        let (end, azimuth) = geodesic_direct(&start, deg(306.0, 52.0, 5.37), meters(54_972.271));
+
+        assert!((end.lat.0 - deg(-37.0, 39.0, 10.15610).0).abs() < 1.0e-8);
+        assert!((end.lon.0 - deg(143.0, 55.0, 35.38390).0).abs() < 1.0e-8);
+        assert!((azimuth.0 - deg(307.0, 10.0, 25.07).0).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn azimuth_elevation() {
+        let observer = GeoPos{ lat_lon: LatLon{ lat: Deg(52.0), lon: Deg(21.0) }, elevation: meters(100.0) };
+        let frame = LocalFrame::new(&observer);
+        let at = |east: f64, north: f64, up: f64| frame.origin + frame.from_enu_vec(&Vector3{ x: east, y: north, z: up });
+
+        for (position, expected_az, expected_el) in [
+            (at(1000.0, 1000.0, 0.0), 45.0, 0.0),
+            (at(0.0, -1000.0, 1000.0), 180.0, 45.0),
+            (at(-1000.0, 0.0, -1000.0), 270.0, -45.0),
+            (at(0.0, 0.0, 1000.0), 0.0, 90.0)
+        ] {
+            let (azimuth, elevation) = frame.azimuth_elevation(&position);
+            // (azimuth is undefined at the zenith; elevation is less accurate close to it)
+            if expected_el < 90.0 { assert!((azimuth.0 - expected_az).abs() < 1.0e-6); }
+            assert!((elevation.0 - expected_el).abs() < 1.0e-4);
+        }
+
+        // a target 1 km above the ellipsoid at the observer's lat./lon. is straight up
+        let above = geodetic_to_ecef(&GeoPos{ lat_lon: observer.lat_lon.clone(), elevation: meters(1100.0) });
+        assert!((frame.azimuth_elevation(&above).1.0 - 90.0).abs() < 1.0e-4);
+    }
+}
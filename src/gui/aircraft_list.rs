@@ -0,0 +1,196 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use crate::program_data::ProgramData;
+use gtk4 as gtk;
+use gtk::glib;
+use gtk::glib::clone;
+use gtk::prelude::*;
+use plane_tracker::{country, data, tracker::Tracker};
+use pointing_utils::uom;
+use std::{cell::{Cell, RefCell}, collections::HashMap, rc::Rc};
+use uom::si::{length, velocity};
+
+/// Columns of the list store; each numeric value is stored twice: as text (shown) and as number (used for sorting).
+mod columns {
+    pub const ICAO: u32 = 0;
+    pub const CALLSIGN: u32 = 1;
+    pub const COUNTRY: u32 = 2;
+    pub const ALTITUDE: u32 = 3;
+    pub const SPEED: u32 = 5;
+    pub const TRACK: u32 = 7;
+    pub const DISTANCE: u32 = 9;
+    pub const AZIMUTH: u32 = 11;
+    pub const ELEVATION: u32 = 13;
+    pub const AGE: u32 = 15;
+
+    /// Numeric value of a column shown as text.
+    pub const fn value(column: u32) -> u32 { column + 1 }
+
+    pub const COUNT: usize = 17;
+}
+
+/// Sorts unknown values before all others.
+const UNKNOWN: f64 = f64::NEG_INFINITY;
+
+/// Table of all tracked aircraft; selecting a row selects the aircraft.
+pub struct AircraftList {
+    scrolled: gtk::ScrolledWindow,
+    view: gtk::TreeView,
+    store: gtk::ListStore,
+    /// Set while the selection is being changed programmatically (and must not be passed to the tracker).
+    updating: Rc<Cell<bool>>
+}
+
+impl AircraftList {
+    pub fn new(program_data_rc: &Rc<RefCell<ProgramData>>) -> AircraftList {
+        let mut types = vec![glib::Type::STRING; columns::COUNT];
+        for column in [
+            columns::ALTITUDE, columns::SPEED, columns::TRACK, columns::DISTANCE, columns::AZIMUTH, columns::ELEVATION,
+            columns::AGE
+        ] {
+            types[columns::value(column) as usize] = glib::Type::F64;
+        }
+        let store = gtk::ListStore::new(&types);
+
+        let view = gtk::TreeView::with_model(&store);
+        for (title, column, numeric) in [
+            ("ICAO", columns::ICAO, false),
+            ("callsign", columns::CALLSIGN, false),
+            ("country", columns::COUNTRY, false),
+            ("alt. (m)", columns::ALTITUDE, true),
+            ("speed (km/h)", columns::SPEED, true),
+            ("track", columns::TRACK, true),
+            ("dist. (km)", columns::DISTANCE, true),
+            ("az.", columns::AZIMUTH, true),
+            ("el.", columns::ELEVATION, true),
+            ("age (s)", columns::AGE, true)
+        ] {
+            let cell = gtk::CellRendererText::new();
+            if numeric { cell.set_xalign(1.0); }
+            let view_column = gtk::TreeViewColumn::new();
+            view_column.set_title(title);
+            view_column.set_resizable(true);
+            view_column.pack_start(&cell, true);
+            view_column.add_attribute(&cell, "text", column as i32);
+            let sort_column = if numeric { columns::value(column) } else { column };
+            view_column.set_sort_column_id(sort_column as i32);
+            view.append_column(&view_column);
+        }
+
+        let updating = Rc::new(Cell::new(false));
+        view.selection().connect_changed(clone!(@weak program_data_rc, @strong updating => @default-panic, move |selection| {
+            if updating.get() { return; }
+            let mut pd = program_data_rc.borrow_mut();
+            match selection.selected() {
+                Some((model, iter)) => match model.get::<String>(&iter, columns::ICAO as i32).parse::<data::ModeSTransponderCode>() {
+                    Ok(id) => { pd.tracker.select(id); },
+                    Err(_) => return
+                },
+                None => pd.tracker.deselect()
+            }
+            pd.process_tracker_events();
+            pd.gui.as_ref().unwrap().queue_draw();
+        }));
+
+        let scrolled = gtk::ScrolledWindow::builder()
+            .child(&view)
+            .hscrollbar_policy(gtk::PolicyType::Automatic)
+            .vscrollbar_policy(gtk::PolicyType::Automatic)
+            .vexpand(true)
+            .build();
+
+        AircraftList{ scrolled, view, store, updating }
+    }
+
+    pub fn widget(&self) -> &gtk::ScrolledWindow {
+        &self.scrolled
+    }
+
+    /// Shows current data of all aircraft in `tracker` and its selected aircraft.
+    pub fn update(&self, tracker: &Tracker, interpolate: bool) {
+        self.updating.set(true);
+
+        let mut rows = HashMap::new();
+        if let Some(iter) = self.store.iter_first() {
+            loop {
+                let icao = self.store.get::<String>(&iter, columns::ICAO as i32);
+                let id = icao.parse::<data::ModeSTransponderCode>().ok().filter(|id| tracker.aircraft.contains_key(id));
+                match id {
+                    Some(id) => {
+                        rows.insert(id, iter.clone());
+                        if !self.store.iter_next(&iter) { break; }
+                    },
+                    // `remove` moves `iter` to the next row
+                    None => if !self.store.remove(&iter) { break; }
+                }
+            }
+        }
+
+        for aircraft in tracker.aircraft.values() {
+            let iter = rows.entry(aircraft.id).or_insert_with(|| self.store.append());
+            self.set_row(iter, aircraft, tracker, interpolate);
+        }
+
+        let selection = self.view.selection();
+        match tracker.selected().and_then(|id| rows.get(&id)) {
+            Some(iter) => if !selection.iter_is_selected(iter) {
+                selection.select_iter(iter);
+                self.view.scroll_to_cell(Some(&self.store.path(iter)), None::<&gtk::TreeViewColumn>, false, 0.0, 0.0);
+            },
+            None => selection.unselect_all()
+        }
+
+        self.updating.set(false);
+    }
+
+    fn set_row(&self, iter: &gtk::TreeIter, aircraft: &data::Aircraft, tracker: &Tracker, interpolate: bool) {
+        fn text(value: Option<f64>, decimals: usize) -> String {
+            value.map_or("".into(), |v| format!("{:.*}", decimals, v))
+        }
+
+        let altitude = aircraft.current_altitude(interpolate).map(|a| a.get::<length::meter>());
+        let speed = aircraft.ground_speed.map(|v| v.get::<velocity::kilometer_per_hour>());
+        let track = aircraft.track.map(|t| t.0);
+        let distance = if aircraft.lat_lon.is_some() && aircraft.altitude.is_some() {
+            Some(data::get_distance(&tracker.observer_location, aircraft, interpolate).get::<length::kilometer>())
+        } else {
+            None
+        };
+        let position = tracker.visibility.apparent_position(&tracker.observer_location, aircraft, interpolate);
+        let azimuth = position.map(|p| p.azimuth.0);
+        let elevation = position.map(|p| p.elevation.0);
+        let age = tracker.clock.now().saturating_duration_since(aircraft.t_last_update).as_secs_f64();
+
+        let country = format!(
+            "{}{}",
+            country::country(aircraft.id).map_or("", |c| c.code),
+            if country::is_military(aircraft.id) { " MIL" } else { "" }
+        );
+
+        self.store.set(iter, &[
+            (columns::ICAO, &aircraft.id.to_string()),
+            (columns::CALLSIGN, &aircraft.callsign.as_deref().unwrap_or("").trim()),
+            (columns::COUNTRY, &country),
+            (columns::ALTITUDE, &text(altitude, 0)),
+            (columns::value(columns::ALTITUDE), &altitude.unwrap_or(UNKNOWN)),
+            (columns::SPEED, &text(speed, 0)),
+            (columns::value(columns::SPEED), &speed.unwrap_or(UNKNOWN)),
+            (columns::TRACK, &text(track, 0)),
+            (columns::value(columns::TRACK), &track.unwrap_or(UNKNOWN)),
+            (columns::DISTANCE, &text(distance, 1)),
+            (columns::value(columns::DISTANCE), &distance.unwrap_or(UNKNOWN)),
+            (columns::AZIMUTH, &text(azimuth, 1)),
+            (columns::value(columns::AZIMUTH), &azimuth.unwrap_or(UNKNOWN)),
+            (columns::ELEVATION, &text(elevation, 1)),
+            (columns::value(columns::ELEVATION), &elevation.unwrap_or(UNKNOWN)),
+            (columns::AGE, &text(Some(age), 0)),
+            (columns::value(columns::AGE), &age)
+        ]);
+    }
+}
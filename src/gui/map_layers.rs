@@ -0,0 +1,161 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use cgmath::{InnerSpace, Point2};
+use crate::gui::{colors, RestoreTransform};
+use gtk4 as gtk;
+use gtk::cairo;
+use plane_tracker::{airports, airports::AirportKind, overlay::{BoundingBox, Layer}};
+use pointing_utils::LatLon;
+
+// all values in pixels
+const LINE_WIDTH: f64 = 1.0;
+const POINT_SIZE: f64 = 3.0;
+const AIRPORT_SIZE: f64 = 8.0;
+const RUNWAY_WIDTH: f64 = 2.0;
+const NAVAID_SIZE: f64 = 8.0;
+const LABEL_FONT_SIZE: f64 = 12.0;
+
+/// Minimum scale (pixels per meter) at which small airports, heliports etc. and navaids are drawn.
+const MIN_DETAIL_SCALE: f64 = 1.0 / 300.0;
+
+/// Configuration of a vector map layer.
+#[derive(Clone, Debug)]
+pub struct LayerSettings {
+    pub path: std::path::PathBuf,
+    pub color: (f64, f64, f64),
+    pub shown: bool
+}
+
+/// Vector layer drawn under the aircraft on the map.
+pub struct MapLayer {
+    pub settings: LayerSettings,
+    layer: Layer
+}
+
+/// Loads (and projects around `observer`) the layers which are shown; reports those which could not be loaded.
+pub fn load(settings: Vec<LayerSettings>, observer: &LatLon) -> Vec<MapLayer> {
+    settings.into_iter().filter(|s| s.shown).filter_map(|settings| {
+        match Layer::load(&settings.path, observer) {
+            Ok(layer) => Some(MapLayer{ settings, layer }),
+            Err(e) => {
+                println!("WARNING: Failed to load map layer from {}: {}.", settings.path.to_string_lossy(), e);
+                None
+            }
+        }
+    }).collect()
+}
+
+/// Current transform of `ctx`: Y points up, observer at (0, 0), global scale (meters); `visible_area`: projected
+/// coordinates of the drawing area.
+pub fn draw(ctx: &cairo::Context, layers: &[MapLayer], visible_area: &BoundingBox, scale: f64) {
+    let _rt = RestoreTransform::new(ctx);
+    ctx.set_line_width(LINE_WIDTH / scale);
+
+    // vertices closer than a pixel to the previous drawn one are skipped
+    let min_step2 = (1.0 / scale).powi(2);
+
+    for map_layer in layers {
+        let (r, g, b) = map_layer.settings.color;
+        ctx.set_source_rgb(r, g, b);
+
+        for path in map_layer.layer.paths.iter().filter(|p| p.bbox.intersects(visible_area)) {
+            let mut last = path.points[0];
+            ctx.move_to(last.x, last.y);
+            for (i, p) in path.points.iter().enumerate().skip(1) {
+                if (*p - last).magnitude2() >= min_step2 || i == path.points.len() - 1 {
+                    ctx.line_to(p.x, p.y);
+                    last = *p;
+                }
+            }
+            if path.closed { ctx.close_path(); }
+        }
+        ctx.stroke().unwrap();
+
+        let point_size = POINT_SIZE / scale;
+        for p in map_layer.layer.points.iter().filter(|p| contains(visible_area, p)) {
+            ctx.rectangle(p.x - point_size / 2.0, p.y - point_size / 2.0, point_size, point_size);
+        }
+        ctx.fill().unwrap();
+    }
+}
+
+/// Draws airports (with runways) and navaids with their identifiers; minor ones only when zoomed in.
+/// Current transform of `ctx`: as in `draw`.
+pub fn draw_airports(
+    ctx: &cairo::Context,
+    database: &airports::Database,
+    visible_area: &BoundingBox,
+    scale: f64,
+    text_scale: f64
+) {
+    let _rt = RestoreTransform::new(ctx);
+    let details = scale >= MIN_DETAIL_SCALE;
+
+    ctx.set_source_rgb(colors::AIRPORT.0, colors::AIRPORT.1, colors::AIRPORT.2);
+    for airport in &database.airports {
+        let major = matches!(airport.kind, AirportKind::Large | AirportKind::Medium);
+        if !(major || details) || !contains(visible_area, &airport.projected) { continue; }
+
+        ctx.set_line_width(RUNWAY_WIDTH / scale);
+        for runway in &airport.runways {
+            if let (Some(low), Some(high)) = (&runway.low_end, &runway.high_end) {
+                ctx.move_to(low.projected.x, low.projected.y);
+                ctx.line_to(high.projected.x, high.projected.y);
+            }
+        }
+        ctx.stroke().unwrap();
+
+        let radius = match airport.kind {
+            AirportKind::Large | AirportKind::Medium | AirportKind::Small => AIRPORT_SIZE / 2.0,
+            _ => AIRPORT_SIZE / 4.0
+        };
+        draw_symbol(ctx, airport.projected, scale, text_scale, &airport.ident, |ctx| {
+            ctx.arc(0.0, 0.0, radius * text_scale, 0.0, 2.0 * std::f64::consts::PI);
+        });
+    }
+
+    if !details { return; }
+
+    ctx.set_source_rgb(colors::NAVAID.0, colors::NAVAID.1, colors::NAVAID.2);
+    for navaid in database.navaids.iter().filter(|n| contains(visible_area, &n.projected)) {
+        draw_symbol(ctx, navaid.projected, scale, text_scale, &navaid.ident, |ctx| {
+            let r = NAVAID_SIZE / 2.0 * text_scale;
+            ctx.move_to(0.0, -r);
+            ctx.line_to(r, 0.0);
+            ctx.line_to(0.0, r);
+            ctx.line_to(-r, 0.0);
+            ctx.close_path();
+        });
+    }
+}
+
+/// Strokes the path created by `symbol` (in pixels, centered at (0, 0)) and draws `label` next to it.
+fn draw_symbol<F: Fn(&cairo::Context)>(
+    ctx: &cairo::Context,
+    position: Point2<f64>,
+    scale: f64,
+    text_scale: f64,
+    label: &str,
+    symbol: F
+) {
+    let _rt = RestoreTransform::new(ctx);
+    ctx.translate(position.x, position.y);
+    ctx.scale(1.0 / scale, -1.0 / scale);
+    ctx.set_line_width(LINE_WIDTH);
+    symbol(ctx);
+    ctx.stroke().unwrap();
+
+    ctx.set_font_size(LABEL_FONT_SIZE * text_scale);
+    ctx.move_to(AIRPORT_SIZE * text_scale, -AIRPORT_SIZE / 2.0 * text_scale);
+    ctx.show_text(label).unwrap();
+}
+
+fn contains(bbox: &BoundingBox, p: &Point2<f64>) -> bool {
+    p.x >= bbox.min.x && p.x <= bbox.max.x && p.y >= bbox.min.y && p.y <= bbox.max.y
+}
@@ -7,12 +7,19 @@
 //
 
 use cgmath::{Deg, InnerSpace, Point2, Rad};
-use crate::{data, data::ProgramData, data_receiver};
+mod aircraft_list;
+pub mod map_layers;
+mod playback_controls;
+mod sky_view;
+mod sources_dialog;
+
+use crate::{data_receiver, data_receiver::DataSource, playback, program_data::ProgramData};
 use gtk4 as gtk;
 use gtk::cairo;
 use gtk::glib;
 use gtk::glib::clone;
 use gtk::prelude::*;
+use plane_tracker::{airports, auto_select, clock, country, data, overlay::BoundingBox, tracker};
 use pointing_utils::{GeoPos, uom};
 use std::{cell::RefCell, rc::Rc};
 use uom::{si::f64, si::{length, velocity}};
@@ -21,12 +28,21 @@ const SPACING: i32 = 10; // control spacing in pixels
 const PADDING: i32 = 10; //TODO: depend on DPI (or does it already?)
 
 const ZOOM_FACTOR: f64 = 1.2;
+/// Maximal pointer movement (pixels) between press and release which is treated as a click rather than a drag.
+const CLICK_TOLERANCE: f64 = 4.0;
 mod colors {
     pub const ACTIVE: (f64, f64, f64) = (0.0, 0.6, 0.0);
     pub const INACTIVE: (f64, f64, f64) = (0.6, 0.0, 0.0);
     pub const SELECTED: (f64, f64, f64) = (1.0, 1.0, 1.0);
+    pub const EMERGENCY: (f64, f64, f64) = (1.0, 0.5, 0.0);
+    /// Aircraft below the horizon mask.
+    pub const HIDDEN: (f64, f64, f64) = (0.35, 0.35, 0.35);
+    pub const AIRPORT: (f64, f64, f64) = (0.3, 0.5, 0.9);
+    pub const NAVAID: (f64, f64, f64) = (0.6, 0.4, 0.8);
 }
 const INACTIVE_DELAY: std::time::Duration = std::time::Duration::from_secs(10);
+/// Vertical rate (m/s) above which an aircraft is shown as climbing or descending.
+const MIN_VERTICAL_RATE: f64 = 1.0;
 
 #[derive(Copy, Clone, PartialEq, PartialOrd)]
 pub enum AircraftInfoLevel {
@@ -46,16 +62,47 @@ impl AircraftInfoLevel {
 }
 
 pub struct StatusBarFields {
-    server_address: gtk::Label,
+    pub connection_state: gtk::Label,
     pub num_aircraft: gtk::Label,
-    pub max_distance: gtk::Label
+    pub max_distance: gtk::Label,
+    /// Number of connected data sender clients.
+    pub data_sender_clients: gtk::Label,
+    /// Last predicted transit.
+    pub transit: gtk::Label
 }
 
 pub struct GuiData {
     pub drawing_area: gtk::DrawingArea,
     pub plot_range: f64::Length, // corresponds to draw area width
+    /// Projected position (see `data::project`) shown at the map's center, unless following the selected aircraft.
+    pub view_center: Point2<f64>,
+    /// Value of `map_center` when dragging started.
+    drag_start_center: Option<Point2<f64>>,
+    /// If active, the map is centered on the selected aircraft.
+    pub follow_selected: gtk::ToggleButton,
+    /// Vector layers (coastlines, borders etc.) projected around the observer.
+    pub map_layers: Vec<map_layers::MapLayer>,
+    pub airports: Option<airports::Database>,
     pub status_bar_fields: StatusBarFields,
-    pub info_level: AircraftInfoLevel
+    pub info_level: AircraftInfoLevel,
+    pub playback_controls: playback_controls::PlaybackControls,
+    pub sky_view: sky_view::SkyView,
+    pub aircraft_list: aircraft_list::AircraftList
+}
+
+impl GuiData {
+    /// Redraws the map and the sky view.
+    pub fn queue_draw(&self) {
+        self.drawing_area.queue_draw();
+        self.sky_view.queue_draw();
+    }
+
+    /// Refreshes the aircraft list (if shown).
+    pub fn update_aircraft_list(&self, tracker: &tracker::Tracker, interpolate: bool) {
+        if self.aircraft_list.widget().is_visible() {
+            self.aircraft_list.update(tracker, interpolate);
+        }
+    }
 }
 
 struct RestoreTransform<'a> {
@@ -95,6 +142,24 @@ fn choose_closest(value: f64, sorted_values: &[f64]) -> f64 {
     }
 }
 
+/// Returns projected position of the selected aircraft, if any.
+fn selected_position(pd: &ProgramData) -> Option<Point2<f64>> {
+    let aircraft = pd.tracker.selected().and_then(|id| pd.tracker.aircraft.get(&id))?;
+    let lat_lon = aircraft.estimated_lat_lon().or(aircraft.lat_lon.as_ref().map(|ll| &ll.0))?;
+
+    Some(data::project(&pd.tracker.observer_location.lat_lon, lat_lon))
+}
+
+/// Returns projected position (see `data::project`) shown at the map's center.
+fn map_center(pd: &ProgramData) -> Point2<f64> {
+    let gui = pd.gui.as_ref().unwrap();
+    if gui.follow_selected.is_active() {
+        if let Some(position) = selected_position(pd) { return position; }
+    }
+
+    gui.view_center
+}
+
 /// Current transform of `ctx`: Y points up, observer at (0, 0), global scale (meters).
 fn draw_range_circles(ctx: &cairo::Context, scale: f64, width: i32, height: i32, program_data_rc: &Rc<RefCell<ProgramData>>) {
     let pd = program_data_rc.borrow();
@@ -129,15 +194,17 @@ fn draw_range_circles(ctx: &cairo::Context, scale: f64, width: i32, height: i32,
 
     let radius_step = kilometers(choose_closest(desired_radius_step.get::<length::kilometer>(), &ALLOWED_R_STEPS_KM));
 
-    let mut radius = radius_step;
-    let max_radius = if width > height {
-        gui.plot_range
-    } else if width > 0 {
-        gui.plot_range * height as f64 / width as f64
-    } else {
-        meters(0.0)
-    };
+    // half-extents of the visible area
+    let half_width = gui.plot_range.get::<length::meter>();
+    let half_height = if width > 0 { half_width * height as f64 / width as f64 } else { 0.0 };
+    // only the circles intersecting the visible area are drawn
+    let center = map_center(&pd);
+    let min_radius = meters(
+        (center.x.abs() - half_width).max(0.0).hypot((center.y.abs() - half_height).max(0.0))
+    );
+    let max_radius = meters((center.x.abs() + half_width).hypot(center.y.abs() + half_height));
 
+    let mut radius = radius_step * (min_radius.get::<length::meter>() / radius_step.get::<length::meter>()).floor().max(1.0);
     while radius < max_radius {
         ctx.arc(
             0.0, 0.0,
@@ -199,14 +266,16 @@ fn draw_aircraft_icon(ctx: &cairo::Context, track: Deg<f64>, text_scale: f64) {
     ctx.stroke().unwrap();
 }
 
-/// Current transform of `ctx`: Y points down, aircraft at (0, 0), pixel scale.
+/// Current transform of `ctx`: Y points down, aircraft at (0, 0), pixel scale. `now`: current time of the tracker.
 fn draw_aircraft_info(
     ctx: &cairo::Context,
     aircraft: &data::Aircraft,
     observer: &GeoPos,
     interpolate: bool,
+    now: std::time::Instant,
     text_scale: f64,
-    mut level: AircraftInfoLevel
+    mut level: AircraftInfoLevel,
+    airports: Option<&airports::Database>
 ) {
     let _rt = RestoreTransform::new(ctx);
 
@@ -229,11 +298,37 @@ fn draw_aircraft_info(
     info_line_idx += 1;
 
     ctx.move_to(h_offs, info_line_idx as f64 * l_spc);
-    if let Some(altitude) = &aircraft.altitude {
-        ctx.show_text(&format!("{:.0} m", altitude.get::<length::meter>())).unwrap();
+    if aircraft.on_ground == Some(true) {
+        ctx.show_text("GND").unwrap();
+    } else if let Some(altitude) = &aircraft.current_altitude(interpolate) {
+        let vertical_rate = aircraft.vertical_rate.map(|v| v.get::<velocity::meter_per_second>());
+        let arrow = match vertical_rate {
+            Some(v) if v >= MIN_VERTICAL_RATE => " ↑",
+            Some(v) if v <= -MIN_VERTICAL_RATE => " ↓",
+            _ => ""
+        };
+        let rate = match vertical_rate {
+            Some(v) if level >= AircraftInfoLevel::All && !arrow.is_empty() => format!(" {:.1} m/s", v.abs()),
+            _ => String::new()
+        };
+        ctx.show_text(&format!("{:.0} m{}{}", altitude.get::<length::meter>(), arrow, rate)).unwrap();
     }
     info_line_idx += 1;
 
+    if level >= AircraftInfoLevel::Medium || aircraft.in_emergency() {
+        ctx.move_to(h_offs, info_line_idx as f64 * l_spc);
+        if let Some(squawk) = &aircraft.squawk {
+            ctx.show_text(&format!(
+                "{}{}{}{}",
+                squawk,
+                if aircraft.alert { " ALRT" } else { "" },
+                if aircraft.emergency { " EMRG" } else { "" },
+                if aircraft.spi { " IDNT" } else { "" }
+            )).unwrap();
+        }
+        info_line_idx += 1;
+    }
+
     if level >= AircraftInfoLevel::Medium {
         ctx.move_to(h_offs, info_line_idx as f64 * l_spc);
         if let Some(ground_speed) = &aircraft.ground_speed {
@@ -242,6 +337,53 @@ fn draw_aircraft_info(
         info_line_idx += 1;
     }
 
+    let lat_lon = if interpolate { aircraft.estimated_lat_lon() } else { None }
+        .or(aircraft.lat_lon.as_ref().map(|ll| &ll.0));
+    let projected = lat_lon.map(|ll| data::project(&observer.lat_lon, ll));
+
+    if level >= AircraftInfoLevel::Medium && aircraft.on_ground != Some(true) {
+        let approach = match (airports, lat_lon, projected, aircraft.current_altitude(interpolate), aircraft.track) {
+            (Some(airports), Some(lat_lon), Some(projected), Some(altitude), Some(track)) =>
+                airports.approach(lat_lon, projected, altitude, track, aircraft.vertical_rate),
+            _ => None
+        };
+        if let Some(approach) = approach {
+            ctx.move_to(h_offs, info_line_idx as f64 * l_spc);
+            ctx.show_text(&format!(
+                "→ {}{} {:.0} km",
+                approach.airport.ident,
+                approach.runway.map(|r| format!(" {}", r)).unwrap_or_default(),
+                approach.distance.get::<length::kilometer>()
+            )).unwrap();
+            info_line_idx += 1;
+        }
+    }
+
+    if level >= AircraftInfoLevel::All {
+        let military = country::is_military(aircraft.id);
+        if let Some(country) = country::country(aircraft.id) {
+            ctx.move_to(h_offs, info_line_idx as f64 * l_spc);
+            ctx.show_text(&format!("{}{}", country.name, if military { " MIL" } else { "" })).unwrap();
+            info_line_idx += 1;
+        }
+    }
+
+    if level >= AircraftInfoLevel::All {
+        if let Some(entry) = &aircraft.registry_entry {
+            let registration_and_type = [&entry.registration, &entry.type_designator, &entry.category]
+                .iter()
+                .filter_map(|v| v.as_deref())
+                .collect::<Vec<&str>>()
+                .join(" ");
+            for line in [Some(registration_and_type.as_str()), entry.operator.as_deref()].into_iter().flatten() {
+                if line.is_empty() { continue; }
+                ctx.move_to(h_offs, info_line_idx as f64 * l_spc);
+                ctx.show_text(line).unwrap();
+                info_line_idx += 1;
+            }
+        }
+    }
+
     if level >= AircraftInfoLevel::All {
         ctx.move_to(h_offs, info_line_idx as f64 * l_spc);
         if aircraft.altitude.is_some() && aircraft.lat_lon.is_some() {
@@ -259,11 +401,58 @@ fn draw_aircraft_info(
         info_line_idx += 1;
     }
 
+    if level >= AircraftInfoLevel::All {
+        if let Some(estimate) = aircraft.track_estimate(now) {
+            ctx.move_to(h_offs, info_line_idx as f64 * l_spc);
+            ctx.show_text(&format!("±{:.0} m", estimate.position_sigma.get::<length::meter>())).unwrap();
+            info_line_idx += 1;
+        }
+    }
+
+    if level >= AircraftInfoLevel::All {
+        ctx.move_to(h_offs, info_line_idx as f64 * l_spc);
+        ctx.show_text(&format!("{:.1} s", now.saturating_duration_since(aircraft.t_last_update).as_secs_f64())).unwrap();
+        info_line_idx += 1;
+    }
+
     if level >= AircraftInfoLevel::All {
         ctx.move_to(h_offs, info_line_idx as f64 * l_spc);
-        ctx.show_text(&format!("{:.1} s", aircraft.t_last_update.elapsed().as_secs_f64())).unwrap();
+        let mut sources: Vec<&str> = aircraft.sources.keys().map(|s| s.as_str()).collect();
+        sources.sort();
+        ctx.show_text(&sources.join(", ")).unwrap();
         info_line_idx += 1;
     }
+
+    if level >= AircraftInfoLevel::All {
+        ctx.move_to(h_offs, info_line_idx as f64 * l_spc);
+        if let Some(rssi) = aircraft.rssi {
+            ctx.show_text(&format!("{:.1} dBFS", rssi)).unwrap();
+        }
+        info_line_idx += 1;
+    }
+
+    if aircraft.state == data::State::Selected {
+        if let Some((airport, distance)) = airports.zip(projected).and_then(|(a, p)| a.nearest_airport(p)) {
+            ctx.move_to(h_offs, info_line_idx as f64 * l_spc);
+            ctx.show_text(&format!("near {} {:.1} km", airport.ident, distance.get::<length::kilometer>())).unwrap();
+            info_line_idx += 1;
+        }
+    }
+}
+
+/// `visible`: whether the aircraft is above the horizon mask; `now`: current time of the tracker.
+fn aircraft_color(aircraft: &data::Aircraft, visible: bool, now: std::time::Instant) -> (f64, f64, f64) {
+    if aircraft.state == data::State::Selected {
+        colors::SELECTED
+    } else if aircraft.in_emergency() {
+        colors::EMERGENCY
+    } else if !visible {
+        colors::HIDDEN
+    } else if now.saturating_duration_since(aircraft.t_last_update) > INACTIVE_DELAY {
+        colors::INACTIVE
+    } else {
+        colors::ACTIVE
+    }
 }
 
 fn draw_single_aircraft(ctx: &cairo::Context, aircraft: &data::Aircraft, scale: f64, text_scale: f64, pd: &ProgramData) {
@@ -272,15 +461,19 @@ fn draw_single_aircraft(ctx: &cairo::Context, aircraft: &data::Aircraft, scale:
 
     let track = if let Some(track) = aircraft.track { track } else { return; };
 
-    let projected_pos = data::project(&pd.observer_location.lat_lon, lat_lon);
+    let projected_pos = data::project(&pd.tracker.observer_location.lat_lon, lat_lon);
 
     let projected_displayed_pos = data::project(
-        &pd.observer_location.lat_lon,
+        &pd.tracker.observer_location.lat_lon,
         if est_lat_lon.is_some() { est_lat_lon.unwrap() } else { lat_lon }
     );
 
     let interpolate = pd.config.interpolate_positions().unwrap_or(true);
 
+    if pd.config.trails_shown().unwrap_or(false) {
+        draw_trail(ctx, aircraft, scale, pd);
+    }
+
     if interpolate {
         let _rt = RestoreTransform::new(ctx);
         ctx.set_line_width(1.0 / scale);
@@ -293,21 +486,70 @@ fn draw_single_aircraft(ctx: &cairo::Context, aircraft: &data::Aircraft, scale:
     let _rt = RestoreTransform::new(ctx);
     ctx.translate(projected_displayed_pos.x, projected_displayed_pos.y);
     ctx.scale(1.0 / scale, 1.0 / scale);
-    let color = if aircraft.state == data::State::Selected {
-        colors::SELECTED
-    } else if aircraft.t_last_update.elapsed() > INACTIVE_DELAY {
-        colors::INACTIVE
-    } else {
-        colors::ACTIVE
-    };
+    let now = pd.tracker.clock.now();
+    let color = aircraft_color(aircraft, pd.tracker.is_visible(aircraft).unwrap_or(true), now);
     ctx.set_source_rgb(color.0, color.1, color.2);
     draw_aircraft_icon(ctx, track, text_scale);
     ctx.scale(1.0, -1.0);
+    let gui = pd.gui.as_ref().unwrap();
     draw_aircraft_info(
-        ctx, aircraft, &pd.observer_location, interpolate, text_scale, pd.gui.as_ref().unwrap().info_level
+        ctx,
+        aircraft,
+        &pd.tracker.observer_location,
+        interpolate,
+        now,
+        text_scale,
+        gui.info_level,
+        gui.airports.as_ref()
     );
 }
 
+/// Returns color corresponding to `altitude`: from red (ground) through yellow, green and cyan to blue (12 km and above).
+fn altitude_color(altitude: f64::Length) -> (f64, f64, f64) {
+    const MAX_ALTITUDE: f64 = 12_000.0; // meters
+
+    let hue = 4.0 * (altitude.get::<length::meter>() / MAX_ALTITUDE).clamp(0.0, 1.0); // in units of 60°
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        _ => (0.0, x, 1.0)
+    }
+}
+
+/// Draws the aircraft's recent positions as a polyline fading with age.
+fn draw_trail(ctx: &cairo::Context, aircraft: &data::Aircraft, scale: f64, pd: &ProgramData) {
+    const LINE_WIDTH: f64 = 2.0; // pixels
+    const MIN_ALPHA: f64 = 0.1;
+
+    let _rt = RestoreTransform::new(ctx);
+    ctx.set_line_width(LINE_WIDTH / scale);
+    ctx.set_line_cap(cairo::LineCap::Round);
+
+    let observer = &pd.tracker.observer_location.lat_lon;
+    let max_age = pd.tracker.trail_limits.max_age.as_secs_f64();
+    let altitude_colors = pd.config.trail_altitude_colors().unwrap_or(false);
+    let now = pd.tracker.clock.now();
+    let default_color = aircraft_color(aircraft, pd.tracker.is_visible(aircraft).unwrap_or(true), now);
+
+    for (p0, p1) in aircraft.trail.iter().zip(aircraft.trail.iter().skip(1)) {
+        let age = now.saturating_duration_since(p1.t).as_secs_f64();
+        let alpha = if max_age > 0.0 { (1.0 - age / max_age).max(MIN_ALPHA) } else { 1.0 };
+        let color = match (altitude_colors, p1.altitude) {
+            (true, Some(altitude)) => altitude_color(altitude),
+            _ => default_color
+        };
+
+        let start = data::project(observer, &p0.lat_lon);
+        let end = data::project(observer, &p1.lat_lon);
+        ctx.set_source_rgba(color.0, color.1, color.2, alpha);
+        ctx.move_to(start.x, start.y);
+        ctx.line_to(end.x, end.y);
+        ctx.stroke().unwrap();
+    }
+}
+
 /// Current transform of `ctx`: Y points up, observer at (0, 0), global scale (meters).
 fn draw_all_aircraft(ctx: &cairo::Context, width: i32, height: i32, program_data_rc: &Rc<RefCell<ProgramData>>) {
     let pd = program_data_rc.borrow();
@@ -317,7 +559,7 @@ fn draw_all_aircraft(ctx: &cairo::Context, width: i32, height: i32, program_data
 
     let mut selected: Option<data::ModeSTransponderCode> = None;
 
-    for aircraft in pd.aircraft.values() {
+    for aircraft in pd.tracker.aircraft.values() {
         if aircraft.state == data::State::Selected {
             selected = Some(aircraft.id);
             continue; // the selected aircraft will be drawn as last
@@ -327,79 +569,150 @@ fn draw_all_aircraft(ctx: &cairo::Context, width: i32, height: i32, program_data
     }
 
     if let Some(id) = selected {
-        draw_single_aircraft(ctx, pd.aircraft.get(&id).unwrap(), scale, text_scale, &pd);
+        draw_single_aircraft(ctx, pd.tracker.aircraft.get(&id).unwrap(), scale, text_scale, &pd);
     }
 }
 
 fn on_draw_main_view(ctx: &cairo::Context, width: i32, height: i32, program_data_rc: &Rc<RefCell<ProgramData>>) {
     let scale = width as f64 / 2.0 / program_data_rc.borrow().gui.as_ref().unwrap().plot_range.get::<length::meter>();
+    let center = map_center(&program_data_rc.borrow());
     ctx.translate(width as f64 / 2.0, height as f64 / 2.0);
     ctx.scale(scale, -scale);
+    ctx.translate(-center.x, -center.y);
 
+    {
+        let pd = program_data_rc.borrow();
+        let gui = pd.gui.as_ref().unwrap();
+        let half_w = gui.plot_range.get::<length::meter>();
+        let half_h = half_w * height as f64 / width as f64;
+        let visible_area = BoundingBox{
+            min: Point2{ x: center.x - half_w, y: center.y - half_h },
+            max: Point2{ x: center.x + half_w, y: center.y + half_h }
+        };
+        map_layers::draw(ctx, &gui.map_layers, &visible_area, scale);
+        if pd.config.airports_shown().unwrap_or(true) {
+            if let Some(airports) = &gui.airports {
+                map_layers::draw_airports(ctx, airports, &visible_area, scale, pd.config.text_scale().unwrap_or(1.0));
+            }
+        }
+    }
     draw_range_circles(ctx, scale, width, height, program_data_rc);
     draw_all_aircraft(ctx, width, height, program_data_rc);
 }
 
-fn on_connect(server_address: String, program_data_rc: &Rc<RefCell<ProgramData>>) {
-    data_receiver::stop(program_data_rc);
+/// Shows changes of the tracker state in the status bar; `clock`: the tracker's clock.
+pub fn on_tracker_event(gui: &GuiData, event: &tracker::Event, clock: &clock::Clock) {
+    match event {
+        tracker::Event::StatisticsChanged{ num_aircraft, max_num_aircraft, max_distance } => {
+            gui.status_bar_fields.num_aircraft.set_text(
+                &format!("Aircraft: {} (max: {})", num_aircraft, max_num_aircraft)
+            );
+
+            if let Some(d) = max_distance {
+                gui.status_bar_fields.max_distance.set_text(
+                    &format!("Max distance: {:.1} km", d.get::<length::kilometer>())
+                );
+            }
+        },
 
-    data_receiver::start(
-        server_address.clone(),
-        if program_data_rc.borrow().recording {
-            Some(std::fs::File::create(get_recording_file_name()).unwrap())
-        } else {
-            None
+        tracker::Event::DataSendersChanged{ num_clients } => {
+            gui.status_bar_fields.data_sender_clients.set_text(&format!("Clients: {}", num_clients));
         },
-        program_data_rc
-    );
+
+        tracker::Event::TransitPredicted(prediction) => {
+            gui.status_bar_fields.transit.set_text(&format!(
+                "{} {}: {} at {}",
+                if prediction.is_transit() { "Transit of" } else { "Near" },
+                prediction.body,
+                prediction.aircraft,
+                (clock.utc().with_timezone(&chrono::Local) + chrono::Duration::from_std(prediction.time_to_event).unwrap())
+                    .format("%H:%M:%S")
+            ));
+        },
+
+        tracker::Event::AircraftUpdated(_) | tracker::Event::AircraftRemoved(_) => ()
+    }
+}
+
+fn on_connect(sources: Vec<DataSource>, program_data_rc: &Rc<RefCell<ProgramData>>) {
+    data_receiver::stop_all(program_data_rc);
+    playback::stop(program_data_rc);
+
+    data_receiver::start_all(sources, program_data_rc);
 
     let pd = program_data_rc.borrow_mut();
     let gui = pd.gui.as_ref().unwrap();
-    gui.status_bar_fields.server_address.set_text(&format!("Connected to {}", server_address));
     gui.status_bar_fields.num_aircraft.set_text("Aircraft: 0 (max: 0)");
 }
 
 fn on_connect_btn(main_wnd: &gtk::ApplicationWindow, program_data_rc: &Rc<RefCell<ProgramData>>) {
-    let dialog = gtk::MessageDialog::new(
-        Some(main_wnd),
-        gtk::DialogFlags::MODAL,
-        gtk::MessageType::Question,
-        gtk::ButtonsType::OkCancel,
-        "Server address:"
-    );
-    dialog.set_title(Some("Connect to SBS server"));
-    let server_address = gtk::Text::new();
-    server_address.set_text(&program_data_rc.borrow().config.server_address().unwrap_or("".into()));
-    set_all_margins(&dialog.content_area(), PADDING);
-    dialog.content_area().append(&server_address);
-    dialog.connect_response(clone!(@weak server_address, @weak program_data_rc => @default-panic, move |dlg, response| {
-        if response == gtk::ResponseType::Ok {
-            program_data_rc.borrow().config.set_server_address(server_address.text().as_str());
-            on_connect(server_address.text().into(), &program_data_rc);
-        }
-        dlg.close();
+    sources_dialog::show(main_wnd, program_data_rc, clone!(@weak program_data_rc => @default-panic, move |sources| {
+        on_connect(sources, &program_data_rc);
     }));
-    dialog.show();
 }
 
-
-
 fn on_disconnect(program_data_rc: &Rc<RefCell<ProgramData>>) {
-    data_receiver::stop(program_data_rc);
+    data_receiver::stop_all(program_data_rc);
+    playback::stop(program_data_rc);
 
     let mut pd = program_data_rc.borrow_mut();
-    pd.aircraft.clear();
-    pd.max_num_aircraft = 0;
+    pd.tracker.clear();
+    pd.process_tracker_events();
+}
 
-    let gui = pd.gui.as_ref().unwrap();
-    gui.status_bar_fields.server_address.set_text("");
-    gui.status_bar_fields.num_aircraft.set_text("Aircraft: 0 (max: 0)");
+fn on_play_file(path: &std::path::Path, main_wnd: &gtk::ApplicationWindow, program_data_rc: &Rc<RefCell<ProgramData>>) {
+    data_receiver::stop_all(program_data_rc);
+
+    {
+        let mut pd = program_data_rc.borrow_mut();
+        pd.tracker.clear();
+        pd.process_tracker_events();
+    }
+
+    if let Err(e) = playback::start(path, program_data_rc) {
+        let error_dlg = gtk::MessageDialog::new(
+            Some(main_wnd),
+            gtk::DialogFlags::MODAL,
+            gtk::MessageType::Error,
+            gtk::ButtonsType::Close,
+            &format!("Cannot play {}: {}", path.display(), e)
+        );
+        error_dlg.connect_response(|d, _| d.close());
+        error_dlg.show();
+    }
+}
+
+fn on_play_btn(main_wnd: &gtk::ApplicationWindow, program_data_rc: &Rc<RefCell<ProgramData>>) {
+    let dialog = gtk::FileChooserDialog::new(
+        Some("Open recording"),
+        Some(main_wnd),
+        gtk::FileChooserAction::Open,
+        &[("Open", gtk::ResponseType::Accept), ("Cancel", gtk::ResponseType::Cancel)]
+    );
+    dialog.set_modal(true);
+
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some("Recordings (rec-*.csv)"));
+    filter.add_pattern("rec-*.csv");
+    dialog.add_filter(&filter);
+
+    dialog.connect_response(clone!(@weak main_wnd, @weak program_data_rc => @default-panic, move |dlg, response| {
+        if response == gtk::ResponseType::Accept {
+            if let Some(path) = dlg.file().and_then(|f| f.path()) {
+                on_play_file(&path, &main_wnd, &program_data_rc);
+            }
+        }
+        dlg.close();
+    }));
+
+    dialog.show();
 }
 
+/// Returns the toolbar and the "follow selected aircraft" button.
 fn create_toolbar(
     main_wnd: &gtk::ApplicationWindow,
     program_data_rc: &Rc<RefCell<ProgramData>>
-) -> gtk::Box {
+) -> (gtk::Box, gtk::ToggleButton) {
 
     let toolbar = gtk::Box::new(gtk::Orientation::Vertical, SPACING);
     toolbar.add_css_class("toolbar");
@@ -416,13 +729,21 @@ fn create_toolbar(
     }));
     toolbar.append(&disconnect);
 
+    let play = gtk::Button::builder().label("play").tooltip_text("Play back a recording").build();
+    play.connect_clicked(clone!(@weak main_wnd, @weak program_data_rc => @default-panic, move |_| {
+        on_play_btn(&main_wnd, &program_data_rc);
+    }));
+    toolbar.append(&play);
+
     let filter = gtk::CheckButton::builder()
         .label("filter")
         .tooltip_text("Filter out-of-order location messages")
         .active(program_data_rc.borrow().config.filter_ooo_messages().unwrap_or(true))
         .build();
     filter.connect_toggled(clone!(@weak program_data_rc => @default-panic, move |checkbox| {
-        program_data_rc.borrow().config.set_filter_ooo_messages(checkbox.is_active());
+        let mut pd = program_data_rc.borrow_mut();
+        pd.config.set_filter_ooo_messages(checkbox.is_active());
+        pd.tracker.filter_ooo_messages = checkbox.is_active();
     }));
     toolbar.append(&filter);
 
@@ -436,6 +757,56 @@ fn create_toolbar(
     }));
     toolbar.append(&interpolate);
 
+    let trails = gtk::CheckButton::builder()
+        .label("trails")
+        .tooltip_text("Show recent flight paths")
+        .active(program_data_rc.borrow().config.trails_shown().unwrap_or(false))
+        .build();
+    trails.connect_toggled(clone!(@weak program_data_rc => @default-panic, move |checkbox| {
+        let pd = program_data_rc.borrow();
+        pd.config.set_trails_shown(checkbox.is_active());
+        pd.gui.as_ref().unwrap().queue_draw();
+    }));
+    toolbar.append(&trails);
+
+    let airports = gtk::CheckButton::builder()
+        .label("airports")
+        .tooltip_text("Show airports and navaids")
+        .active(program_data_rc.borrow().config.airports_shown().unwrap_or(true))
+        .build();
+    airports.connect_toggled(clone!(@weak program_data_rc => @default-panic, move |checkbox| {
+        let pd = program_data_rc.borrow();
+        pd.config.set_airports_shown(checkbox.is_active());
+        pd.gui.as_ref().unwrap().queue_draw();
+    }));
+    toolbar.append(&airports);
+
+    let list = gtk::CheckButton::builder()
+        .label("list")
+        .tooltip_text("Show the list of aircraft")
+        .active(program_data_rc.borrow().config.aircraft_list_shown().unwrap_or(false))
+        .build();
+    list.connect_toggled(clone!(@weak program_data_rc => @default-panic, move |checkbox| {
+        let pd = program_data_rc.borrow();
+        pd.config.set_aircraft_list_shown(checkbox.is_active());
+        let gui = pd.gui.as_ref().unwrap();
+        gui.aircraft_list.widget().set_visible(checkbox.is_active());
+        gui.update_aircraft_list(&pd.tracker, pd.config.interpolate_positions().unwrap_or(true));
+    }));
+    toolbar.append(&list);
+
+    let mode_names: Vec<String> = auto_select::Mode::ALL.iter().map(|m| m.to_string()).collect();
+    let auto_select_mode = gtk::DropDown::from_strings(&mode_names.iter().map(|s| s.as_str()).collect::<Vec<&str>>());
+    auto_select_mode.set_tooltip_text(Some("Automatic target selection"));
+    let mode = program_data_rc.borrow().config.auto_select_mode();
+    auto_select_mode.set_selected(auto_select::Mode::ALL.iter().position(|m| *m == mode).unwrap() as u32);
+    auto_select_mode.connect_selected_notify(clone!(@weak program_data_rc => @default-panic, move |dd| {
+        let mut pd = program_data_rc.borrow_mut();
+        pd.config.set_auto_select_mode(auto_select::Mode::ALL[dd.selected() as usize]);
+        pd.tracker.auto_selector = pd.config.auto_select_policy().map(auto_select::AutoSelector::new);
+    }));
+    toolbar.append(&auto_select_mode);
+
     let info_level = gtk::Button::builder().label("info level").build();
     info_level.connect_clicked(clone!(@weak program_data_rc => @default-panic, move |_| {
         let mut pd = program_data_rc.borrow_mut();
@@ -456,11 +827,38 @@ fn create_toolbar(
     }));
     toolbar.append(&zoom_out);
 
+    let follow_selected = gtk::ToggleButton::builder()
+        .label("follow")
+        .tooltip_text("Center the map on the selected aircraft")
+        .build();
+    follow_selected.connect_toggled(clone!(@weak program_data_rc => @default-panic, move |btn| {
+        let mut pd = program_data_rc.borrow_mut();
+        if pd.gui.is_none() { return; }
+        if !btn.is_active() {
+            // stay where the followed aircraft was
+            if let Some(position) = selected_position(&pd) { pd.gui.as_mut().unwrap().view_center = position; }
+        }
+        pd.gui.as_ref().unwrap().drawing_area.queue_draw();
+    }));
+    toolbar.append(&follow_selected);
+
+    let reset_view = gtk::Button::builder().label("observer").tooltip_text("Center the map on the observer").build();
+    reset_view.connect_clicked(clone!(@weak program_data_rc => @default-panic, move |_| {
+        let follow_selected = program_data_rc.borrow().gui.as_ref().unwrap().follow_selected.clone();
+        follow_selected.set_active(false);
+
+        let mut pd = program_data_rc.borrow_mut();
+        let gui = pd.gui.as_mut().unwrap();
+        gui.view_center = Point2{ x: 0.0, y: 0.0 };
+        gui.drawing_area.queue_draw();
+    }));
+    toolbar.append(&reset_view);
+
     let text_enlarge = gtk::Button::builder().label("text+").build();
     text_enlarge.connect_clicked(clone!(@weak program_data_rc => @default-panic, move |_| {
         let pd = program_data_rc.borrow();
         pd.config.set_text_scale(pd.config.text_scale().unwrap_or(1.0) * ZOOM_FACTOR);
-        pd.gui.as_ref().unwrap().drawing_area.queue_draw();
+        pd.gui.as_ref().unwrap().queue_draw();
     }));
     toolbar.append(&text_enlarge);
 
@@ -468,35 +866,27 @@ fn create_toolbar(
     text_shrink.connect_clicked(clone!(@weak program_data_rc => @default-panic, move |_| {
         let pd = program_data_rc.borrow();
         pd.config.set_text_scale(pd.config.text_scale().unwrap_or(1.0) / ZOOM_FACTOR);
-        pd.gui.as_ref().unwrap().drawing_area.queue_draw();
+        pd.gui.as_ref().unwrap().queue_draw();
     }));
     toolbar.append(&text_shrink);
 
-    let toggle_recording = gtk::ToggleButton::builder().label("rec").build();
+    let toggle_recording = gtk::ToggleButton::builder()
+        .label("rec")
+        .active(program_data_rc.borrow().recording)
+        .build();
     toggle_recording.connect_clicked(clone!(@weak program_data_rc => @default-panic, move |btn| {
         on_toggle_recording(btn.is_active(), &program_data_rc);
     }));
     toolbar.append(&toggle_recording);
 
-    toolbar
-}
-
-fn get_recording_file_name() -> String {
-    format!("rec-{}.csv", chrono::Local::now().format("%Y-%m-%d_%H%M%S"))
+    (toolbar, follow_selected)
 }
 
 fn on_toggle_recording(enabled: bool, program_data_rc: &Rc<RefCell<ProgramData>>) {
     program_data_rc.borrow_mut().recording = enabled;
 
-    if let Some(prev_address) = data_receiver::stop(program_data_rc) {
-        let rec_output = if enabled {
-            Some(std::fs::File::create(get_recording_file_name()).unwrap())
-        } else {
-            None
-        };
-
-        data_receiver::start(prev_address, rec_output, program_data_rc);
-    }
+    let sources = data_receiver::stop_all(program_data_rc);
+    data_receiver::start_all(sources, program_data_rc);
 }
 
 fn set_all_margins(widget: &impl gtk::traits::WidgetExt, margin: i32) {
@@ -515,8 +905,8 @@ fn create_status_bar(program_data_rc: &Rc<RefCell<ProgramData>>) -> (gtk::Frame,
     let status_bar_box = gtk::Box::new(gtk::Orientation::Horizontal, 0);
     set_all_margins(&status_bar_box, PADDING);
 
-    let server_address = gtk::Label::new(None);
-    set_start_end_margins(&server_address, PADDING);
+    let connection_state = gtk::Label::new(None);
+    set_start_end_margins(&connection_state, PADDING);
 
     let num_aircraft = gtk::Label::new(None);
     set_start_end_margins(&num_aircraft, PADDING);
@@ -524,17 +914,27 @@ fn create_status_bar(program_data_rc: &Rc<RefCell<ProgramData>>) -> (gtk::Frame,
     let max_distance = gtk::Label::new(None);
     set_start_end_margins(&max_distance, PADDING);
 
-    status_bar_box.append(&server_address);
+    let data_sender_clients = gtk::Label::new(Some("Clients: 0"));
+    set_start_end_margins(&data_sender_clients, PADDING);
+
+    let transit = gtk::Label::new(None);
+    set_start_end_margins(&transit, PADDING);
+
+    status_bar_box.append(&connection_state);
     status_bar_box.append(&gtk::Separator::new(gtk::Orientation::Vertical));
     status_bar_box.append(&num_aircraft);
     status_bar_box.append(&gtk::Separator::new(gtk::Orientation::Vertical));
     status_bar_box.append(&max_distance);
+    status_bar_box.append(&gtk::Separator::new(gtk::Orientation::Vertical));
+    status_bar_box.append(&data_sender_clients);
+    status_bar_box.append(&gtk::Separator::new(gtk::Orientation::Vertical));
+    status_bar_box.append(&transit);
 
     let status_bar_frame = gtk::Frame::builder().child(&status_bar_box).build();
     //status_bar_frame.set_shadow_type(gtk::ShadowType::In);
     //TODO: set shadowed inset border
 
-    (status_bar_frame, StatusBarFields{ server_address, num_aircraft, max_distance })
+    (status_bar_frame, StatusBarFields{ connection_state, num_aircraft, max_distance, data_sender_clients, transit })
 }
 
 fn on_zoom(steps: i32, program_data_rc: &Rc<RefCell<ProgramData>>) {
@@ -588,7 +988,7 @@ pub fn init_main_window(app: &gtk::Application, program_data_rc: &Rc<RefCell<Pro
     sub_contents.set_hexpand(true);
     sub_contents.set_vexpand(true);
 
-    let toolbar = create_toolbar(&window, program_data_rc);
+    let (toolbar, follow_selected) = create_toolbar(&window, program_data_rc);
     sub_contents.append(&toolbar);
 
     let drawing_area = gtk::DrawingArea::builder().build();
@@ -605,24 +1005,88 @@ pub fn init_main_window(app: &gtk::Application, program_data_rc: &Rc<RefCell<Pro
     }));
     drawing_area.add_controller(evt_ctrl_scroll);
 
-    let g_click = gtk::GestureClick::builder().build();
-    g_click.connect_pressed(clone!(@weak program_data_rc => @default-panic, move |_, button, x, y| {
-        on_main_view_button_pressed(button, x, y, &program_data_rc);
+    let g_drag = gtk::GestureDrag::builder().build();
+    g_drag.connect_drag_begin(clone!(@weak program_data_rc => @default-panic, move |_, _, _| {
+        let mut pd = program_data_rc.borrow_mut();
+        let center = map_center(&pd);
+        pd.gui.as_mut().unwrap().drag_start_center = Some(center);
     }));
-    drawing_area.add_controller(g_click);
+    g_drag.connect_drag_update(clone!(@weak program_data_rc => @default-panic, move |_, dx, dy| {
+        on_main_view_dragged(dx, dy, &program_data_rc);
+    }));
+    g_drag.connect_drag_end(clone!(@weak program_data_rc => @default-panic, move |gesture, dx, dy| {
+        program_data_rc.borrow_mut().gui.as_mut().unwrap().drag_start_center = None;
+        if dx.hypot(dy) <= CLICK_TOLERANCE {
+            if let Some((x, y)) = gesture.start_point() {
+                on_main_view_button_pressed(gesture.current_button() as i32, x, y, &program_data_rc);
+            }
+        }
+    }));
+    drawing_area.add_controller(g_drag);
+
+    let sky_view = sky_view::SkyView::new(program_data_rc);
+
+    let views = gtk::Notebook::new();
+    views.set_hexpand(true);
+    views.append_page(&drawing_area, Some(&gtk::Label::new(Some("map"))));
+    views.append_page(sky_view.widget(), Some(&gtk::Label::new(Some("sky"))));
+    sky_view.widget().add_css_class(RADAR_VIEW_CSS_CLASS);
+    if program_data_rc.borrow().config.sky_view_shown().unwrap_or(false) {
+        views.set_current_page(Some(1));
+    }
+    views.connect_switch_page(clone!(@weak program_data_rc => @default-panic, move |_, _, page| {
+        program_data_rc.borrow().config.set_sky_view_shown(page == 1);
+    }));
+
+    let aircraft_list = aircraft_list::AircraftList::new(program_data_rc);
+    aircraft_list.widget().set_visible(program_data_rc.borrow().config.aircraft_list_shown().unwrap_or(false));
 
-    sub_contents.append(&drawing_area);
+    let panes = gtk::Paned::new(gtk::Orientation::Horizontal);
+    panes.set_start_child(Some(&views));
+    panes.set_end_child(Some(aircraft_list.widget()));
+    panes.set_resize_end_child(false);
+    panes.set_shrink_end_child(false);
+    sub_contents.append(&panes);
 
     contents.append(&sub_contents);
 
+    let playback_controls = playback_controls::PlaybackControls::new(program_data_rc);
+    contents.append(playback_controls.widget());
+
     let (status_bar, status_bar_fields) = create_status_bar(program_data_rc);
     contents.append(&status_bar);
 
+    let map_layers = {
+        let pd = program_data_rc.borrow();
+        map_layers::load(pd.config.map_layers(), &pd.tracker.observer_location.lat_lon)
+    };
+
+    let airports = {
+        let pd = program_data_rc.borrow();
+        pd.config.airport_files().and_then(|files| {
+            match airports::Database::load(&files, &pd.tracker.observer_location.lat_lon, pd.config.airports_max_distance()) {
+                Ok(db) => Some(db),
+                Err(e) => {
+                    println!("WARNING: Failed to load airport database: {}.", e);
+                    None
+                }
+            }
+        })
+    };
+
     program_data_rc.borrow_mut().gui = Some(GuiData{
         drawing_area: drawing_area.clone(),
         plot_range: f64::Length::new::<length::kilometer>(200.0),
+        view_center: Point2{ x: 0.0, y: 0.0 },
+        drag_start_center: None,
+        follow_selected,
+        map_layers,
+        airports,
         status_bar_fields,
-        info_level: AircraftInfoLevel::Basic
+        info_level: AircraftInfoLevel::Basic,
+        playback_controls,
+        sky_view,
+        aircraft_list
     });
 
     window.present();
@@ -632,7 +1096,7 @@ fn on_main_view_button_pressed(button: i32, x: f64, y: f64, program_data_rc: &Rc
     if button != 1 { return; } //TODO: use symbolic constant
 
     let mut pd = program_data_rc.borrow_mut();
-    let observer_ll = pd.observer_location.lat_lon.clone();
+    let observer_ll = pd.tracker.observer_location.lat_lon.clone();
     let global;
     let scale;
     let range;
@@ -642,7 +1106,8 @@ fn on_main_view_button_pressed(button: i32, x: f64, y: f64, program_data_rc: &Rc
         let dh = gui.drawing_area.height();
         range = gui.plot_range.get::<length::meter>();
         scale = 2.0 * range / dw as f64;
-        global = Point2{ x: scale * (x - dw as f64 / 2.0), y: scale * (dh as f64 / 2.0 - y) };
+        let center = map_center(&pd);
+        global = Point2{ x: center.x + scale * (x - dw as f64 / 2.0), y: center.y + scale * (dh as f64 / 2.0 - y) };
     };
 
     // At the moment we track no more than ~100 aircraft at a time, so just check all of them. If it changes,
@@ -650,10 +1115,7 @@ fn on_main_view_button_pressed(button: i32, x: f64, y: f64, program_data_rc: &Rc
 
     let mut min_dist2 = std::f64::MAX;
     let mut closest = None;
-    let mut prev_selected = None;
-    for aircraft in pd.aircraft.values_mut() {
-        if aircraft.state == data::State::Selected { prev_selected = Some(aircraft.id); }
-
+    for aircraft in pd.tracker.aircraft.values() {
         let lat_lon = if let Some(lat_lon) = &aircraft.estimated_lat_lon {
             lat_lon.0.clone()
         } else if let Some(lat_lon) = &aircraft.lat_lon {
@@ -673,9 +1135,27 @@ fn on_main_view_button_pressed(button: i32, x: f64, y: f64, program_data_rc: &Rc
 
     if let Some(closest_id) = closest {
         if min_dist2.sqrt() <= range / 10.0 {
-            if let Some(id) = prev_selected { pd.aircraft.get_mut(&id).unwrap().state = data::State::Normal; }
-            pd.aircraft.get_mut(&closest_id).unwrap().state = data::State::Selected;
-            pd.gui.as_ref().unwrap().drawing_area.queue_draw();
+            pd.tracker.select(closest_id);
+            pd.process_tracker_events();
+            let gui = pd.gui.as_ref().unwrap();
+            gui.queue_draw();
+            gui.update_aircraft_list(&pd.tracker, pd.config.interpolate_positions().unwrap_or(true));
         }
     }
 }
+
+/// Pans the map; `dx`, `dy`: pointer offset (pixels) since dragging started.
+fn on_main_view_dragged(dx: f64, dy: f64, program_data_rc: &Rc<RefCell<ProgramData>>) {
+    if dx.hypot(dy) <= CLICK_TOLERANCE { return; }
+
+    // panning manually ends following
+    let follow_selected = program_data_rc.borrow().gui.as_ref().unwrap().follow_selected.clone();
+    follow_selected.set_active(false);
+
+    let mut pd = program_data_rc.borrow_mut();
+    let gui = pd.gui.as_mut().unwrap();
+    let start = match gui.drag_start_center { Some(start) => start, None => return };
+    let meters_per_pixel = 2.0 * gui.plot_range.get::<length::meter>() / gui.drawing_area.width() as f64;
+    gui.view_center = Point2{ x: start.x - dx * meters_per_pixel, y: start.y + dy * meters_per_pixel };
+    gui.drawing_area.queue_draw();
+}
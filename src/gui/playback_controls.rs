@@ -0,0 +1,110 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use crate::{playback, playback::Speed, program_data::ProgramData};
+use gtk4 as gtk;
+use gtk::glib;
+use gtk::glib::clone;
+use gtk::prelude::*;
+use std::{cell::RefCell, rc::Rc};
+use super::{PADDING, SPACING, set_all_margins};
+
+/// Controls of recording playback; hidden when no playback is in progress.
+pub struct PlaybackControls {
+    container: gtk::Box,
+    play_pause: gtk::Button,
+    speed: gtk::DropDown,
+    /// Shows playback progress; can be dragged to seek.
+    progress: gtk::Scale,
+    position: gtk::Label
+}
+
+impl PlaybackControls {
+    pub fn new(program_data_rc: &Rc<RefCell<ProgramData>>) -> PlaybackControls {
+        let container = gtk::Box::new(gtk::Orientation::Horizontal, SPACING);
+        set_all_margins(&container, PADDING);
+        container.set_visible(false);
+
+        let play_pause = gtk::Button::with_label("pause");
+        play_pause.connect_clicked(clone!(@weak program_data_rc => @default-panic, move |_| {
+            let paused = program_data_rc.borrow().player.as_ref().is_none_or(|p| p.paused);
+            playback::set_paused(!paused, &program_data_rc);
+        }));
+
+        let speed_names: Vec<String> = Speed::ALL.iter().map(|s| s.to_string()).collect();
+        let speed = gtk::DropDown::from_strings(&speed_names.iter().map(|s| s.as_str()).collect::<Vec<&str>>());
+        speed.set_tooltip_text(Some("Playback speed"));
+        speed.connect_selected_notify(clone!(@weak program_data_rc => @default-panic, move |dd| {
+            playback::set_speed(Speed::ALL[dd.selected() as usize], &program_data_rc);
+        }));
+
+        let progress = gtk::Scale::with_range(gtk::Orientation::Horizontal, 0.0, 1.0, 0.001);
+        progress.set_draw_value(false);
+        progress.set_hexpand(true);
+        progress.connect_change_value(clone!(@weak program_data_rc => @default-panic, move |_, _, value| {
+            let num_entries = program_data_rc.borrow().player.as_ref().map(|p| p.entries.len());
+            if let Some(num_entries) = num_entries {
+                playback::seek((value.clamp(0.0, 1.0) * num_entries as f64) as usize, &program_data_rc);
+            }
+            glib::Propagation::Proceed
+        }));
+
+        let position = gtk::Label::new(None);
+
+        let close = gtk::Button::with_label("close");
+        close.set_tooltip_text(Some("Stop playback"));
+        close.connect_clicked(clone!(@weak program_data_rc => @default-panic, move |_| {
+            playback::stop(&program_data_rc);
+            let mut pd = program_data_rc.borrow_mut();
+            pd.tracker.clear();
+            pd.process_tracker_events();
+        }));
+
+        container.append(&play_pause);
+        container.append(&speed);
+        container.append(&progress);
+        container.append(&position);
+        container.append(&close);
+
+        PlaybackControls{ container, play_pause, speed, progress, position }
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    /// Returns the currently selected playback speed.
+    pub fn speed(&self) -> Speed {
+        Speed::ALL[self.speed.selected() as usize]
+    }
+
+    /// Shows state of `player` (or hides the controls if there is none).
+    pub fn update(&self, player: Option<&playback::Player>) {
+        let player = match player {
+            Some(player) => player,
+            None => {
+                self.container.set_visible(false);
+                return;
+            }
+        };
+
+        self.container.set_visible(true);
+        self.play_pause.set_label(if player.paused { "play" } else { "pause" });
+
+        let num_entries = player.entries.len();
+        self.progress.set_value(player.position as f64 / num_entries as f64);
+
+        let t = player.entries[player.position.min(num_entries - 1)].t;
+        self.position.set_text(&format!(
+            "{}  {}  ({:.0}%)",
+            player.file_name,
+            t.format("%Y-%m-%d %H:%M:%S"),
+            100.0 * player.position as f64 / num_entries as f64
+        ));
+    }
+}
@@ -0,0 +1,318 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use cgmath::{Deg, InnerSpace, Point2, Rad, Vector2};
+use crate::{gui, gui::RestoreTransform, program_data::ProgramData};
+use gtk4 as gtk;
+use gtk::cairo;
+use gtk::glib::clone;
+use gtk::prelude::*;
+use plane_tracker::{data, transit, visibility::{HorizonMask, Visibility}};
+use pointing_utils::{GeoPos, uom};
+use std::{cell::RefCell, rc::Rc};
+use uom::si::length;
+
+// all values in pixels
+const MARGIN: f64 = 30.0;
+const FONT_SIZE: f64 = 20.0;
+const MARKER_SIZE: f64 = 8.0;
+/// Minimal drawn radius of the Sun and Moon discs.
+const MIN_DISC_RADIUS: f64 = 6.0;
+
+const ELEVATION_STEP: Deg<f64> = Deg(15.0);
+const AZIMUTH_STEP: Deg<f64> = Deg(30.0);
+
+/// Polar chart of the observer's sky: zenith at the center, horizon at the edge, North up, East to the right
+/// (as on the map). Elevations are apparent ones (see `Visibility`).
+pub struct SkyView {
+    drawing_area: gtk::DrawingArea
+}
+
+impl SkyView {
+    pub fn new(program_data_rc: &Rc<RefCell<ProgramData>>) -> SkyView {
+        let drawing_area = gtk::DrawingArea::builder().build();
+        drawing_area.set_hexpand(true);
+        drawing_area.set_vexpand(true);
+        drawing_area.set_draw_func(clone!(@weak program_data_rc => @default-panic, move |_widget, ctx, width, height| {
+            on_draw(ctx, width, height, &program_data_rc);
+        }));
+
+        let g_click = gtk::GestureClick::builder().build();
+        g_click.connect_pressed(clone!(@weak program_data_rc => @default-panic, move |_, button, x, y| {
+            on_button_pressed(button, x, y, &program_data_rc);
+        }));
+        drawing_area.add_controller(g_click);
+
+        SkyView{ drawing_area }
+    }
+
+    pub fn widget(&self) -> &gtk::DrawingArea {
+        &self.drawing_area
+    }
+
+    pub fn queue_draw(&self) {
+        self.drawing_area.queue_draw();
+    }
+}
+
+/// Returns chart center and radius (corresponding to the horizon) in pixels.
+fn chart_geometry(width: i32, height: i32) -> (Point2<f64>, f64) {
+    let center = Point2{ x: width as f64 / 2.0, y: height as f64 / 2.0 };
+    let radius = (0.5 * width.min(height) as f64 - MARGIN).max(0.0);
+
+    (center, radius)
+}
+
+/// Returns position relative to the chart center (Y points down) in units of chart radius.
+fn chart_position(azimuth: Deg<f64>, elevation: Deg<f64>) -> Vector2<f64> {
+    let r = (Deg(90.0) - elevation) / Deg(90.0);
+    let azimuth = Rad::from(azimuth).0;
+
+    Vector2{ x: r * azimuth.sin(), y: -r * azimuth.cos() }
+}
+
+/// Current transform of `ctx`: Y points down, chart center at (0, 0), pixel scale.
+fn draw_grid(ctx: &cairo::Context, radius: f64, text_scale: f64) {
+    ctx.set_source_rgb(0.3, 0.3, 0.3);
+    ctx.set_line_width(1.0);
+    ctx.set_font_size(FONT_SIZE * text_scale);
+
+    let mut elevation = Deg(0.0);
+    while elevation < Deg(90.0) {
+        let r = radius * ((Deg(90.0) - elevation) / Deg(90.0));
+        ctx.arc(0.0, 0.0, r, 0.0, 2.0 * std::f64::consts::PI);
+        ctx.stroke().unwrap();
+
+        if elevation > Deg(0.0) {
+            ctx.move_to(0.2 * FONT_SIZE * text_scale, -r);
+            ctx.show_text(&format!("{:.0}°", elevation.0)).unwrap();
+            ctx.stroke().unwrap();
+        }
+
+        elevation += ELEVATION_STEP;
+    }
+
+    let mut azimuth = Deg(0.0);
+    while azimuth < Deg(360.0) {
+        let dir = chart_position(azimuth, Deg(0.0));
+        ctx.move_to(0.0, 0.0);
+        ctx.line_to(radius * dir.x, radius * dir.y);
+        ctx.stroke().unwrap();
+
+        let label = match azimuth.0 as i32 {
+            0 => "N".to_string(),
+            90 => "E".to_string(),
+            180 => "S".to_string(),
+            270 => "W".to_string(),
+            _ => format!("{:.0}°", azimuth.0)
+        };
+        let extents = ctx.text_extents(&label).unwrap();
+        let label_pos = (radius + 0.5 * MARGIN) * dir;
+        ctx.move_to(label_pos.x - extents.width() / 2.0, label_pos.y + extents.height() / 2.0);
+        ctx.show_text(&label).unwrap();
+        ctx.stroke().unwrap();
+
+        azimuth += AZIMUTH_STEP;
+    }
+}
+
+/// Current transform of `ctx`: Y points down, chart center at (0, 0), pixel scale.
+fn draw_sun_and_moon(
+    ctx: &cairo::Context,
+    radius: f64,
+    observer: &GeoPos,
+    now: &chrono::DateTime<chrono::Utc>,
+    text_scale: f64
+) {
+    for body in transit::Body::ALL {
+        let position = transit::body_position(body, observer, now);
+        if position.elevation < Deg(0.0) { continue; }
+
+        let color = match body {
+            transit::Body::Sun => (1.0, 0.85, 0.0),
+            transit::Body::Moon => (0.75, 0.75, 0.75)
+        };
+        let pos = radius * chart_position(position.azimuth, position.elevation);
+        let disc_radius = (radius * (position.radius / Deg(90.0))).max(MIN_DISC_RADIUS * text_scale);
+
+        ctx.set_source_rgb(color.0, color.1, color.2);
+        ctx.arc(pos.x, pos.y, disc_radius, 0.0, 2.0 * std::f64::consts::PI);
+        ctx.fill().unwrap();
+
+        ctx.set_font_size(FONT_SIZE * text_scale);
+        ctx.move_to(pos.x + disc_radius + 0.2 * FONT_SIZE * text_scale, pos.y - disc_radius);
+        ctx.show_text(&body.to_string()).unwrap();
+        ctx.stroke().unwrap();
+    }
+}
+
+/// Lists predicted passages near the Sun and Moon in the lower left corner.
+/// Current transform of `ctx`: Y points down, pixel scale.
+fn draw_transits(ctx: &cairo::Context, height: i32, pd: &ProgramData, text_scale: f64) {
+    let line_spacing = 1.1 * FONT_SIZE * text_scale;
+    ctx.set_font_size(FONT_SIZE * text_scale);
+
+    let mut y = height as f64 - 0.5 * MARGIN;
+    for prediction in pd.tracker.transits.iter().rev() {
+        let callsign = pd.tracker.aircraft
+            .get(&prediction.aircraft)
+            .and_then(|a| a.callsign.as_ref())
+            .map(|c| c.trim().to_string())
+            .unwrap_or_else(|| prediction.aircraft.to_string());
+
+        let color = if prediction.is_transit() { gui::colors::EMERGENCY } else { gui::colors::ACTIVE };
+        ctx.set_source_rgb(color.0, color.1, color.2);
+        ctx.move_to(0.5 * MARGIN, y);
+        ctx.show_text(&format!(
+            "{}  {}  {:.0} s  {:.2}°",
+            callsign,
+            prediction.body,
+            prediction.time_to_event.as_secs_f64(),
+            prediction.separation.0
+        )).unwrap();
+        ctx.stroke().unwrap();
+
+        y -= line_spacing;
+    }
+}
+
+/// Current transform of `ctx`: Y points down, chart center at (0, 0), pixel scale.
+fn draw_horizon_mask(ctx: &cairo::Context, radius: f64, mask: &HorizonMask) {
+    const STEP: f64 = 1.0; // degrees
+
+    ctx.set_source_rgba(0.3, 0.2, 0.1, 0.5);
+    ctx.arc(0.0, 0.0, radius, 0.0, 2.0 * std::f64::consts::PI);
+    let mut azimuth = 0.0;
+    while azimuth <= 360.0 {
+        let pos = radius * chart_position(Deg(azimuth), mask.elevation_at(Deg(azimuth)).max(Deg(0.0)));
+        if azimuth == 0.0 { ctx.move_to(pos.x, pos.y); } else { ctx.line_to(pos.x, pos.y); }
+        azimuth += STEP;
+    }
+    ctx.close_path();
+    // the area between the horizon and the mask
+    ctx.set_fill_rule(cairo::FillRule::EvenOdd);
+    ctx.fill().unwrap();
+    ctx.set_fill_rule(cairo::FillRule::Winding);
+}
+
+/// Current transform of `ctx`: Y points down, pixel scale.
+fn draw_selected_info(
+    ctx: &cairo::Context,
+    observer: &GeoPos,
+    visibility: &Visibility,
+    aircraft: &data::Aircraft,
+    interpolate: bool,
+    text_scale: f64
+) {
+    let position = match visibility.apparent_position(observer, aircraft, interpolate) {
+        Some(position) => position,
+        None => return
+    };
+    let slant_range = data::get_distance(observer, aircraft, interpolate);
+
+    ctx.set_source_rgb(gui::colors::SELECTED.0, gui::colors::SELECTED.1, gui::colors::SELECTED.2);
+    ctx.set_font_size(FONT_SIZE * text_scale);
+    ctx.move_to(0.5 * MARGIN, 0.5 * MARGIN + FONT_SIZE * text_scale);
+    ctx.show_text(&format!(
+        "{}  az. {:.1}°  el. {:.1}°  {:.1} km{}",
+        aircraft.callsign.as_ref().map(|c| c.trim().to_string()).unwrap_or_else(|| aircraft.id.to_string()),
+        position.azimuth.0,
+        position.elevation.0,
+        slant_range.get::<length::kilometer>(),
+        if position.visible { "" } else { "  (hidden)" }
+    )).unwrap();
+    ctx.stroke().unwrap();
+}
+
+fn on_draw(ctx: &cairo::Context, width: i32, height: i32, program_data_rc: &Rc<RefCell<ProgramData>>) {
+    let pd = program_data_rc.borrow();
+    let text_scale = pd.config.text_scale().unwrap_or(1.0);
+    let interpolate = pd.config.interpolate_positions().unwrap_or(true);
+    let info_level = pd.gui.as_ref().unwrap().info_level;
+    let airports = pd.gui.as_ref().unwrap().airports.as_ref();
+    let observer = &pd.tracker.observer_location;
+    let now = pd.tracker.clock.now();
+
+    let (center, radius) = chart_geometry(width, height);
+
+    {
+        let _rt = RestoreTransform::new(ctx);
+        ctx.translate(center.x, center.y);
+        if let Some(mask) = &pd.tracker.visibility.horizon_mask {
+            draw_horizon_mask(ctx, radius, mask);
+        }
+        draw_grid(ctx, radius, text_scale);
+        draw_sun_and_moon(ctx, radius, observer, &pd.tracker.clock.utc(), text_scale);
+
+        let mut aircraft: Vec<&data::Aircraft> = pd.tracker.aircraft.values().collect();
+        // the selected aircraft will be drawn as last
+        aircraft.sort_by_key(|a| a.state == data::State::Selected);
+
+        for aircraft in aircraft {
+            let position = match pd.tracker.visibility.apparent_position(observer, aircraft, interpolate) {
+                Some(position) => position,
+                None => continue
+            };
+            if position.elevation < Deg(0.0) { continue; }
+
+            let pos = radius * chart_position(position.azimuth, position.elevation);
+
+            let _rt = RestoreTransform::new(ctx);
+            ctx.translate(pos.x, pos.y);
+            let color = gui::aircraft_color(aircraft, position.visible, now);
+            ctx.set_source_rgb(color.0, color.1, color.2);
+            ctx.arc(0.0, 0.0, MARKER_SIZE / 2.0 * text_scale, 0.0, 2.0 * std::f64::consts::PI);
+            ctx.fill().unwrap();
+            gui::draw_aircraft_info(ctx, aircraft, observer, interpolate, now, text_scale, info_level, airports);
+        }
+    }
+
+    if let Some(selected) = pd.tracker.selected().and_then(|id| pd.tracker.aircraft.get(&id)) {
+        draw_selected_info(ctx, observer, &pd.tracker.visibility, selected, interpolate, text_scale);
+    }
+    draw_transits(ctx, height, &pd, text_scale);
+}
+
+fn on_button_pressed(button: i32, x: f64, y: f64, program_data_rc: &Rc<RefCell<ProgramData>>) {
+    if button != 1 { return; } //TODO: use symbolic constant
+
+    let mut pd = program_data_rc.borrow_mut();
+    let interpolate = pd.config.interpolate_positions().unwrap_or(true);
+    let (center, radius) = {
+        let sky_view = &pd.gui.as_ref().unwrap().sky_view;
+        chart_geometry(sky_view.drawing_area.width(), sky_view.drawing_area.height())
+    };
+    if radius <= 0.0 { return; }
+    let clicked = Vector2{ x: (x - center.x) / radius, y: (y - center.y) / radius };
+
+    let mut min_dist2 = std::f64::MAX;
+    let mut closest = None;
+    for aircraft in pd.tracker.aircraft.values() {
+        let position = match pd.tracker.visibility.apparent_position(&pd.tracker.observer_location, aircraft, interpolate) {
+            Some(position) => position,
+            None => continue
+        };
+        if position.elevation < Deg(0.0) { continue; }
+
+        let dist2 = (chart_position(position.azimuth, position.elevation) - clicked).magnitude2();
+        if dist2 < min_dist2 {
+            min_dist2 = dist2;
+            closest = Some(aircraft.id);
+        }
+    }
+
+    if let Some(closest_id) = closest {
+        if min_dist2.sqrt() <= 0.1 {
+            pd.tracker.select(closest_id);
+            pd.process_tracker_events();
+            let gui = pd.gui.as_ref().unwrap();
+            gui.queue_draw();
+            gui.update_aircraft_list(&pd.tracker, interpolate);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use crate::{data_receiver::{DataSource, InputFormat}, program_data::ProgramData};
+use gtk4 as gtk;
+use gtk::glib::clone;
+use gtk::prelude::*;
+use std::{cell::RefCell, rc::Rc};
+use super::{PADDING, SPACING, set_all_margins};
+
+struct SourceRow {
+    container: gtk::Box,
+    enabled: gtk::CheckButton,
+    label: gtk::Entry,
+    address: gtk::Entry,
+    format: gtk::DropDown
+}
+
+impl SourceRow {
+    fn new(source: &DataSource) -> SourceRow {
+        let container = gtk::Box::new(gtk::Orientation::Horizontal, SPACING);
+
+        let enabled = gtk::CheckButton::builder()
+            .active(source.enabled)
+            .tooltip_text("Connect to this source")
+            .build();
+
+        let label = gtk::Entry::builder().text(&source.label).placeholder_text("label").build();
+
+        let address = gtk::Entry::builder().text(&source.address).placeholder_text("host:port").hexpand(true).build();
+
+        let format_names: Vec<String> = InputFormat::ALL.iter().map(|f| f.to_string()).collect();
+        let format = gtk::DropDown::from_strings(&format_names.iter().map(|s| s.as_str()).collect::<Vec<&str>>());
+        format.set_selected(InputFormat::ALL.iter().position(|f| *f == source.format).unwrap() as u32);
+
+        container.append(&enabled);
+        container.append(&label);
+        container.append(&address);
+        container.append(&format);
+
+        SourceRow{ container, enabled, label, address, format }
+    }
+
+    fn source(&self) -> DataSource {
+        DataSource{
+            label: self.label.text().trim().into(),
+            address: self.address.text().trim().into(),
+            format: InputFormat::ALL[self.format.selected() as usize],
+            enabled: self.enabled.is_active()
+        }
+    }
+}
+
+fn validate(sources: &[DataSource]) -> Result<(), String> {
+    for (i, source) in sources.iter().enumerate() {
+        if source.label.is_empty() || source.address.is_empty() {
+            return Err("Label and address must not be empty.".into());
+        }
+        if source.label.contains(';') || source.address.contains(';') {
+            return Err("Label and address must not contain \";\".".into());
+        }
+        if sources[..i].iter().any(|s| s.label == source.label) {
+            return Err(format!("Duplicated label: \"{}\".", source.label));
+        }
+    }
+
+    Ok(())
+}
+
+fn add_row(source: &DataSource, rows_box: &gtk::Box, rows: &Rc<RefCell<Vec<SourceRow>>>) {
+    let row = SourceRow::new(source);
+
+    let remove = gtk::Button::with_label("remove");
+    let container = row.container.clone();
+    remove.connect_clicked(clone!(@weak rows_box, @weak container, @weak rows => move |_| {
+        rows_box.remove(&container);
+        rows.borrow_mut().retain(|r| r.container != container);
+    }));
+    row.container.append(&remove);
+
+    rows_box.append(&row.container);
+    rows.borrow_mut().push(row);
+}
+
+/// Shows dialog for editing the list of data sources; `on_accepted` receives the new list.
+pub fn show(
+    main_wnd: &gtk::ApplicationWindow,
+    program_data_rc: &Rc<RefCell<ProgramData>>,
+    on_accepted: impl Fn(Vec<DataSource>) + 'static
+) {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Data sources"),
+        Some(main_wnd),
+        gtk::DialogFlags::MODAL,
+        &[("Connect", gtk::ResponseType::Ok), ("Cancel", gtk::ResponseType::Cancel)]
+    );
+    set_all_margins(&dialog.content_area(), PADDING);
+    dialog.content_area().set_spacing(SPACING);
+
+    let rows_box = gtk::Box::new(gtk::Orientation::Vertical, SPACING);
+    let rows = Rc::new(RefCell::new(Vec::<SourceRow>::new()));
+    for source in program_data_rc.borrow().config.sources() {
+        add_row(&source, &rows_box, &rows);
+    }
+
+    let add = gtk::Button::with_label("add");
+    add.connect_clicked(clone!(@weak rows_box, @strong rows => move |_| {
+        let num_rows = rows.borrow().len();
+        add_row(
+            &DataSource{
+                label: format!("source{}", num_rows + 1),
+                address: "localhost:30003".into(),
+                format: InputFormat::Sbs,
+                enabled: true
+            },
+            &rows_box,
+            &rows
+        );
+    }));
+
+    dialog.content_area().append(&rows_box);
+    dialog.content_area().append(&add);
+
+    dialog.connect_response(clone!(@strong rows, @weak program_data_rc => @default-panic, move |dlg, response| {
+        if response == gtk::ResponseType::Ok {
+            let sources: Vec<DataSource> = rows.borrow().iter().map(|r| r.source()).collect();
+            if let Err(message) = validate(&sources) {
+                let error_dlg = gtk::MessageDialog::new(
+                    Some(dlg),
+                    gtk::DialogFlags::MODAL,
+                    gtk::MessageType::Error,
+                    gtk::ButtonsType::Close,
+                    &message
+                );
+                error_dlg.connect_response(|d, _| d.close());
+                error_dlg.show();
+                return;
+            }
+            program_data_rc.borrow_mut().config.set_sources(&sources);
+            on_accepted(sources);
+        }
+        dlg.close();
+    }));
+
+    dialog.show();
+}
@@ -0,0 +1,41 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use crate::{data_receiver, program_data::ProgramData};
+use glib::clone;
+use std::{cell::RefCell, rc::Rc};
+
+#[cfg(unix)]
+const SIGINT: i32 = 2;
+#[cfg(unix)]
+const SIGTERM: i32 = 15;
+
+/// Runs without GUI until interrupted; connects to the enabled data sources from the configuration.
+pub fn run(program_data_rc: &Rc<RefCell<ProgramData>>) -> glib::ExitCode {
+    let main_loop = glib::MainLoop::new(None, false);
+
+    #[cfg(unix)]
+    for signal in [SIGINT, SIGTERM] {
+        glib::unix_signal_add_local(signal, clone!(@strong main_loop => move || {
+            main_loop.quit();
+            glib::ControlFlow::Break
+        }));
+    }
+
+    let sources = program_data_rc.borrow().config.sources();
+    if !sources.iter().any(|s| s.enabled) {
+        println!("WARNING: No enabled data sources in configuration.");
+    }
+    data_receiver::start_all(sources, program_data_rc);
+
+    main_loop.run();
+
+    data_receiver::stop_all(program_data_rc);
+
+    glib::ExitCode::SUCCESS
+}
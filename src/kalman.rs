@@ -0,0 +1,705 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use cgmath::{Deg, InnerSpace, Rad, Vector3};
+use crate::geodesy::{LocalFrame, ecef_to_geodetic, geodetic_to_ecef, horizontal_velocity_ecef};
+use pointing_utils::{GeoPos, LatLon, uom};
+use uom::{si::f64, si::{length, velocity}};
+
+/// Standard deviation of reported positions (meters).
+const POSITION_SIGMA: f64 = 50.0;
+/// Standard deviation of reported horizontal velocity components (m/s).
+const VELOCITY_SIGMA: f64 = 2.0;
+/// Standard deviation of reported altitude (meters); covers the 25 ft quantization and some barometric noise.
+const ALTITUDE_SIGMA: f64 = 15.0;
+/// Standard deviation of reported vertical rate (m/s).
+const VERTICAL_RATE_SIGMA: f64 = 1.0;
+
+/// Process noise (m/s²) of the constant velocity model; large enough to follow standard rate turns.
+const CV_ACCELERATION_SIGMA: f64 = 4.0;
+/// Process noise (m/s²) of the constant turn rate model.
+const CT_ACCELERATION_SIGMA: f64 = 1.0;
+/// Process noise of the turn rate (rad/s²).
+const TURN_RATE_ACCELERATION_SIGMA: f64 = 0.01;
+const VERTICAL_ACCELERATION_SIGMA: f64 = 1.0;
+
+/// Initial standard deviations of values not yet reported.
+const INITIAL_SPEED_SIGMA: f64 = 150.0;
+const INITIAL_TURN_RATE_SIGMA: f64 = 0.03;
+const INITIAL_VERTICAL_RATE_SIGMA: f64 = 10.0;
+
+/// Measurements further from the prediction (squared Mahalanobis distance) are rejected;
+/// 99.9% quantiles of the chi-squared distribution with 2 and 1 degrees of freedom.
+const GATE_2D: f64 = 13.8;
+const GATE_1D: f64 = 10.8;
+/// After this many consecutive rejected measurements the filter is restarted from the next one.
+const MAX_REJECTIONS: usize = 3;
+
+/// Maximal distance (meters) of the estimated position from the origin of the filter's local frame
+/// before the frame is moved.
+const MAX_FRAME_OFFSET: f64 = 20_000.0;
+
+/// Motion model of the horizontal track filter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Model {
+    ConstantVelocity,
+    /// Constant speed and turn rate (straight flight being a turn with zero rate).
+    ConstantTurn
+}
+
+impl Model {
+    pub const ALL: [Model; 2] = [Model::ConstantVelocity, Model::ConstantTurn];
+}
+
+impl std::fmt::Display for Model {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Model::ConstantVelocity => "constant-velocity",
+            Model::ConstantTurn => "constant-turn"
+        })
+    }
+}
+
+impl std::str::FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Model::ALL.iter().find(|m| m.to_string() == s).copied().ok_or_else(|| format!("unknown track filter model \"{}\"", s))
+    }
+}
+
+/// Smoothed state of an aircraft.
+#[derive(Clone, Debug)]
+pub struct Estimate {
+    pub lat_lon: LatLon,
+    /// `None` if altitude has not been reported.
+    pub altitude: Option<f64::Length>,
+    pub track: Deg<f64>,
+    pub ground_speed: f64::Velocity,
+    /// `None` if altitude has not been reported.
+    pub vertical_rate: Option<f64::Velocity>,
+    /// Rate of change of track (per second; positive to the right); always zero for `Model::ConstantVelocity`.
+    pub turn_rate: Deg<f64>,
+    /// Standard deviation of the horizontal position along the axis of largest uncertainty.
+    pub position_sigma: f64::Length,
+    /// Standard deviation of the horizontal velocity along the axis of largest uncertainty.
+    pub velocity_sigma: f64::Velocity,
+    pub altitude_sigma: Option<f64::Length>
+}
+
+impl Estimate {
+    /// Returns ECEF velocity (m/s), including the vertical rate (if known).
+    pub fn velocity_ecef(&self) -> Vector3<f64> {
+        let position = GeoPos{ lat_lon: self.lat_lon.clone(), elevation: self.altitude.unwrap_or(meters(0.0)) };
+        let vertical_rate = self.vertical_rate.map_or(0.0, |v| v.get::<velocity::meter_per_second>());
+
+        horizontal_velocity_ecef(&position, self.track, self.ground_speed)
+            + LocalFrame::new(&position).up * vertical_rate
+    }
+}
+
+type Matrix<const R: usize, const C: usize> = [[f64; C]; R];
+
+fn mul<const R: usize, const K: usize, const C: usize>(a: &Matrix<R, K>, b: &Matrix<K, C>) -> Matrix<R, C> {
+    let mut result = [[0.0; C]; R];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..K).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    result
+}
+
+fn transpose<const R: usize, const C: usize>(a: &Matrix<R, C>) -> Matrix<C, R> {
+    let mut result = [[0.0; R]; C];
+    for (i, row) in a.iter().enumerate() {
+        for (j, value) in row.iter().enumerate() {
+            result[j][i] = *value;
+        }
+    }
+    result
+}
+
+fn identity<const N: usize>() -> Matrix<N, N> {
+    let mut result = [[0.0; N]; N];
+    for (i, row) in result.iter_mut().enumerate() { row[i] = 1.0; }
+    result
+}
+
+/// Returns `F P F^T + Q`.
+fn propagate<const N: usize>(f: &Matrix<N, N>, p: &Matrix<N, N>, q: &Matrix<N, N>) -> Matrix<N, N> {
+    let mut result = mul(&mul(f, p), &transpose(f));
+    for i in 0..N {
+        for j in 0..N { result[i][j] += q[i][j]; }
+    }
+    result
+}
+
+/// Performs the measurement update of state `x` and covariance `p` with measurement `z` of state elements
+/// starting at `index`, measurement variance `r` and innovation covariance inverted by `invert`.
+/// Returns false (leaving the state unchanged) if the measurement is rejected by gate `gate`.
+fn update<const N: usize, const M: usize>(
+    x: &mut [f64; N],
+    p: &mut Matrix<N, N>,
+    index: usize,
+    z: [f64; M],
+    r: f64,
+    gate: Option<f64>,
+    invert: fn(&Matrix<M, M>) -> Matrix<M, M>
+) -> bool {
+    let mut h = [[0.0; N]; M];
+    for (i, row) in h.iter_mut().enumerate() { row[index + i] = 1.0; }
+
+    let innovation: [f64; M] = std::array::from_fn(|i| z[i] - x[index + i]);
+    let mut s: Matrix<M, M> = std::array::from_fn(|i| std::array::from_fn(|j| p[index + i][index + j]));
+    for (i, row) in s.iter_mut().enumerate() { row[i] += r; }
+    let s_inv = invert(&s);
+
+    let distance2: f64 = (0..M).map(|i| (0..M).map(|j| innovation[i] * s_inv[i][j] * innovation[j]).sum::<f64>()).sum();
+    if gate.is_some_and(|gate| distance2 > gate) { return false; }
+
+    let gain = mul(&mul(p, &transpose(&h)), &s_inv);
+    for i in 0..N {
+        x[i] += (0..M).map(|j| gain[i][j] * innovation[j]).sum::<f64>();
+    }
+    let mut i_kh = identity::<N>();
+    let kh = mul(&gain, &h);
+    for i in 0..N {
+        for j in 0..N { i_kh[i][j] -= kh[i][j]; }
+    }
+    *p = mul(&i_kh, p);
+    // keep symmetric despite rounding errors
+    let p_t = transpose(p);
+    for (i, row) in p.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() { *value = 0.5 * (*value + p_t[i][j]); }
+    }
+
+    true
+}
+
+fn invert_1x1(m: &Matrix<1, 1>) -> Matrix<1, 1> {
+    [[1.0 / m[0][0]]]
+}
+
+fn invert_2x2(m: &Matrix<2, 2>) -> Matrix<2, 2> {
+    let det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+    [[m[1][1] / det, -m[0][1] / det], [-m[1][0] / det, m[0][0] / det]]
+}
+
+/// Returns the larger eigenvalue of a symmetric 2x2 matrix.
+fn max_eigenvalue(a: f64, b: f64, d: f64) -> f64 {
+    0.5 * (a + d) + (0.25 * (a - d).powi(2) + b * b).sqrt()
+}
+
+fn meters(value: f64) -> f64::Length {
+    f64::Length::new::<length::meter>(value)
+}
+
+fn meters_per_second(value: f64) -> f64::Velocity {
+    f64::Velocity::new::<velocity::meter_per_second>(value)
+}
+
+/// Horizontal motion in the East-North plane of a local frame: position (meters), velocity (m/s)
+/// and turn rate (rad/s, positive counterclockwise).
+#[derive(Clone, Debug)]
+struct Horizontal {
+    frame: LocalFrame,
+    x: [f64; 5],
+    p: Matrix<5, 5>,
+    t: std::time::Instant,
+    rejections: usize
+}
+
+impl Horizontal {
+    /// `velocity`: ECEF.
+    fn new(model: Model, lat_lon: &LatLon, velocity: Option<&Vector3<f64>>, t: std::time::Instant) -> Horizontal {
+        let frame = LocalFrame::new(&GeoPos{ lat_lon: lat_lon.clone(), elevation: meters(0.0) });
+        let (v, v_var) = match velocity {
+            Some(v) => { let v = frame.to_enu_vec(v); ([v.x, v.y], VELOCITY_SIGMA.powi(2)) },
+            None => ([0.0, 0.0], INITIAL_SPEED_SIGMA.powi(2))
+        };
+
+        let mut p = [[0.0; 5]; 5];
+        p[0][0] = POSITION_SIGMA.powi(2);
+        p[1][1] = POSITION_SIGMA.powi(2);
+        p[2][2] = v_var;
+        p[3][3] = v_var;
+        if model == Model::ConstantTurn { p[4][4] = INITIAL_TURN_RATE_SIGMA.powi(2); }
+
+        Horizontal{ frame, x: [0.0, 0.0, v[0], v[1], 0.0], p, t, rejections: 0 }
+    }
+
+    /// Returns state and covariance at `t`.
+    fn predict(&self, model: Model, t: std::time::Instant) -> ([f64; 5], Matrix<5, 5>) {
+        let dt = t.saturating_duration_since(self.t).as_secs_f64();
+        let [e, n, ve, vn, omega] = self.x;
+        let (sin, cos) = (omega * dt).sin_cos();
+
+        // `a` = sin(ωT)/ω, `b` = (1 - cos(ωT))/ω, and their derivatives w.r.t. ω
+        let (a, b, da, db) = if omega.abs() < 1.0e-6 {
+            (dt, 0.5 * omega * dt * dt, -omega * dt.powi(3) / 3.0, 0.5 * dt * dt)
+        } else {
+            (
+                sin / omega,
+                (1.0 - cos) / omega,
+                (dt * cos * omega - sin) / (omega * omega),
+                (dt * sin * omega - (1.0 - cos)) / (omega * omega)
+            )
+        };
+
+        let x = [
+            e + a * ve - b * vn,
+            n + b * ve + a * vn,
+            cos * ve - sin * vn,
+            sin * ve + cos * vn,
+            omega
+        ];
+
+        let f = [
+            [1.0, 0.0, a, -b, da * ve - db * vn],
+            [0.0, 1.0, b, a, db * ve + da * vn],
+            [0.0, 0.0, cos, -sin, -dt * (sin * ve + cos * vn)],
+            [0.0, 0.0, sin, cos, dt * (cos * ve - sin * vn)],
+            [0.0, 0.0, 0.0, 0.0, 1.0]
+        ];
+
+        let (acc_var, turn_var) = match model {
+            Model::ConstantVelocity => (CV_ACCELERATION_SIGMA.powi(2), 0.0),
+            Model::ConstantTurn => (CT_ACCELERATION_SIGMA.powi(2), TURN_RATE_ACCELERATION_SIGMA.powi(2))
+        };
+        let (q_pp, q_pv, q_vv) = (acc_var * dt.powi(4) / 4.0, acc_var * dt.powi(3) / 2.0, acc_var * dt * dt);
+        let q = [
+            [q_pp, 0.0, q_pv, 0.0, 0.0],
+            [0.0, q_pp, 0.0, q_pv, 0.0],
+            [q_pv, 0.0, q_vv, 0.0, 0.0],
+            [0.0, q_pv, 0.0, q_vv, 0.0],
+            [0.0, 0.0, 0.0, 0.0, turn_var * dt * dt]
+        ];
+
+        (x, propagate(&f, &self.p, &q))
+    }
+
+    fn advance(&mut self, model: Model, t: std::time::Instant) {
+        let (x, p) = self.predict(model, t);
+        self.x = x;
+        self.p = p;
+        self.t = self.t.max(t);
+    }
+
+    fn to_lat_lon(&self, e: f64, n: f64) -> LatLon {
+        ecef_to_geodetic(&(self.frame.origin + self.frame.east * e + self.frame.north * n)).lat_lon
+    }
+
+    fn project(&self, lat_lon: &LatLon) -> [f64; 2] {
+        let enu = self.frame.to_enu_point(&geodetic_to_ecef(&GeoPos{ lat_lon: lat_lon.clone(), elevation: meters(0.0) }));
+        [enu.x, enu.y]
+    }
+
+    /// Moves the frame's origin to the current position (to keep the flat-Earth approximation valid).
+    fn recenter(&mut self) {
+        let frame = LocalFrame::new(&GeoPos{ lat_lon: self.to_lat_lon(self.x[0], self.x[1]), elevation: meters(0.0) });
+        let rot = [
+            [frame.east.dot(self.frame.east), frame.east.dot(self.frame.north)],
+            [frame.north.dot(self.frame.east), frame.north.dot(self.frame.north)]
+        ];
+        let mut t = identity::<5>();
+        for i in 0..2 {
+            for j in 0..2 {
+                t[i][j] = rot[i][j];
+                t[i + 2][j + 2] = rot[i][j];
+            }
+        }
+
+        let (ve, vn) = (self.x[2], self.x[3]);
+        self.x = [0.0, 0.0, rot[0][0] * ve + rot[0][1] * vn, rot[1][0] * ve + rot[1][1] * vn, self.x[4]];
+        self.p = mul(&mul(&t, &self.p), &transpose(&t));
+        self.frame = frame;
+    }
+}
+
+/// Altitude (meters) and vertical rate (m/s).
+#[derive(Clone, Debug)]
+struct Vertical {
+    x: [f64; 2],
+    p: Matrix<2, 2>,
+    t: std::time::Instant,
+    rejections: usize
+}
+
+impl Vertical {
+    fn new(altitude: f64, t: std::time::Instant) -> Vertical {
+        Vertical{
+            x: [altitude, 0.0],
+            p: [[ALTITUDE_SIGMA.powi(2), 0.0], [0.0, INITIAL_VERTICAL_RATE_SIGMA.powi(2)]],
+            t,
+            rejections: 0
+        }
+    }
+
+    fn predict(&self, t: std::time::Instant) -> ([f64; 2], Matrix<2, 2>) {
+        let dt = t.saturating_duration_since(self.t).as_secs_f64();
+        let var = VERTICAL_ACCELERATION_SIGMA.powi(2);
+        let f = [[1.0, dt], [0.0, 1.0]];
+        let q = [[var * dt.powi(4) / 4.0, var * dt.powi(3) / 2.0], [var * dt.powi(3) / 2.0, var * dt * dt]];
+
+        ([self.x[0] + dt * self.x[1], self.x[1]], propagate(&f, &self.p, &q))
+    }
+
+    fn advance(&mut self, t: std::time::Instant) {
+        let (x, p) = self.predict(t);
+        self.x = x;
+        self.p = p;
+        self.t = self.t.max(t);
+    }
+}
+
+/// Kalman filter estimating an aircraft's track from reported positions, velocities and altitudes.
+/// Horizontal and vertical motion are filtered separately; the former in a local tangent plane
+/// following the aircraft.
+#[derive(Clone, Debug)]
+pub struct TrackFilter {
+    model: Model,
+    horizontal: Option<Horizontal>,
+    vertical: Option<Vertical>,
+    /// Track and ground speed reported before the first position; used to initialize `horizontal`.
+    initial_velocity: Option<(Deg<f64>, f64::Velocity)>
+}
+
+impl TrackFilter {
+    pub fn new(model: Model) -> TrackFilter {
+        TrackFilter{ model, horizontal: None, vertical: None, initial_velocity: None }
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Returns false if the position is rejected as inconsistent with the track (e.g., an out-of-order
+    /// message); after several consecutive rejections the track is restarted from the reported position.
+    pub fn update_position(&mut self, lat_lon: &LatLon, t: std::time::Instant) -> bool {
+        let model = self.model;
+        let horizontal = match &mut self.horizontal {
+            Some(horizontal) => horizontal,
+            None => {
+                let velocity = self.initial_velocity.map(|(track, ground_speed)| horizontal_velocity_ecef(
+                    &GeoPos{ lat_lon: lat_lon.clone(), elevation: meters(0.0) }, track, ground_speed
+                ));
+                self.horizontal = Some(Horizontal::new(model, lat_lon, velocity.as_ref(), t));
+                return true;
+            }
+        };
+
+        let z = horizontal.project(lat_lon);
+        let (mut x, mut p) = horizontal.predict(model, t);
+        if !update(&mut x, &mut p, 0, z, POSITION_SIGMA.powi(2), Some(GATE_2D), invert_2x2) {
+            horizontal.rejections += 1;
+            if horizontal.rejections > MAX_REJECTIONS {
+                let velocity = horizontal.frame.from_enu_vec(&Vector3{ x: x[2], y: x[3], z: 0.0 });
+                *horizontal = Horizontal::new(model, lat_lon, Some(&velocity), t);
+                return true;
+            }
+            return false;
+        }
+
+        horizontal.x = x;
+        horizontal.p = p;
+        horizontal.t = horizontal.t.max(t);
+        horizontal.rejections = 0;
+        if horizontal.x[0].hypot(horizontal.x[1]) > MAX_FRAME_OFFSET { horizontal.recenter(); }
+
+        true
+    }
+
+    pub fn update_velocity(&mut self, track: Deg<f64>, ground_speed: f64::Velocity, t: std::time::Instant) {
+        let model = self.model;
+        let horizontal = match &mut self.horizontal {
+            Some(horizontal) => horizontal,
+            None => {
+                self.initial_velocity = Some((track, ground_speed));
+                return;
+            }
+        };
+
+        horizontal.advance(model, t);
+        let position = GeoPos{ lat_lon: horizontal.to_lat_lon(horizontal.x[0], horizontal.x[1]), elevation: meters(0.0) };
+        let v = horizontal.frame.to_enu_vec(&horizontal_velocity_ecef(&position, track, ground_speed));
+        update(&mut horizontal.x, &mut horizontal.p, 2, [v.x, v.y], VELOCITY_SIGMA.powi(2), None, invert_2x2);
+    }
+
+    /// Returns false if the altitude is rejected as inconsistent with the track; after several consecutive
+    /// rejections the altitude track is restarted from the reported value.
+    pub fn update_altitude(&mut self, altitude: f64::Length, t: std::time::Instant) -> bool {
+        let altitude = altitude.get::<length::meter>();
+        let vertical = match &mut self.vertical {
+            Some(vertical) => vertical,
+            None => {
+                self.vertical = Some(Vertical::new(altitude, t));
+                return true;
+            }
+        };
+
+        vertical.advance(t);
+        if update(&mut vertical.x, &mut vertical.p, 0, [altitude], ALTITUDE_SIGMA.powi(2), Some(GATE_1D), invert_1x1) {
+            vertical.rejections = 0;
+            true
+        } else {
+            vertical.rejections += 1;
+            if vertical.rejections > MAX_REJECTIONS {
+                *vertical = Vertical::new(altitude, t);
+                true
+            } else {
+                false
+            }
+        }
+    }
+
+    pub fn update_vertical_rate(&mut self, vertical_rate: f64::Velocity, t: std::time::Instant) {
+        if let Some(vertical) = &mut self.vertical {
+            vertical.advance(t);
+            let z = [vertical_rate.get::<velocity::meter_per_second>()];
+            update(&mut vertical.x, &mut vertical.p, 1, z, VERTICAL_RATE_SIGMA.powi(2), None, invert_1x1);
+        }
+    }
+
+    /// Returns the estimated state at `t` (predicted if later than the last update); `None` if no position
+    /// has been reported yet.
+    pub fn estimate(&self, t: std::time::Instant) -> Option<Estimate> {
+        let horizontal = self.horizontal.as_ref()?;
+        let (x, p) = horizontal.predict(self.model, t);
+        let vertical = self.vertical.as_ref().map(|vertical| vertical.predict(t));
+
+        let lat_lon = horizontal.to_lat_lon(x[0], x[1]);
+        // express velocity in the aircraft's own local frame
+        let v = LocalFrame::new(&GeoPos{ lat_lon: lat_lon.clone(), elevation: meters(0.0) })
+            .to_enu_vec(&horizontal.frame.from_enu_vec(&Vector3{ x: x[2], y: x[3], z: 0.0 }));
+        let track = Deg::from(Rad(f64::atan2(v.x, v.y)));
+
+        Some(Estimate{
+            lat_lon,
+            altitude: vertical.map(|(x, _)| meters(x[0])),
+            track: if track.0 < 0.0 { track + Deg(360.0) } else { track },
+            ground_speed: meters_per_second(v.x.hypot(v.y)),
+            vertical_rate: vertical.map(|(x, _)| meters_per_second(x[1])),
+            turn_rate: -Deg::from(Rad(x[4])),
+            position_sigma: meters(max_eigenvalue(p[0][0], p[0][1], p[1][1]).sqrt()),
+            velocity_sigma: meters_per_second(max_eigenvalue(p[2][2], p[2][3], p[3][3]).sqrt()),
+            altitude_sigma: vertical.map(|(_, p)| meters(p[0][0].sqrt()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geodesy::geodesic_direct;
+
+    const SPEED: f64 = 200.0;
+    /// Standard rate turn (°/s).
+    const TURN_RATE: f64 = 3.0;
+
+    fn at(t0: std::time::Instant, seconds: f64) -> std::time::Instant {
+        t0 + std::time::Duration::from_secs_f64(seconds)
+    }
+
+    /// Deterministic noise in [-0.5, 0.5).
+    fn noise(i: usize) -> f64 {
+        ((i as f64 * 12.9898).sin() * 43758.5453).rem_euclid(1.0) - 0.5
+    }
+
+    /// Horizontal distance (meters) between `p1` and `p2`.
+    fn distance(p1: &LatLon, p2: &LatLon) -> f64 {
+        let ground = |lat_lon: &LatLon| GeoPos{ lat_lon: lat_lon.clone(), elevation: meters(0.0) };
+        let enu = LocalFrame::new(&ground(p1)).to_enu_point(&geodetic_to_ecef(&ground(p2)));
+        enu.x.hypot(enu.y)
+    }
+
+    fn angle_difference(a1: Deg<f64>, a2: Deg<f64>) -> f64 {
+        ((a1.0 - a2.0 + 180.0).rem_euclid(360.0) - 180.0).abs()
+    }
+
+    /// Returns position and track at `t` (seconds) of an aircraft flying east at `SPEED`.
+    fn straight(t: f64) -> (LatLon, Deg<f64>) {
+        geodesic_direct(&LatLon{ lat: Deg(52.0), lon: Deg(21.0) }, Deg(90.0), meters(SPEED * t))
+    }
+
+    /// Returns position and track at `t` (seconds) of an aircraft initially flying north at `SPEED`
+    /// and turning right at `TURN_RATE`.
+    fn turning(t: f64) -> (LatLon, Deg<f64>) {
+        let center = LocalFrame::new(&GeoPos{
+            lat_lon: LatLon{ lat: Deg(52.0), lon: Deg(21.0) },
+            elevation: meters(0.0)
+        });
+        let omega = Rad::from(Deg(TURN_RATE)).0;
+        let radius = SPEED / omega;
+        let angle = std::f64::consts::PI - omega * t; // measured counterclockwise from East
+        let position = center.origin + center.east * (radius * angle.cos()) + center.north * (radius * angle.sin());
+
+        (ecef_to_geodetic(&position).lat_lon, Deg((TURN_RATE * t).rem_euclid(360.0)))
+    }
+
+    /// Feeds `filter` once per second (until `duration`) with positions (with errors up to 40 m), tracks and speeds
+    /// of `trajectory`; returns false if any position was rejected.
+    fn feed(
+        filter: &mut TrackFilter,
+        trajectory: fn(f64) -> (LatLon, Deg<f64>),
+        t0: std::time::Instant,
+        duration: usize
+    ) -> bool {
+        let mut all_accepted = true;
+        for i in 0..=duration {
+            let (lat_lon, track) = trajectory(i as f64);
+            let t = at(t0, i as f64);
+            filter.update_velocity(track, meters_per_second(SPEED), t);
+            let error = (Deg(360.0 * (noise(2 * i) + 0.5)), meters(80.0 * noise(2 * i + 1).abs()));
+            let (position, _) = geodesic_direct(&lat_lon, error.0, error.1);
+            all_accepted &= filter.update_position(&position, t);
+        }
+        all_accepted
+    }
+
+    #[test]
+    fn straight_track_convergence() {
+        for model in Model::ALL {
+            let t0 = std::time::Instant::now();
+            let mut filter = TrackFilter::new(model);
+            assert!(filter.estimate(t0).is_none());
+            assert!(feed(&mut filter, straight, t0, 120), "{}", model);
+
+            let (lat_lon, track) = straight(120.0);
+            let estimate = filter.estimate(at(t0, 120.0)).unwrap();
+            assert!(distance(&lat_lon, &estimate.lat_lon) < 30.0, "{}", model);
+            assert!(angle_difference(track, estimate.track) < 0.5, "{}", model);
+            assert!((estimate.ground_speed.get::<velocity::meter_per_second>() - SPEED).abs() < 1.0, "{}", model);
+            assert!(estimate.turn_rate.0.abs() < 0.1, "{}", model);
+            assert!(estimate.altitude.is_none() && estimate.vertical_rate.is_none());
+
+            // prediction
+            let (lat_lon, _) = straight(150.0);
+            assert!(distance(&lat_lon, &filter.estimate(at(t0, 150.0)).unwrap().lat_lon) < 100.0, "{}", model);
+        }
+    }
+
+    #[test]
+    fn turn_convergence() {
+        for model in Model::ALL {
+            let t0 = std::time::Instant::now();
+            let mut filter = TrackFilter::new(model);
+            assert!(feed(&mut filter, turning, t0, 90), "{}", model);
+
+            let (lat_lon, track) = turning(90.0);
+            let estimate = filter.estimate(at(t0, 90.0)).unwrap();
+            assert!(distance(&lat_lon, &estimate.lat_lon) < 30.0, "{}", model);
+            assert!(angle_difference(track, estimate.track) < 1.0, "{}", model);
+
+            match model {
+                Model::ConstantVelocity => assert_eq!(0.0, estimate.turn_rate.0),
+                Model::ConstantTurn => {
+                    assert!((estimate.turn_rate.0 - TURN_RATE).abs() < 0.1);
+                    // follows the turn when predicting
+                    let (lat_lon, _) = turning(100.0);
+                    assert!(distance(&lat_lon, &filter.estimate(at(t0, 100.0)).unwrap().lat_lon) < 100.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn vertical_rate_tracking() {
+        const CLIMB_RATE: f64 = 10.0;
+        const FT: f64 = 0.3048;
+
+        let t0 = std::time::Instant::now();
+        let mut filter = TrackFilter::new(Model::ConstantVelocity);
+        filter.update_position(&LatLon{ lat: Deg(52.0), lon: Deg(21.0) }, t0);
+        for i in 0..=60 {
+            // quantized to 25 ft
+            let altitude = ((1000.0 + CLIMB_RATE * i as f64) / (25.0 * FT)).round() * 25.0 * FT;
+            assert!(filter.update_altitude(meters(altitude), at(t0, i as f64)));
+        }
+
+        let estimate = filter.estimate(at(t0, 60.0)).unwrap();
+        assert!((estimate.altitude.unwrap().get::<length::meter>() - 1600.0).abs() < 10.0);
+        assert!((estimate.vertical_rate.unwrap().get::<velocity::meter_per_second>() - CLIMB_RATE).abs() < 1.0);
+        assert!(estimate.altitude_sigma.unwrap().get::<length::meter>() < ALTITUDE_SIGMA);
+        // extrapolated
+        let estimate = filter.estimate(at(t0, 70.0)).unwrap();
+        assert!((estimate.altitude.unwrap().get::<length::meter>() - 1700.0).abs() < 20.0);
+
+        // level-off reported by the vertical rate
+        for i in 61..=70 {
+            filter.update_vertical_rate(meters_per_second(0.0), at(t0, i as f64));
+            filter.update_altitude(meters(1600.0), at(t0, i as f64));
+        }
+        let estimate = filter.estimate(at(t0, 70.0)).unwrap();
+        assert!((estimate.altitude.unwrap().get::<length::meter>() - 1600.0).abs() < 20.0);
+        assert!(estimate.vertical_rate.unwrap().get::<velocity::meter_per_second>().abs() < 2.0);
+    }
+
+    #[test]
+    fn outliers_are_rejected() {
+        let t0 = std::time::Instant::now();
+        let mut filter = TrackFilter::new(Model::ConstantVelocity);
+        assert!(feed(&mut filter, straight, t0, 60));
+        for i in 0..=60 {
+            assert!(filter.update_altitude(meters(10_000.0), at(t0, i as f64)));
+        }
+
+        let t = at(t0, 61.0);
+        let before = filter.estimate(t).unwrap();
+        let (lat_lon, _) = straight(61.0);
+        let (far, _) = geodesic_direct(&lat_lon, Deg(0.0), meters(2_000.0));
+        assert!(!filter.update_position(&far, t));
+        assert!(!filter.update_altitude(meters(12_000.0), t));
+
+        let after = filter.estimate(t).unwrap();
+        assert_eq!(before.lat_lon, after.lat_lon);
+        assert_eq!(before.track, after.track);
+        assert_eq!(before.altitude, after.altitude);
+        assert_eq!(before.vertical_rate, after.vertical_rate);
+
+        // the track continues
+        assert!(filter.update_position(&lat_lon, t));
+        assert!(distance(&lat_lon, &filter.estimate(t).unwrap().lat_lon) < 30.0);
+    }
+
+    #[test]
+    fn track_restart() {
+        let t0 = std::time::Instant::now();
+        let mut filter = TrackFilter::new(Model::ConstantVelocity);
+        assert!(feed(&mut filter, straight, t0, 60));
+
+        // consistently offset positions (e.g., after a wrong position decoding) are rejected at first
+        let offset = |t: f64| geodesic_direct(&straight(t).0, Deg(0.0), meters(5_000.0)).0;
+        for i in 1..=MAX_REJECTIONS {
+            assert!(!filter.update_position(&offset(60.0 + i as f64), at(t0, 60.0 + i as f64)));
+        }
+        let t = 61.0 + MAX_REJECTIONS as f64;
+        assert!(filter.update_position(&offset(t), at(t0, t)));
+        let estimate = filter.estimate(at(t0, t)).unwrap();
+        assert!(distance(&offset(t), &estimate.lat_lon) < 1.0);
+        // the velocity is kept
+        assert!(angle_difference(Deg(90.0), estimate.track) < 1.0);
+
+        // the restarted track is followed
+        for i in 1..=10 {
+            let t = t + i as f64;
+            assert!(filter.update_position(&offset(t), at(t0, t)));
+        }
+
+        // the same for altitude
+        for i in 0..=10 {
+            assert!(filter.update_altitude(meters(10_000.0), at(t0, i as f64)));
+        }
+        for i in 1..=MAX_REJECTIONS {
+            assert!(!filter.update_altitude(meters(3_000.0), at(t0, 10.0 + i as f64)));
+        }
+        let t = 11.0 + MAX_REJECTIONS as f64;
+        assert!(filter.update_altitude(meters(3_000.0), at(t0, t)));
+        assert!((filter.estimate(at(t0, t)).unwrap().altitude.unwrap().get::<length::meter>() - 3_000.0).abs() < 1.0);
+    }
+}
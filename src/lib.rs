@@ -0,0 +1,25 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+pub mod airports;
+pub mod auto_select;
+pub mod beast;
+pub mod clock;
+pub mod country;
+mod csv;
+pub mod data;
+pub mod data_sender;
+pub mod geodesy;
+pub mod kalman;
+pub mod mode_s;
+pub mod overlay;
+pub mod registry;
+pub mod sbs;
+pub mod tracker;
+pub mod transit;
+pub mod visibility;
@@ -6,34 +6,90 @@
 // (see the LICENSE file for details).
 //
 
+mod cli;
 mod config;
 mod data_receiver;
-mod data_sender;
-mod data;
+#[cfg(feature = "gui")]
 mod gui;
+mod headless;
+#[cfg(feature = "gui")]
+mod playback;
+mod program_data;
 
-use data::{ProgramData, State};
+use glib::clone;
+#[cfg(feature = "gui")]
 use gtk4 as gtk;
+#[cfg(feature = "gui")]
 use gtk::prelude::*;
-use gtk::glib;
-use gtk::glib::clone;
+use plane_tracker::{data_sender, registry};
+use program_data::ProgramData;
 use std::{cell::RefCell, rc::Rc};
 
 fn main() -> glib::ExitCode {
+    let mut args = std::env::args();
+    let program_name = args.next().unwrap_or_default();
+    let options = match cli::parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}.\n\n{}", e, cli::USAGE);
+            return glib::ExitCode::FAILURE;
+        }
+    };
+    if options.help {
+        print!("{}", cli::USAGE);
+        return glib::ExitCode::SUCCESS;
+    }
+    if let Some(dir) = &options.recording_dir {
+        if !dir.is_dir() {
+            eprintln!("Error: recording directory {} does not exist.", dir.to_string_lossy());
+            return glib::ExitCode::FAILURE;
+        }
+    }
+
+    let config = config::Configuration::new(options.config_path, options.overrides);
+    let program_data_rc = Rc::new(RefCell::new(ProgramData::new(config)));
+    {
+        let mut pd = program_data_rc.borrow_mut();
+        pd.recording = options.record;
+        pd.recording_dir = options.recording_dir;
+    }
+
+    let run_headless = options.headless || cfg!(not(feature = "gui"));
+
+    set_up_timer(&program_data_rc);
+    if let Err(e) = set_up_data_sender(&program_data_rc) {
+        if run_headless {
+            eprintln!("Error: {}.", e);
+            return glib::ExitCode::FAILURE;
+        }
+        println!("WARNING: Data sender disabled: {}.", e);
+    }
+    set_up_registry(&program_data_rc);
+
+    if run_headless {
+        return headless::run(&program_data_rc);
+    }
+
+    run_gui(&program_data_rc, program_name, options.connect)
+}
+
+#[cfg(feature = "gui")]
+fn run_gui(program_data_rc: &Rc<RefCell<ProgramData>>, program_name: String, connect: bool) -> glib::ExitCode {
     let application = gtk::Application::builder()
         .application_id("ga_software.plane_tracker")
+        .flags(gtk::gio::ApplicationFlags::NON_UNIQUE) // allow several instances with different setups
         .build();
 
-    let program_data_rc = Rc::new(RefCell::new(data::ProgramData::new()));
-
     application.connect_activate(clone!(@weak program_data_rc => @default-panic, move |app| {
         gui::init_main_window(&app, &program_data_rc);
+        if connect {
+            let sources = program_data_rc.borrow().config.sources();
+            data_receiver::start_all(sources, &program_data_rc);
+        }
     }));
 
-    set_up_timer(&program_data_rc);
-    set_up_data_sender(&program_data_rc);
-
-    let exit_code = application.run();
+    // options have already been handled; do not let GTK interpret them
+    let exit_code = application.run_with_args(&[program_name]);
 
     if program_data_rc.borrow().config.store().is_err() {
         println!("WARNING: Failed to save configuration.");
@@ -42,14 +98,64 @@ fn main() -> glib::ExitCode {
     exit_code
 }
 
-fn set_up_data_sender(program_data_rc: &Rc<RefCell<ProgramData>>) {
+/// Only headless mode is available in a build without the "gui" feature.
+#[cfg(not(feature = "gui"))]
+fn run_gui(_: &Rc<RefCell<ProgramData>>, _: String, _: bool) -> glib::ExitCode {
+    unreachable!()
+}
+
+fn set_up_data_sender(program_data_rc: &Rc<RefCell<ProgramData>>) -> Result<(), Box<dyn std::error::Error>> {
+    let (request_sender_worker, request_receiver_main) = glib::MainContext::channel(glib::Priority::DEFAULT);
+    request_receiver_main.attach(None, clone!(@weak program_data_rc => @default-panic, move |()| {
+        let mut pd = program_data_rc.borrow_mut();
+        pd.tracker.process_requests();
+        pd.process_tracker_events();
+        glib::ControlFlow::Continue
+    }));
+    let request_sender_worker = std::sync::Mutex::new(request_sender_worker);
+    program_data_rc.borrow_mut().tracker.set_request_notifier(move || {
+        let _ = request_sender_worker.lock().unwrap().send(());
+    });
+
     let (sender_worker, receiver_main) = glib::MainContext::channel(glib::Priority::DEFAULT);
     receiver_main.attach(None, clone!(@weak program_data_rc => @default-panic, move |stream| {
-        program_data_rc.borrow_mut().data_senders.push(stream);
+        program_data_rc.borrow_mut().tracker.add_data_sender(stream);
+        program_data_rc.borrow().process_tracker_events();
         glib::ControlFlow::Continue
     }));
 
-    data_sender::start_listener(program_data_rc.borrow().config.data_sender_port(), sender_worker);
+    let address = program_data_rc.borrow().config.data_sender_address();
+    let port = program_data_rc.borrow().config.data_sender_port();
+    data_sender::start_listener(
+        &address,
+        port,
+        move |stream| sender_worker.send(stream).unwrap()
+    )
+}
+
+/// Loads the registry database (if configured) in the background, so that startup is not delayed.
+fn set_up_registry(program_data_rc: &Rc<RefCell<ProgramData>>) {
+    let path = match program_data_rc.borrow().config.registry_database() {
+        Some(path) => path,
+        None => return
+    };
+
+    let (sender_worker, receiver_main) = glib::MainContext::channel(glib::Priority::DEFAULT);
+    receiver_main.attach(None, clone!(@weak program_data_rc => @default-panic, move |result| {
+        match result {
+            Ok(registry) => {
+                let mut pd = program_data_rc.borrow_mut();
+                pd.tracker.set_registry(registry);
+                pd.process_tracker_events();
+            },
+            Err(e) => println!("WARNING: Failed to load registry database: {}.", e)
+        }
+        glib::ControlFlow::Break
+    }));
+
+    std::thread::spawn(move || {
+        let _ = sender_worker.send(registry::Database::load(&path).map_err(|e| e.to_string()));
+    });
 }
 
 fn set_up_timer(program_data_rc: &Rc<RefCell<ProgramData>>) {
@@ -71,15 +177,16 @@ fn on_timer(program_data_rc: &Rc<RefCell<ProgramData>>) {
     let pd = &mut *program_data_rc.borrow_mut();
     let interpolate = pd.config.interpolate_positions().unwrap_or(true);
     if interpolate  {
-        let now = std::time::Instant::now();
-        for aircraft in pd.aircraft.values_mut() {
-            aircraft.update_interpolated_position(now);
-            if aircraft.state == State::Selected {
-                data_sender::send_data(&aircraft, &pd.observer_location, &mut pd.data_senders);
-            }
-        }
+        pd.tracker.interpolate_positions();
     }
 
-    pd.garbage_collect();
-    pd.gui.as_ref().unwrap().drawing_area.queue_draw();
+    pd.tracker.garbage_collect();
+    pd.tracker.update_auto_selection();
+    pd.tracker.predict_transits();
+    pd.process_tracker_events();
+    #[cfg(feature = "gui")]
+    if let Some(gui) = pd.gui.as_ref() {
+        gui.queue_draw();
+        gui.update_aircraft_list(&pd.tracker, interpolate);
+    }
 }
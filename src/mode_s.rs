@@ -0,0 +1,701 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use cgmath::Deg;
+use crate::data::{ModeSTransponderCode, SbsMessage, Squawk};
+use pointing_utils::{LatLon, uom};
+use std::collections::HashMap;
+use uom::{si::f64, si::{length, velocity}};
+
+/// Length (bytes) of a short (56-bit) frame.
+pub const SHORT_FRAME_LEN: usize = 7;
+/// Length (bytes) of a long (112-bit) frame.
+pub const LONG_FRAME_LEN: usize = 14;
+
+/// Aircraft not heard from for this long are removed from decoder state.
+const MAX_STATE_AGE: std::time::Duration = std::time::Duration::from_secs(60);
+const CLEANUP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+/// Maximum age of last decoded position to be used as reference for local CPR decoding.
+const MAX_LOCAL_REF_AGE: std::time::Duration = std::time::Duration::from_secs(60);
+
+mod downlink_format {
+    pub const SHORT_AIR_AIR_SURVEILLANCE: u32 = 0;
+    pub const SURVEILLANCE_ALTITUDE_REPLY: u32 = 4;
+    pub const SURVEILLANCE_IDENTITY_REPLY: u32 = 5;
+    pub const ALL_CALL_REPLY: u32 = 11;
+    pub const LONG_AIR_AIR_SURVEILLANCE: u32 = 16;
+    pub const EXTENDED_SQUITTER: u32 = 17;
+    pub const EXTENDED_SQUITTER_NON_TRANSPONDER: u32 = 18;
+    pub const COMM_B_ALTITUDE_REPLY: u32 = 20;
+    pub const COMM_B_IDENTITY_REPLY: u32 = 21;
+}
+
+mod capability {
+    pub const ON_GROUND: u32 = 4;
+    pub const AIRBORNE: u32 = 5;
+}
+
+/// Value of the DF18 control field denoting ADS-B message with an ICAO address.
+const CF_ADS_B_ICAO_ADDRESS: u32 = 0;
+
+const CALLSIGN_CHARS: &[u8; 64] = b"#ABCDEFGHIJKLMNOPQRSTUVWXYZ##### ###############0123456789######";
+
+fn feet(value: f64) -> f64::Length {
+    f64::Length::new::<length::foot>(value)
+}
+
+fn knots(value: f64) -> f64::Velocity {
+    f64::Velocity::new::<velocity::knot>(value)
+}
+
+/// Returns bits `first`..=`last` of `frame`; bits are numbered from 1 (the most significant bit of the first byte),
+/// as in ICAO Annex 10.
+fn bits(frame: &[u8], first: usize, last: usize) -> u32 {
+    assert!(first >= 1 && last >= first && last - first < 32 && last <= 8 * frame.len());
+
+    let mut result = 0u32;
+    for bit in first..=last {
+        let byte = frame[(bit - 1) / 8];
+        result = (result << 1) | ((byte >> (7 - (bit - 1) % 8)) & 1) as u32;
+    }
+
+    result
+}
+
+/// Returns the Mode S CRC-24 remainder of `data`.
+fn crc(data: &[u8]) -> u32 {
+    const GENERATOR: u32 = 0xFFF409;
+
+    let mut remainder = 0u32;
+    for byte in data {
+        remainder ^= (*byte as u32) << 16;
+        for _ in 0..8 {
+            remainder = if remainder & 0x800000 != 0 {
+                (remainder << 1) ^ GENERATOR
+            } else {
+                remainder << 1
+            };
+        }
+    }
+
+    remainder & 0xFFFFFF
+}
+
+/// Returns the value of the parity field (last 24 bits) XOR-ed with the CRC of the preceding bits.
+fn parity_syndrome(frame: &[u8]) -> u32 {
+    let n = frame.len();
+    crc(&frame[..n - 3]) ^ bits(frame, 8 * n - 23, 8 * n)
+}
+
+/// Rearranges a 13-bit Mode S identity/altitude field (C1 A1 C2 A2 C4 A4 X B1 D1 B2 D2 B4 D4)
+/// into the Mode A order (bits of each octal digit A, B, C, D in successive nibbles, as 0xABCD).
+fn id13_to_mode_a(value: u32) -> u32 {
+    const MAPPING: [(u32, u32); 12] = [
+        (0x1000, 0x0010), // C1
+        (0x0800, 0x1000), // A1
+        (0x0400, 0x0020), // C2
+        (0x0200, 0x2000), // A2
+        (0x0100, 0x0040), // C4
+        (0x0080, 0x4000), // A4
+        (0x0020, 0x0100), // B1
+        (0x0010, 0x0001), // D1
+        (0x0008, 0x0200), // B2
+        (0x0004, 0x0002), // D2
+        (0x0002, 0x0400), // B4
+        (0x0001, 0x0004), // D4
+    ];
+
+    MAPPING.iter().fold(0, |acc, (src, dest)| if value & src != 0 { acc | dest } else { acc })
+}
+
+fn mode_a_to_squawk(mode_a: u32) -> Squawk {
+    let digit = |shift: u32| ((mode_a >> shift) & 7) as u16;
+    Squawk::from_bits((digit(12) << 9) | (digit(8) << 6) | (digit(4) << 3) | digit(0))
+}
+
+/// Decodes Gillham-coded altitude (Mode C); returns value in hundreds of feet.
+fn mode_a_to_mode_c(mode_a: u32) -> Option<i32> {
+    if mode_a & 0xFFFF8889 != 0 || mode_a & 0x00F0 == 0 { return None; }
+
+    let mut one_hundreds = 0i32;
+    if mode_a & 0x0010 != 0 { one_hundreds ^= 0x007; } // C1
+    if mode_a & 0x0020 != 0 { one_hundreds ^= 0x003; } // C2
+    if mode_a & 0x0040 != 0 { one_hundreds ^= 0x001; } // C4
+
+    if one_hundreds & 5 == 5 { one_hundreds ^= 2; } // 7 -> 5, 5 -> 7
+    if one_hundreds > 5 { return None; }
+
+    let mut five_hundreds = 0i32;
+    if mode_a & 0x0002 != 0 { five_hundreds ^= 0x0FF; } // D2
+    if mode_a & 0x0004 != 0 { five_hundreds ^= 0x07F; } // D4
+    if mode_a & 0x1000 != 0 { five_hundreds ^= 0x03F; } // A1
+    if mode_a & 0x2000 != 0 { five_hundreds ^= 0x01F; } // A2
+    if mode_a & 0x4000 != 0 { five_hundreds ^= 0x00F; } // A4
+    if mode_a & 0x0100 != 0 { five_hundreds ^= 0x007; } // B1
+    if mode_a & 0x0200 != 0 { five_hundreds ^= 0x003; } // B2
+    if mode_a & 0x0400 != 0 { five_hundreds ^= 0x001; } // B4
+
+    if five_hundreds & 1 != 0 { one_hundreds = 6 - one_hundreds; }
+
+    Some(five_hundreds * 5 + one_hundreds - 13)
+}
+
+/// Decodes the 13-bit altitude field of surveillance and air-air replies.
+fn decode_ac13(value: u32) -> Option<f64::Length> {
+    const M_BIT: u32 = 1 << 6;
+    const Q_BIT: u32 = 1 << 4;
+
+    if value == 0 || value & M_BIT != 0 { return None; } // metric altitude is not supported
+
+    if value & Q_BIT != 0 {
+        let n = ((value & 0x1F80) >> 2) | ((value & 0x0020) >> 1) | (value & 0x000F);
+        Some(feet(n as f64 * 25.0 - 1000.0))
+    } else {
+        mode_a_to_mode_c(id13_to_mode_a(value)).map(|h| feet(100.0 * h as f64))
+    }
+}
+
+/// Decodes the 12-bit altitude field of an airborne position message.
+fn decode_ac12(value: u32) -> Option<f64::Length> {
+    // insert the (zero) M bit
+    decode_ac13(((value & 0x0FC0) << 1) | (value & 0x003F))
+}
+
+mod cpr {
+    use cgmath::Deg;
+    use pointing_utils::LatLon;
+    use std::f64::consts::PI;
+
+    /// Number of latitude zones between the equator and a pole.
+    const NZ: f64 = 15.0;
+
+    /// Encoded values are fractions of this.
+    const MAX_VALUE: f64 = 131072.0;
+
+    /// Maximum distance (degrees of arc; 180 NM) between the reference and a locally decoded airborne position
+    /// (a quarter of it for surface positions).
+    const MAX_LOCAL_RANGE: f64 = 3.0;
+
+    /// Maximum time between even and odd frames to be used for global decoding.
+    const MAX_PAIR_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+    #[derive(Clone)]
+    pub struct Frame {
+        pub odd: bool,
+        pub surface: bool,
+        pub lat: u32,
+        pub lon: u32,
+        pub t: std::time::Instant
+    }
+
+    impl Frame {
+        fn zone_span(&self) -> f64 {
+            if self.surface { 90.0 } else { 360.0 }
+        }
+    }
+
+    fn modulo(x: f64, y: f64) -> f64 {
+        x - y * (x / y).floor()
+    }
+
+    /// Number of longitude zones at the given latitude.
+    fn nl(lat: f64) -> i32 {
+        let lat = lat.abs();
+        if lat == 0.0 {
+            59
+        } else if lat == 87.0 {
+            2
+        } else if lat > 87.0 {
+            1
+        } else {
+            let a = 1.0 - (PI / (2.0 * NZ)).cos();
+            let b = (PI / 180.0 * lat).cos().powi(2);
+            (2.0 * PI / (1.0 - a / b).acos()).floor() as i32
+        }
+    }
+
+    fn normalize_lon(lon: f64) -> f64 {
+        modulo(lon + 180.0, 360.0) - 180.0
+    }
+
+    /// Returns the great-circle distance in degrees of arc.
+    fn arc_distance(a: &LatLon, b: &LatLon) -> f64 {
+        let (lat1, lat2) = (a.lat.0.to_radians(), b.lat.0.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (b.lon.0 - a.lon.0).to_radians();
+        let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        (2.0 * h.sqrt().min(1.0).asin()).to_degrees()
+    }
+
+    /// Decodes an airborne position from a pair of even and odd frames.
+    pub fn decode_global(even: &Frame, odd: &Frame) -> Option<LatLon> {
+        if even.surface || odd.surface { return None; }
+
+        let interval = if even.t > odd.t { even.t - odd.t } else { odd.t - even.t };
+        if interval > MAX_PAIR_INTERVAL { return None; }
+
+        let (lat_e, lon_e) = (even.lat as f64 / MAX_VALUE, even.lon as f64 / MAX_VALUE);
+        let (lat_o, lon_o) = (odd.lat as f64 / MAX_VALUE, odd.lon as f64 / MAX_VALUE);
+
+        let j = (59.0 * lat_e - 60.0 * lat_o + 0.5).floor();
+        let mut rlat_e = 360.0 / 60.0 * (modulo(j, 60.0) + lat_e);
+        let mut rlat_o = 360.0 / 59.0 * (modulo(j, 59.0) + lat_o);
+        if rlat_e >= 270.0 { rlat_e -= 360.0; }
+        if rlat_o >= 270.0 { rlat_o -= 360.0; }
+
+        if nl(rlat_e) != nl(rlat_o) { return None; } // frames straddle a zone boundary
+
+        let (lat, ni, lon_cpr) = if even.t >= odd.t {
+            (rlat_e, nl(rlat_e).max(1), lon_e)
+        } else {
+            (rlat_o, (nl(rlat_o) - 1).max(1), lon_o)
+        };
+        if lat.abs() > 90.0 { return None; }
+
+        let nl_lat = nl(lat) as f64;
+        let m = (lon_e * (nl_lat - 1.0) - lon_o * nl_lat + 0.5).floor();
+        let lon = 360.0 / ni as f64 * (modulo(m, ni as f64) + lon_cpr);
+
+        Some(LatLon{ lat: Deg(lat), lon: Deg(normalize_lon(lon)) })
+    }
+
+    /// Decodes position from a single frame using a reference position. The reference must be within
+    /// 180 NM (airborne) or 45 NM (surface) of the actual position; results farther away are rejected.
+    pub fn decode_local(frame: &Frame, reference: &LatLon) -> Option<LatLon> {
+        let i = if frame.odd { 1.0 } else { 0.0 };
+        let (lat_cpr, lon_cpr) = (frame.lat as f64 / MAX_VALUE, frame.lon as f64 / MAX_VALUE);
+        let (ref_lat, ref_lon) = (reference.lat.0, reference.lon.0);
+
+        let dlat = frame.zone_span() / (4.0 * NZ - i);
+        let j = (ref_lat / dlat).floor() + (0.5 + modulo(ref_lat, dlat) / dlat - lat_cpr).floor();
+        let lat = dlat * (j + lat_cpr);
+        if lat.abs() > 90.0 { return None; }
+
+        let ni = (nl(lat) - i as i32).max(1);
+        let dlon = frame.zone_span() / ni as f64;
+        let m = (ref_lon / dlon).floor() + (0.5 + modulo(ref_lon, dlon) / dlon - lon_cpr).floor();
+        let lon = dlon * (m + lon_cpr);
+
+        let position = LatLon{ lat: Deg(lat), lon: Deg(normalize_lon(lon)) };
+        if arc_distance(&position, reference) > if frame.surface { MAX_LOCAL_RANGE / 4.0 } else { MAX_LOCAL_RANGE } {
+            return None;
+        }
+
+        Some(position)
+    }
+}
+
+/// Decoder state of a single aircraft.
+struct AircraftState {
+    /// Time of last frame with verified CRC.
+    t_last_seen: std::time::Instant,
+    even: Option<cpr::Frame>,
+    odd: Option<cpr::Frame>,
+    last_position: Option<(LatLon, std::time::Instant)>
+}
+
+/// Decodes Mode S frames; keeps per-aircraft state needed to resolve CPR-encoded positions.
+pub struct Decoder {
+    /// Reference position for local CPR decoding (typically the observer location).
+    reference: LatLon,
+    aircraft: HashMap<ModeSTransponderCode, AircraftState>,
+    t_last_cleanup: std::time::Instant
+}
+
+impl Decoder {
+    pub fn new(reference: LatLon) -> Decoder {
+        Decoder{ reference, aircraft: HashMap::new(), t_last_cleanup: std::time::Instant::now() }
+    }
+
+    /// Decodes a frame (without the surrounding framing of the transport protocol).
+    ///
+    /// Returns `None` for unsupported, corrupted or (as yet) undecodable frames.
+    pub fn decode(&mut self, frame: &[u8]) -> Option<SbsMessage> {
+        if self.t_last_cleanup.elapsed() > CLEANUP_INTERVAL {
+            self.aircraft.retain(|_, state| state.t_last_seen.elapsed() <= MAX_STATE_AGE);
+            self.t_last_cleanup = std::time::Instant::now();
+        }
+
+        if frame.is_empty() { return None; }
+        let df = bits(frame, 1, 5);
+        let expected_len = if df & 0x10 != 0 { LONG_FRAME_LEN } else { SHORT_FRAME_LEN };
+        if frame.len() != expected_len { return None; }
+
+        match df {
+            downlink_format::ALL_CALL_REPLY => self.decode_all_call_reply(frame),
+
+            downlink_format::EXTENDED_SQUITTER | downlink_format::EXTENDED_SQUITTER_NON_TRANSPONDER =>
+                self.decode_extended_squitter(frame),
+
+            downlink_format::SHORT_AIR_AIR_SURVEILLANCE
+            | downlink_format::LONG_AIR_AIR_SURVEILLANCE
+            | downlink_format::SURVEILLANCE_ALTITUDE_REPLY
+            | downlink_format::SURVEILLANCE_IDENTITY_REPLY
+            | downlink_format::COMM_B_ALTITUDE_REPLY
+            | downlink_format::COMM_B_IDENTITY_REPLY => self.decode_surveillance_reply(df, frame),
+
+            _ => None
+        }
+    }
+
+    fn mark_seen(&mut self, id: ModeSTransponderCode) {
+        let state = self.aircraft.entry(id).or_insert(AircraftState{
+            t_last_seen: std::time::Instant::now(),
+            even: None,
+            odd: None,
+            last_position: None
+        });
+        state.t_last_seen = std::time::Instant::now();
+    }
+
+    fn decode_all_call_reply(&mut self, frame: &[u8]) -> Option<SbsMessage> {
+        // parity may be overlaid with the interrogator code (lowest 7 bits)
+        if parity_syndrome(frame) & !0x7F != 0 { return None; }
+
+        let id = ModeSTransponderCode::new(bits(frame, 9, 32));
+        self.mark_seen(id);
+
+        let on_ground = match bits(frame, 6, 8) {
+            capability::ON_GROUND => Some(true),
+            capability::AIRBORNE => Some(false),
+            _ => None
+        };
+
+        Some(SbsMessage::AllCallReply{ id, on_ground })
+    }
+
+    /// Decodes a reply whose address is overlaid on parity; only replies from already known aircraft are accepted.
+    fn decode_surveillance_reply(&mut self, df: u32, frame: &[u8]) -> Option<SbsMessage> {
+        let id = ModeSTransponderCode::new(parity_syndrome(frame));
+        if !self.aircraft.contains_key(&id) { return None; }
+
+        let field13 = bits(frame, 20, 32);
+
+        match df {
+            downlink_format::SHORT_AIR_AIR_SURVEILLANCE | downlink_format::LONG_AIR_AIR_SURVEILLANCE => {
+                let on_ground = Some(bits(frame, 6, 6) == 1);
+                Some(SbsMessage::AirToAir{ id, altitude: decode_ac13(field13), on_ground })
+            },
+
+            downlink_format::SURVEILLANCE_ALTITUDE_REPLY | downlink_format::COMM_B_ALTITUDE_REPLY =>
+                decode_ac13(field13).map(|altitude| SbsMessage::SurveillanceAltitude{ id, altitude }),
+
+            downlink_format::SURVEILLANCE_IDENTITY_REPLY | downlink_format::COMM_B_IDENTITY_REPLY => {
+                let (alert, spi, on_ground) = match bits(frame, 6, 8) {
+                    0 => (false, false, Some(false)),
+                    1 => (false, false, Some(true)),
+                    2 => (true, false, Some(false)),
+                    3 => (true, false, Some(true)),
+                    4 => (true, true, None),
+                    5 => (false, true, None),
+                    _ => return None
+                };
+
+                Some(SbsMessage::SurveillanceId{
+                    id,
+                    altitude: None,
+                    squawk: mode_a_to_squawk(id13_to_mode_a(field13)),
+                    alert: Some(alert),
+                    emergency: None,
+                    spi: Some(spi),
+                    on_ground
+                })
+            },
+
+            _ => None
+        }
+    }
+
+    fn decode_extended_squitter(&mut self, frame: &[u8]) -> Option<SbsMessage> {
+        if parity_syndrome(frame) != 0 { return None; }
+
+        if bits(frame, 1, 5) == downlink_format::EXTENDED_SQUITTER_NON_TRANSPONDER
+            && bits(frame, 6, 8) != CF_ADS_B_ICAO_ADDRESS {
+
+            return None;
+        }
+
+        let id = ModeSTransponderCode::new(bits(frame, 9, 32));
+        self.mark_seen(id);
+
+        match bits(frame, 33, 37) {
+            1..=4 => decode_identification(id, frame),
+            5..=8 => self.decode_surface_position(id, frame),
+            9..=18 => self.decode_airborne_position(id, frame, true),
+            20..=22 => self.decode_airborne_position(id, frame, false),
+            19 => decode_airborne_velocity(id, frame),
+            _ => None
+        }
+    }
+
+    /// Resolves position from the CPR frame contained in `frame`.
+    fn resolve_position(&mut self, id: ModeSTransponderCode, frame: &[u8], surface: bool) -> Option<LatLon> {
+        let now = std::time::Instant::now();
+        let cpr_frame = cpr::Frame{
+            odd: bits(frame, 54, 54) == 1,
+            surface,
+            lat: bits(frame, 55, 71),
+            lon: bits(frame, 72, 88),
+            t: now
+        };
+
+        let reference = self.reference.clone();
+        let state = self.aircraft.get_mut(&id).unwrap();
+
+        if cpr_frame.odd { state.odd = Some(cpr_frame.clone()); } else { state.even = Some(cpr_frame.clone()); }
+
+        let global = match (&state.even, &state.odd) {
+            (Some(even), Some(odd)) if even.surface == surface && odd.surface == surface => cpr::decode_global(even, odd),
+            _ => None
+        };
+
+        let position = match global {
+            Some(position) => Some(position),
+            None => match &state.last_position {
+                Some((last_pos, t)) if now - *t <= MAX_LOCAL_REF_AGE => cpr::decode_local(&cpr_frame, last_pos),
+                // surface positions are never decoded globally; assumes the receiver's surface range is below 45 NM
+                _ if surface => cpr::decode_local(&cpr_frame, &reference),
+                // the receiver's range may exceed 180 NM, so the first airborne position requires an even/odd pair
+                _ => None
+            }
+        };
+
+        if let Some(position) = &position {
+            state.last_position = Some((position.clone(), now));
+        }
+
+        position
+    }
+
+    fn decode_surface_position(&mut self, id: ModeSTransponderCode, frame: &[u8]) -> Option<SbsMessage> {
+        let ground_speed = decode_movement(bits(frame, 38, 44));
+        let track = if bits(frame, 45, 45) == 1 {
+            Some(Deg(bits(frame, 46, 52) as f64 * 360.0 / 128.0))
+        } else {
+            None
+        };
+        let lat_lon = self.resolve_position(id, frame, true);
+
+        Some(SbsMessage::EsSurfacePosition{ id, altitude: None, ground_speed, track, lat_lon })
+    }
+
+    /// Decodes an airborne position message; the altitude field is used only if it contains barometric altitude
+    /// (not GNSS height).
+    fn decode_airborne_position(&mut self, id: ModeSTransponderCode, frame: &[u8], barometric: bool) -> Option<SbsMessage> {
+        let altitude = if barometric { decode_ac12(bits(frame, 41, 52)) } else { None };
+        let lat_lon = self.resolve_position(id, frame, false);
+
+        Some(SbsMessage::EsAirbornePosition{ id, altitude, lat_lon })
+    }
+}
+
+fn decode_identification(id: ModeSTransponderCode, frame: &[u8]) -> Option<SbsMessage> {
+    let callsign: String = (0..8)
+        .map(|i| CALLSIGN_CHARS[bits(frame, 41 + 6 * i, 46 + 6 * i) as usize] as char)
+        .collect();
+
+    let callsign = callsign.trim_end_matches([' ', '#']);
+    if callsign.is_empty() || callsign.contains('#') { return None; }
+
+    Some(SbsMessage::EsIdentificationAndCategory{ id, callsign: callsign.into() })
+}
+
+/// Decodes the 7-bit movement field of a surface position message.
+fn decode_movement(value: u32) -> Option<f64::Velocity> {
+    let kt = match value {
+        1 => 0.0,
+        2..=8 => 0.125 * (value - 1) as f64,
+        9..=12 => 1.0 + 0.25 * (value - 9) as f64,
+        13..=38 => 2.0 + 0.5 * (value - 13) as f64,
+        39..=93 => 15.0 + (value - 39) as f64,
+        94..=108 => 70.0 + 2.0 * (value - 94) as f64,
+        109..=123 => 100.0 + 5.0 * (value - 109) as f64,
+        124 => 175.0,
+        _ => return None
+    };
+
+    Some(knots(kt))
+}
+
+fn decode_airborne_velocity(id: ModeSTransponderCode, frame: &[u8]) -> Option<SbsMessage> {
+    let subtype = bits(frame, 38, 40);
+    let multiplier = match subtype {
+        1 => 1.0,
+        2 => 4.0, // supersonic
+        _ => return None // airspeed and heading only
+    };
+
+    let v_ew = bits(frame, 47, 56);
+    let v_ns = bits(frame, 58, 67);
+    if v_ew == 0 || v_ns == 0 { return None; }
+
+    let sign = |bit: u32| if bit == 1 { -1.0 } else { 1.0 };
+    let v_east = sign(bits(frame, 46, 46)) * multiplier * (v_ew - 1) as f64;
+    let v_north = sign(bits(frame, 57, 57)) * multiplier * (v_ns - 1) as f64;
+
+    let ground_speed = knots((v_east * v_east + v_north * v_north).sqrt());
+    let mut track = v_east.atan2(v_north).to_degrees();
+    if track < 0.0 { track += 360.0; }
+
+    // 0: no information; otherwise in units of 64 ft/min, offset by 1
+    let vr = bits(frame, 70, 78);
+    let vertical_rate = if vr == 0 {
+        None
+    } else {
+        Some(f64::Velocity::new::<velocity::foot_per_minute>(sign(bits(frame, 69, 69)) * 64.0 * (vr - 1) as f64))
+    };
+
+    Some(SbsMessage::EsAirborneVelocity{ id, ground_speed, track: Deg(track), vertical_rate })
+}
+
+#[cfg(test)]
+mod tests {
+    // reference frames from "The 1090 Megahertz Riddle" (J. Sun), https://mode-s.org/decode/
+    use super::*;
+
+    fn frame(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn position(lat: f64, lon: f64) -> LatLon {
+        LatLon{ lat: Deg(lat), lon: Deg(lon) }
+    }
+
+    fn assert_near(expected: &LatLon, actual: &LatLon, tolerance: f64) {
+        assert!(
+            (expected.lat.0 - actual.lat.0).abs() < tolerance && (expected.lon.0 - actual.lon.0).abs() < tolerance,
+            "expected {:?}, got {:?}", expected, actual
+        );
+    }
+
+    const EVEN_POSITION: &str = "8D40621D58C382D690C8AC2863A7";
+    const ODD_POSITION: &str = "8D40621D58C386435CC412692AD6";
+
+    fn cpr_frame(hex: &str, surface: bool) -> cpr::Frame {
+        let frame = frame(hex);
+        cpr::Frame{
+            odd: bits(&frame, 54, 54) == 1,
+            surface,
+            lat: bits(&frame, 55, 71),
+            lon: bits(&frame, 72, 88),
+            t: std::time::Instant::now()
+        }
+    }
+
+    #[test]
+    fn checks_crc() {
+        assert_eq!(0, parity_syndrome(&frame(EVEN_POSITION)));
+        let mut corrupted = frame(EVEN_POSITION);
+        corrupted[6] ^= 0x04;
+        assert!(Decoder::new(position(52.0, 4.0)).decode(&corrupted).is_none());
+    }
+
+    #[test]
+    fn decodes_identification() {
+        match Decoder::new(position(0.0, 0.0)).decode(&frame("8D4840D6202CC371C32CE0576098")) {
+            Some(SbsMessage::EsIdentificationAndCategory{ id, callsign }) => {
+                assert_eq!(ModeSTransponderCode::new(0x4840D6), id);
+                assert_eq!("KLM1023", callsign);
+            },
+            other => panic!("unexpected message: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn decodes_position_globally() {
+        // the observer is far away, so the first odd frame alone cannot be resolved
+        let mut decoder = Decoder::new(position(0.0, 0.0));
+
+        match decoder.decode(&frame(ODD_POSITION)) {
+            Some(SbsMessage::EsAirbornePosition{ altitude, lat_lon, .. }) => {
+                assert_eq!(Some(38000.0), altitude.map(|a| a.get::<length::foot>()));
+                assert!(lat_lon.is_none());
+            },
+            other => panic!("unexpected message: {:?}", other)
+        }
+
+        match decoder.decode(&frame(EVEN_POSITION)) {
+            Some(SbsMessage::EsAirbornePosition{ lat_lon: Some(lat_lon), .. }) =>
+                assert_near(&position(52.2572, 3.91937), &lat_lon, 1.0e-4),
+            other => panic!("unexpected message: {:?}", other)
+        }
+
+        // subsequent frames are decoded locally against the last position
+        match decoder.decode(&frame(ODD_POSITION)) {
+            Some(SbsMessage::EsAirbornePosition{ lat_lon: Some(lat_lon), .. }) =>
+                assert_near(&position(52.2658, 3.9389), &lat_lon, 1.0e-3),
+            other => panic!("unexpected message: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn decodes_position_locally() {
+        let decoded = cpr::decode_local(&cpr_frame(EVEN_POSITION, false), &position(52.258, 3.918)).unwrap();
+        assert_near(&position(52.2572, 3.91937), &decoded, 1.0e-4);
+    }
+
+    #[test]
+    fn rejects_local_position_out_of_range() {
+        assert!(cpr::decode_local(&cpr_frame(ODD_POSITION, false), &position(0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn decodes_surface_position() {
+        let mut decoder = Decoder::new(position(51.990, 4.375));
+
+        match decoder.decode(&frame("8C4841753A8A35323FAEBDAC702D")) {
+            Some(SbsMessage::EsSurfacePosition{ lat_lon: Some(lat_lon), .. }) =>
+                assert_near(&position(52.32061, 4.73473), &lat_lon, 1.0e-4),
+            other => panic!("unexpected message: {:?}", other)
+        }
+
+        match decoder.decode(&frame("8C4841753A9A153237AEF0F275BE")) {
+            Some(SbsMessage::EsSurfacePosition{ ground_speed, track, .. }) => {
+                assert!((ground_speed.unwrap().get::<velocity::knot>() - 17.0).abs() < 1.0e-9);
+                assert!((track.unwrap().0 - 92.8125).abs() < 1.0e-9);
+            },
+            other => panic!("unexpected message: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn ignores_gnss_height() {
+        // change type code to 20 (airborne position with GNSS height)
+        let mut frame = frame(EVEN_POSITION);
+        frame[4] = (20 << 3) | (frame[4] & 0x07);
+        let parity = crc(&frame[..11]);
+        frame[11..].copy_from_slice(&parity.to_be_bytes()[1..]);
+
+        match Decoder::new(position(52.258, 3.918)).decode(&frame) {
+            Some(SbsMessage::EsAirbornePosition{ altitude, .. }) => assert!(altitude.is_none()),
+            other => panic!("unexpected message: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn decodes_airborne_velocity() {
+        match Decoder::new(position(0.0, 0.0)).decode(&frame("8D485020994409940838175B284F")) {
+            Some(SbsMessage::EsAirborneVelocity{ ground_speed, track, vertical_rate, .. }) => {
+                assert!((ground_speed.get::<velocity::knot>() - 159.20).abs() < 0.01);
+                assert!((track.0 - 182.88).abs() < 0.01);
+                assert!((vertical_rate.unwrap().get::<velocity::foot_per_minute>() + 832.0).abs() < 1.0e-9);
+            },
+            other => panic!("unexpected message: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn decodes_altitude() {
+        // 38000 ft in the 12-bit field of the reference position frame (Q bit set)
+        assert_eq!(Some(38000.0), decode_ac12(bits(&frame(EVEN_POSITION), 41, 52)).map(|a| a.get::<length::foot>().round()));
+        // 13-bit field with 25 ft resolution
+        assert_eq!(Some(1000.0), decode_ac13(0x0130).map(|a| a.get::<length::foot>().round()));
+        // metric altitude is not supported
+        assert_eq!(None, decode_ac13(0x0170));
+    }
+}
@@ -0,0 +1,385 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use cgmath::{Angle, Deg, Point2};
+use crate::data::project;
+use pointing_utils::LatLon;
+use serde_json::Value;
+use std::error::Error;
+
+/// Axis-aligned rectangle in the projected map plane (meters).
+#[derive(Copy, Clone, Debug)]
+pub struct BoundingBox {
+    pub min: Point2<f64>,
+    pub max: Point2<f64>
+}
+
+impl BoundingBox {
+    fn of(points: &[Point2<f64>]) -> BoundingBox {
+        let mut bbox = BoundingBox{
+            min: Point2{ x: f64::INFINITY, y: f64::INFINITY },
+            max: Point2{ x: f64::NEG_INFINITY, y: f64::NEG_INFINITY }
+        };
+        for p in points {
+            bbox.min.x = bbox.min.x.min(p.x);
+            bbox.min.y = bbox.min.y.min(p.y);
+            bbox.max.x = bbox.max.x.max(p.x);
+            bbox.max.y = bbox.max.y.max(p.y);
+        }
+        bbox
+    }
+
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.min.x <= other.max.x && self.max.x >= other.min.x && self.min.y <= other.max.y && self.max.y >= other.min.y
+    }
+}
+
+/// Polyline or polygon ring, projected (see `data::project`).
+#[derive(Clone, Debug)]
+pub struct Path {
+    pub points: Vec<Point2<f64>>,
+    pub closed: bool,
+    pub bbox: BoundingBox
+}
+
+/// Vector map layer (e.g., coastlines, borders, airspace boundaries) projected around the observer once on loading,
+/// so that drawing needs no further conversions.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    pub paths: Vec<Path>,
+    /// Point features.
+    pub points: Vec<Point2<f64>>
+}
+
+impl Layer {
+    /// Loads a GeoJSON file or (if the extension is ".shp") an ESRI shapefile; coordinates are expected
+    /// to be WGS84 longitude and latitude (any ".prj" file is ignored).
+    pub fn load(path: &std::path::Path, observer: &LatLon) -> Result<Layer, Box<dyn Error>> {
+        let mut builder = LayerBuilder{ observer, layer: Layer{ paths: vec![], points: vec![] } };
+
+        let is_shapefile = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("shp"));
+        if is_shapefile {
+            builder.add_shapefile(&std::fs::read(path)?)?;
+        } else {
+            let json: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            builder.add_geojson(&json)?;
+        }
+
+        Ok(builder.layer)
+    }
+
+    /// Returns the bounding box of all paths and points (`None` if the layer is empty).
+    pub fn bbox(&self) -> Option<BoundingBox> {
+        if self.paths.is_empty() && self.points.is_empty() { return None; }
+
+        let mut corners: Vec<Point2<f64>> = self.paths.iter().flat_map(|p| [p.bbox.min, p.bbox.max]).collect();
+        corners.extend_from_slice(&self.points);
+        Some(BoundingBox::of(&corners))
+    }
+}
+
+/// Returns `None` for points on the far side of the Earth (which would overlap the near side in projection).
+fn project_lon_lat(observer: &LatLon, lon: f64, lat: f64) -> Option<Point2<f64>> {
+    let lat_lon = LatLon{ lat: Deg(lat), lon: Deg(lon) };
+    let cos_distance = observer.lat.sin() * lat_lon.lat.sin()
+        + observer.lat.cos() * lat_lon.lat.cos() * (lat_lon.lon - observer.lon).cos();
+
+    if cos_distance < 0.0 { None } else { Some(project(observer, &lat_lon)) }
+}
+
+struct LayerBuilder<'a> {
+    observer: &'a LatLon,
+    layer: Layer
+}
+
+impl<'a> LayerBuilder<'a> {
+    /// Hidden (`None`) points split the path.
+    fn add_path(&mut self, points: Vec<Option<Point2<f64>>>, closed: bool) {
+        let closed = closed && points.iter().all(|p| p.is_some());
+        for run in points.split(|p| p.is_none()) {
+            if run.len() < 2 { continue; }
+            let points: Vec<Point2<f64>> = run.iter().map(|p| p.unwrap()).collect();
+            let bbox = BoundingBox::of(&points);
+            self.layer.paths.push(Path{ points, closed, bbox });
+        }
+    }
+
+    fn add_points(&mut self, points: Vec<Option<Point2<f64>>>) {
+        self.layer.points.extend(points.into_iter().flatten());
+    }
+
+    fn add_geojson(&mut self, value: &Value) -> Result<(), Box<dyn Error>> {
+        let object_type = value["type"].as_str().ok_or("missing GeoJSON object type")?;
+        match object_type {
+            "FeatureCollection" => {
+                for feature in value["features"].as_array().ok_or("missing features")? {
+                    self.add_geojson(feature)?;
+                }
+            },
+
+            "Feature" => if !value["geometry"].is_null() { self.add_geojson(&value["geometry"])?; },
+
+            "GeometryCollection" => {
+                for geometry in value["geometries"].as_array().ok_or("missing geometries")? {
+                    self.add_geojson(geometry)?;
+                }
+            },
+
+            "Point" => {
+                let p = self.geojson_position(&value["coordinates"])?;
+                self.add_points(vec![p]);
+            },
+
+            "MultiPoint" => {
+                let points = self.geojson_positions(&value["coordinates"])?;
+                self.add_points(points);
+            },
+
+            "LineString" => {
+                let points = self.geojson_positions(&value["coordinates"])?;
+                self.add_path(points, false);
+            },
+
+            "MultiLineString" | "Polygon" => {
+                for line in value["coordinates"].as_array().ok_or("invalid coordinates")? {
+                    let points = self.geojson_positions(line)?;
+                    self.add_path(points, object_type == "Polygon");
+                }
+            },
+
+            "MultiPolygon" => {
+                for polygon in value["coordinates"].as_array().ok_or("invalid coordinates")? {
+                    for ring in polygon.as_array().ok_or("invalid coordinates")? {
+                        let points = self.geojson_positions(ring)?;
+                        self.add_path(points, true);
+                    }
+                }
+            },
+
+            _ => return Err(format!("unsupported GeoJSON object type \"{}\"", object_type).into())
+        }
+
+        Ok(())
+    }
+
+    /// Parses "[<lon.>, <lat.>(, <altitude>)]".
+    fn geojson_position(&self, value: &Value) -> Result<Option<Point2<f64>>, Box<dyn Error>> {
+        let values = value.as_array().ok_or("invalid position")?;
+        match (values.first().and_then(|v| v.as_f64()), values.get(1).and_then(|v| v.as_f64())) {
+            (Some(lon), Some(lat)) => Ok(project_lon_lat(self.observer, lon, lat)),
+            _ => Err("invalid position".into())
+        }
+    }
+
+    fn geojson_positions(&self, value: &Value) -> Result<Vec<Option<Point2<f64>>>, Box<dyn Error>> {
+        value.as_array().ok_or("invalid coordinates")?.iter().map(|p| self.geojson_position(p)).collect()
+    }
+
+    /// Reads the main (".shp") file of a shapefile; all shape types except MultiPatch are supported
+    /// (Z and M values are ignored).
+    fn add_shapefile(&mut self, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+        const HEADER_LEN: usize = 100;
+        const FILE_CODE: u32 = 9994;
+
+        if contents.len() < HEADER_LEN || read_u32_be(contents, 0) != Some(FILE_CODE) {
+            return Err("not a shapefile".into());
+        }
+
+        let mut offset = HEADER_LEN;
+        while offset + 8 <= contents.len() {
+            // record header: record number and content length (16-bit words), big-endian
+            let length = 2 * read_u32_be(contents, offset + 4).unwrap() as usize;
+            let record = contents.get(offset + 8..offset + 8 + length).ok_or("truncated record")?;
+            self.add_shape(record)?;
+            offset += 8 + length;
+        }
+
+        Ok(())
+    }
+
+    fn add_shape(&mut self, record: &[u8]) -> Result<(), Box<dyn Error>> {
+        const INVALID: &str = "invalid shape record";
+
+        let shape_type = read_u32_le(record, 0).ok_or(INVALID)?;
+        let observer = self.observer;
+        let point = |index: usize, base: usize| -> Result<Option<Point2<f64>>, Box<dyn Error>> {
+            let x = read_f64_le(record, base + 16 * index).ok_or(INVALID)?;
+            let y = read_f64_le(record, base + 16 * index + 8).ok_or(INVALID)?;
+            Ok(project_lon_lat(observer, x, y))
+        };
+
+        match shape_type {
+            0 => (), // null shape
+
+            1 | 11 | 21 => {
+                let p = point(0, 4)?;
+                self.add_points(vec![p]);
+            },
+
+            8 | 18 | 28 => {
+                // shape type, bounding box, number of points, points
+                let num_points = read_u32_le(record, 36).ok_or(INVALID)? as usize;
+                let points = (0..num_points).map(|i| point(i, 40)).collect::<Result<Vec<_>, _>>()?;
+                self.add_points(points);
+            },
+
+            3 | 13 | 23 | 5 | 15 | 25 => {
+                // shape type, bounding box, number of parts, number of points, part start indices, points
+                let num_parts = read_u32_le(record, 36).ok_or(INVALID)? as usize;
+                let num_points = read_u32_le(record, 40).ok_or(INVALID)? as usize;
+                let points_base = 44 + 4 * num_parts;
+                let starts = (0..num_parts)
+                    .map(|i| read_u32_le(record, 44 + 4 * i).map(|s| s as usize).ok_or(INVALID))
+                    .collect::<Result<Vec<usize>, _>>()?;
+
+                let closed = matches!(shape_type, 5 | 15 | 25);
+                for (i, start) in starts.iter().enumerate() {
+                    let end = starts.get(i + 1).copied().unwrap_or(num_points);
+                    if *start > end || end > num_points { return Err(INVALID.into()); }
+                    let points = (*start..end).map(|j| point(j, points_base)).collect::<Result<Vec<_>, _>>()?;
+                    self.add_path(points, closed);
+                }
+            },
+
+            _ => return Err(format!("unsupported shape type {}", shape_type).into())
+        }
+
+        Ok(())
+    }
+}
+
+fn read_u32_be(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().unwrap()))
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().unwrap()))
+}
+
+fn read_f64_le(data: &[u8], offset: usize) -> Option<f64> {
+    Some(f64::from_le_bytes(data.get(offset..offset + 8)?.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observer() -> LatLon {
+        LatLon{ lat: Deg(52.0), lon: Deg(21.0) }
+    }
+
+    fn geojson_layer(json: &str) -> Result<Layer, Box<dyn Error>> {
+        let observer = observer();
+        let mut builder = LayerBuilder{ observer: &observer, layer: Layer{ paths: vec![], points: vec![] } };
+        builder.add_geojson(&serde_json::from_str(json)?)?;
+        Ok(builder.layer)
+    }
+
+    fn shapefile_layer(records: &[Vec<u8>]) -> Result<Layer, Box<dyn Error>> {
+        let mut contents = vec![0u8; 100];
+        contents[..4].copy_from_slice(&9994u32.to_be_bytes());
+        for (i, record) in records.iter().enumerate() {
+            contents.extend_from_slice(&(i as u32 + 1).to_be_bytes());
+            contents.extend_from_slice(&(record.len() as u32 / 2).to_be_bytes());
+            contents.extend_from_slice(record);
+        }
+
+        let observer = observer();
+        let mut builder = LayerBuilder{ observer: &observer, layer: Layer{ paths: vec![], points: vec![] } };
+        builder.add_shapefile(&contents)?;
+        Ok(builder.layer)
+    }
+
+    /// Returns a PolyLine (3) or Polygon (5) record with a single part.
+    fn single_part_shape(shape_type: u32, lon_lat: &[(f64, f64)]) -> Vec<u8> {
+        let mut record = shape_type.to_le_bytes().to_vec();
+        record.extend_from_slice(&[0u8; 32]); // bounding box
+        record.extend_from_slice(&1u32.to_le_bytes());
+        record.extend_from_slice(&(lon_lat.len() as u32).to_le_bytes());
+        record.extend_from_slice(&0u32.to_le_bytes());
+        for (lon, lat) in lon_lat {
+            record.extend_from_slice(&lon.to_le_bytes());
+            record.extend_from_slice(&lat.to_le_bytes());
+        }
+        record
+    }
+
+    #[test]
+    fn geojson() {
+        let layer = geojson_layer(r#"{
+            "type": "FeatureCollection",
+            "features": [
+                { "type": "Feature", "properties": {}, "geometry": { "type": "Point", "coordinates": [21.0, 52.0, 100.0] } },
+                { "type": "Feature", "properties": {}, "geometry": null },
+                { "type": "Feature", "geometry": { "type": "LineString", "coordinates": [[21.0, 52.0], [21.1, 52.1]] } },
+                { "type": "Feature", "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[20.0, 51.0], [22.0, 51.0], [22.0, 53.0], [20.0, 51.0]]]
+                } }
+            ]
+        }"#).unwrap();
+
+        assert_eq!(1, layer.points.len());
+        assert!(layer.points[0].x.abs() < 1.0e-6 && layer.points[0].y.abs() < 1.0e-6);
+        assert_eq!(2, layer.paths.len());
+        assert!(!layer.paths[0].closed && layer.paths[0].points.len() == 2);
+        assert!(layer.paths[1].closed && layer.paths[1].points.len() == 4);
+        // north-east of the observer
+        assert!(layer.paths[0].points[1].x > 0.0 && layer.paths[0].points[1].y > 0.0);
+    }
+
+    #[test]
+    fn geojson_far_side_splits_path() {
+        let layer = geojson_layer(r#"{
+            "type": "MultiLineString",
+            "coordinates": [[[21.0, 52.0], [21.0, 53.0], [-159.0, 0.0], [21.0, 51.0], [21.0, 50.0]]]
+        }"#).unwrap();
+
+        assert_eq!(2, layer.paths.len());
+        assert!(layer.paths.iter().all(|p| p.points.len() == 2 && !p.closed));
+    }
+
+    #[test]
+    fn geojson_errors() {
+        assert!(geojson_layer(r#"{ "type": "Circle", "coordinates": [21.0, 52.0] }"#).is_err());
+        assert!(geojson_layer(r#"{ "type": "Point", "coordinates": [21.0] }"#).is_err());
+        assert!(geojson_layer(r#"{ "coordinates": [21.0, 52.0] }"#).is_err());
+    }
+
+    #[test]
+    fn shapefile() {
+        let mut point = 1u32.to_le_bytes().to_vec();
+        point.extend_from_slice(&21.0f64.to_le_bytes());
+        point.extend_from_slice(&52.0f64.to_le_bytes());
+
+        let layer = shapefile_layer(&[
+            point,
+            0u32.to_le_bytes().to_vec(), // null shape
+            single_part_shape(3, &[(21.0, 52.0), (21.1, 52.0), (21.1, 52.1)]),
+            single_part_shape(5, &[(20.0, 51.0), (22.0, 51.0), (22.0, 53.0), (20.0, 51.0)])
+        ]).unwrap();
+
+        assert_eq!(1, layer.points.len());
+        assert_eq!(2, layer.paths.len());
+        assert!(!layer.paths[0].closed && layer.paths[0].points.len() == 3);
+        assert!(layer.paths[1].closed && layer.paths[1].points.len() == 4);
+    }
+
+    #[test]
+    fn shapefile_errors() {
+        assert!(shapefile_layer(&[31u32.to_le_bytes().to_vec()]).is_err()); // MultiPatch
+
+        let mut truncated = single_part_shape(3, &[(21.0, 52.0), (21.1, 52.0)]);
+        truncated.truncate(truncated.len() - 8);
+        assert!(shapefile_layer(&[truncated]).is_err());
+
+        let observer = observer();
+        let mut builder = LayerBuilder{ observer: &observer, layer: Layer{ paths: vec![], points: vec![] } };
+        assert!(builder.add_shapefile(&[0u8; 100]).is_err());
+    }
+}
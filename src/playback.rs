@@ -0,0 +1,379 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use crate::program_data::ProgramData;
+use gtk4 as gtk;
+use gtk::{glib, glib::clone};
+use plane_tracker::{data, mode_s, sbs};
+use pointing_utils::LatLon;
+use std::{cell::RefCell, error::Error, io::prelude::*, rc::Rc, sync::{Arc, atomic::{AtomicUsize, Ordering}, mpsc}};
+
+/// Data source label of messages read from a recording.
+pub const SOURCE_LABEL: &str = "playback";
+
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+/// Maximum number of entries played at once at `Speed::Max`.
+const MAX_SPEED_BATCH: usize = 200;
+/// Maximum number of played messages not yet processed by the main thread; playback waits until it catches up.
+const MAX_PENDING_MESSAGES: usize = 1000;
+/// Interval of checking whether the main thread has caught up.
+const PENDING_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(5);
+
+static NEXT_PLAYER_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Speed {
+    X1,
+    X2,
+    X10,
+    /// As fast as possible.
+    Max
+}
+
+impl Speed {
+    pub const ALL: [Speed; 4] = [Speed::X1, Speed::X2, Speed::X10, Speed::Max];
+
+    fn factor(&self) -> Option<f64> {
+        match self {
+            Speed::X1 => Some(1.0),
+            Speed::X2 => Some(2.0),
+            Speed::X10 => Some(10.0),
+            Speed::Max => None
+        }
+    }
+}
+
+impl std::fmt::Display for Speed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Speed::X1 => "1x",
+            Speed::X2 => "2x",
+            Speed::X10 => "10x",
+            Speed::Max => "max"
+        })
+    }
+}
+
+enum Command {
+    SetPaused(bool),
+    SetSpeed(Speed),
+    /// Entry index and the new seek generation.
+    Seek(usize, u64)
+}
+
+pub enum EventKind {
+    /// Message and its recorded time of reception.
+    Message(data::SbsMessage, chrono::NaiveDateTime),
+    /// Index of the next entry to be played.
+    Progress(usize),
+    Finished
+}
+
+pub struct PlaybackEvent {
+    /// Number of seeks performed before the event was sent; events from before the last seek are ignored.
+    seek_generation: u64,
+    kind: EventKind
+}
+
+/// Single line of a recording.
+pub struct Entry {
+    /// Time of reception.
+    pub t: chrono::NaiveDateTime,
+    /// Line of SBS message or frame in AVR format.
+    line: String
+}
+
+pub struct Player {
+    /// Unique among all players started during program run.
+    pub id: u64,
+    pub file_name: String,
+    pub entries: Arc<Vec<Entry>>,
+    worker: Option<std::thread::JoinHandle<()>>, // always `Some`
+    /// Dropped to make the worker finish.
+    command_sender: Option<mpsc::Sender<Command>>,
+    pub paused: bool,
+    pub speed: Speed,
+    /// Index of the next entry to be played.
+    pub position: usize,
+    /// Number of seeks performed.
+    seek_generation: u64
+}
+
+impl Player {
+    pub fn finished(&self) -> bool {
+        self.position >= self.entries.len()
+    }
+
+    /// Rate of advancing of the tracker's clock between recorded messages.
+    fn clock_rate(&self) -> f64 {
+        if self.paused { 0.0 } else { self.speed.factor().unwrap_or(0.0) }
+    }
+
+    fn send(&self, command: Command) {
+        let _ = self.command_sender.as_ref().unwrap().send(command);
+    }
+}
+
+/// Loads a recording consisting of lines "<timestamp>;<SBS message or AVR frame>".
+fn load(path: &std::path::Path) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+
+    let mut entries = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.is_empty() { continue; }
+
+        let (t, contents) = line.split_once(';').ok_or_else(|| format!("line {}: missing separator", i + 1))?;
+        let t = chrono::NaiveDateTime::parse_from_str(t, "%Y-%m-%d %H:%M:%S%.f")
+            .map_err(|e| format!("line {}: {}", i + 1, e))?;
+
+        entries.push(Entry{ t, line: contents.into() });
+    }
+
+    if entries.is_empty() { return Err("recording is empty".into()); }
+
+    Ok(entries)
+}
+
+/// Increments `pending` for each sent message.
+fn play_entry(
+    entry: &Entry,
+    decoder: &mut mode_s::Decoder,
+    seek_generation: u64,
+    pending: &AtomicUsize,
+    sender: &glib::Sender<PlaybackEvent>
+) {
+    let result = if entry.line.starts_with('*') || entry.line.starts_with('@') {
+        sbs::parse_avr_frame(&entry.line).map(|frame| decoder.decode(&frame))
+    } else {
+        sbs::parse_sbs_message(&entry.line)
+    };
+
+    match result {
+        Ok(Some(msg)) => {
+            pending.fetch_add(1, Ordering::Relaxed);
+            sender.send(PlaybackEvent{ seek_generation, kind: EventKind::Message(msg, entry.t) }).unwrap();
+        },
+        Ok(None) => (),
+        Err(e) => println!("Error parsing recorded message \"{}\": {}.", entry.line, e)
+    }
+}
+
+/// Plays `entries`, keeping their original time intervals (scaled by `speed`), but waiting whenever the receiver
+/// lags behind by `MAX_PENDING_MESSAGES` (`pending` is decremented by the receiver for each processed message).
+/// Finishes once the sending end of `commands` is dropped.
+fn player(
+    entries: Arc<Vec<Entry>>,
+    reference: LatLon,
+    mut speed: Speed,
+    commands: mpsc::Receiver<Command>,
+    pending: Arc<AtomicUsize>,
+    sender: glib::Sender<PlaybackEvent>
+) {
+    let mut decoder = mode_s::Decoder::new(reference.clone());
+    let mut index = 0;
+    let mut paused = false;
+    let mut seek_generation = 0;
+    let send = |seek_generation: u64, kind: EventKind| sender.send(PlaybackEvent{ seek_generation, kind }).unwrap();
+    // wall-clock time corresponding to the recording time of `entries[base_index]`
+    let mut t_base = std::time::Instant::now();
+    let mut base_index = 0;
+    let mut t_last_progress = std::time::Instant::now();
+
+    loop {
+        let due_in = |index: usize, speed: Speed, t_base: std::time::Instant, base_index: usize| {
+            match speed.factor() {
+                None => std::time::Duration::ZERO,
+                Some(factor) => {
+                    let dt = (entries[index].t - entries[base_index].t).to_std().unwrap_or(std::time::Duration::ZERO);
+                    (t_base + dt.div_f64(factor)).saturating_duration_since(std::time::Instant::now())
+                }
+            }
+        };
+
+        let command = if paused || index >= entries.len() {
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => break
+            }
+        } else {
+            let wait = if pending.load(Ordering::Relaxed) >= MAX_PENDING_MESSAGES {
+                PENDING_CHECK_INTERVAL
+            } else {
+                due_in(index, speed, t_base, base_index).min(PROGRESS_INTERVAL)
+            };
+            match commands.recv_timeout(wait) {
+                Ok(command) => Some(command),
+                Err(mpsc::RecvTimeoutError::Timeout) => None,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break
+            }
+        };
+
+        if let Some(command) = command {
+            match command {
+                Command::SetPaused(value) => paused = value,
+                Command::SetSpeed(value) => speed = value,
+                Command::Seek(value, generation) => {
+                    index = value.min(entries.len());
+                    seek_generation = generation;
+                    decoder = mode_s::Decoder::new(reference.clone());
+                    send(seek_generation, EventKind::Progress(index));
+                }
+            }
+            base_index = index.min(entries.len() - 1);
+            t_base = std::time::Instant::now();
+            continue;
+        }
+
+        let mut num_played = 0;
+        while index < entries.len()
+            && num_played < MAX_SPEED_BATCH
+            && pending.load(Ordering::Relaxed) < MAX_PENDING_MESSAGES
+            && due_in(index, speed, t_base, base_index).is_zero() {
+
+            play_entry(&entries[index], &mut decoder, seek_generation, &pending, &sender);
+            index += 1;
+            num_played += 1;
+        }
+
+        if index >= entries.len() {
+            send(seek_generation, EventKind::Progress(index));
+            send(seek_generation, EventKind::Finished);
+        } else if t_last_progress.elapsed() >= PROGRESS_INTERVAL {
+            send(seek_generation, EventKind::Progress(index));
+            t_last_progress = std::time::Instant::now();
+        }
+    }
+}
+
+fn on_playback_event(program_data_rc: &Rc<RefCell<ProgramData>>, player_id: u64, event: PlaybackEvent) {
+    let mut pd = program_data_rc.borrow_mut();
+
+    // ignore events still queued by an already stopped player or sent before the last seek
+    match pd.player.as_ref() {
+        Some(player) if player.id == player_id && player.seek_generation == event.seek_generation => (),
+        _ => return
+    }
+
+    match event.kind {
+        EventKind::Message(msg, t) => {
+            let rate = pd.player.as_ref().unwrap().clock_rate();
+            pd.tracker.clock.set_recorded_time(t, rate);
+            pd.update(msg, SOURCE_LABEL);
+        },
+
+        EventKind::Progress(index) => {
+            pd.player.as_mut().unwrap().position = index;
+            show_state(&pd);
+        },
+
+        EventKind::Finished => {
+            pd.player.as_mut().unwrap().paused = true;
+            pd.tracker.clock.set_rate(0.0);
+            show_state(&pd);
+        }
+    }
+}
+
+fn show_state(pd: &ProgramData) {
+    pd.gui.as_ref().unwrap().playback_controls.update(pd.player.as_ref());
+}
+
+/// Starts playing the recording from `path` (stopping any previous playback). The tracker's clock follows
+/// the recorded times until the playback is stopped.
+pub fn start(path: &std::path::Path, program_data_rc: &Rc<RefCell<ProgramData>>) -> Result<(), Box<dyn Error>> {
+    let entries = Arc::new(load(path)?);
+
+    stop(program_data_rc);
+
+    let id = NEXT_PLAYER_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+    let pending = Arc::new(AtomicUsize::new(0));
+    let (sender_worker, receiver_main) = glib::MainContext::channel(glib::Priority::DEFAULT);
+    receiver_main.attach(None, clone!(@weak program_data_rc, @strong pending => @default-panic, move |event| {
+        if let EventKind::Message(..) = event.kind { pending.fetch_sub(1, Ordering::Relaxed); }
+        on_playback_event(&program_data_rc, id, event);
+        glib::ControlFlow::Continue
+    }));
+
+    let reference = program_data_rc.borrow().tracker.observer_location.lat_lon.clone();
+    let speed = program_data_rc.borrow().gui.as_ref().unwrap().playback_controls.speed();
+    let (command_sender, commands) = mpsc::channel();
+    let entries2 = Arc::clone(&entries);
+    let worker = Some(std::thread::spawn(move || {
+        player(entries2, reference, speed, commands, pending, sender_worker);
+    }));
+
+    let mut pd = program_data_rc.borrow_mut();
+    pd.player = Some(Player{
+        id,
+        file_name: path.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default(),
+        entries,
+        worker,
+        command_sender: Some(command_sender),
+        paused: false,
+        speed,
+        position: 0,
+        seek_generation: 0
+    });
+    show_state(&pd);
+
+    Ok(())
+}
+
+pub fn stop(program_data_rc: &Rc<RefCell<ProgramData>>) {
+    let mut pd = program_data_rc.borrow_mut();
+    if let Some(mut player) = pd.player.take() {
+        player.command_sender.take(); // signals the worker to finish
+        player.worker.take().unwrap().join().unwrap();
+        // times of the played aircraft's updates are meaningless in wall-clock time
+        pd.tracker.clear();
+        pd.tracker.clock.set_real_time();
+        pd.process_tracker_events();
+        show_state(&pd);
+    }
+}
+
+pub fn set_paused(paused: bool, program_data_rc: &Rc<RefCell<ProgramData>>) {
+    if !paused && program_data_rc.borrow().player.as_ref().is_some_and(|p| p.finished()) {
+        seek(0, program_data_rc);
+    }
+
+    let mut pd = program_data_rc.borrow_mut();
+    if let Some(player) = pd.player.as_mut() {
+        player.paused = paused;
+        player.send(Command::SetPaused(paused));
+        let rate = player.clock_rate();
+        pd.tracker.clock.set_rate(rate);
+        show_state(&pd);
+    }
+}
+
+pub fn set_speed(speed: Speed, program_data_rc: &Rc<RefCell<ProgramData>>) {
+    let mut pd = program_data_rc.borrow_mut();
+    if let Some(player) = pd.player.as_mut() {
+        player.speed = speed;
+        player.send(Command::SetSpeed(speed));
+        let rate = player.clock_rate();
+        pd.tracker.clock.set_rate(rate);
+    }
+}
+
+/// Continues playback from entry `index`; aircraft seen so far (and messages played before, but not yet
+/// processed) are discarded.
+pub fn seek(index: usize, program_data_rc: &Rc<RefCell<ProgramData>>) {
+    let mut pd = program_data_rc.borrow_mut();
+    if let Some(player) = pd.player.as_mut() {
+        player.position = index.min(player.entries.len());
+        player.seek_generation += 1;
+        player.send(Command::Seek(index, player.seek_generation));
+        pd.tracker.clear();
+        pd.process_tracker_events();
+        show_state(&pd);
+    }
+}
@@ -0,0 +1,98 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use cgmath::Deg;
+use crate::{config, data_receiver::{ConnectionState, DataSource}};
+#[cfg(feature = "gui")]
+use crate::{gui, playback};
+use plane_tracker::{auto_select::AutoSelector, data::SbsMessage, tracker};
+use pointing_utils::{GeoPos, LatLon, uom};
+use uom::{si::f64, si::length};
+
+pub struct DataReceiver {
+    /// Unique among all receivers started during program run.
+    pub id: u64,
+    pub source: DataSource,
+    pub state: ConnectionState,
+    /// Stream providing messages; `None` while not connected.
+    pub stream: std::sync::Arc<std::sync::Mutex<Option<std::net::TcpStream>>>,
+    /// Dropped to make the worker finish.
+    pub stop_sender: std::sync::mpsc::Sender<()>
+}
+
+pub struct ProgramData {
+    pub tracker: tracker::Tracker,
+    tracker_events: std::sync::mpsc::Receiver<tracker::Event>,
+    #[cfg(feature = "gui")]
+    pub gui: Option<gui::GuiData>, // always set once GUI is initialized,
+    pub config: config::Configuration,
+    pub data_receivers: Vec<DataReceiver>,
+    pub recording: bool,
+    /// Directory of recordings; if `None`, the working directory is used.
+    pub recording_dir: Option<std::path::PathBuf>,
+    /// Set while a recording is being played back.
+    #[cfg(feature = "gui")]
+    pub player: Option<playback::Player>
+}
+
+impl ProgramData {
+    pub fn new(config: config::Configuration) -> ProgramData {
+        let mut tracker = tracker::Tracker::new(config.observer_location().unwrap_or(
+            GeoPos{
+                lat_lon: LatLon{ lat: Deg(0.0), lon: Deg(0.0) },
+                elevation: f64::Length::new::<length::meter>(0.0)
+            }
+        ));
+        tracker.filter_ooo_messages = config.filter_ooo_messages().unwrap_or(true);
+        tracker.track_filter = config.track_filter();
+        tracker.trail_limits = config.trail_limits();
+        tracker.auto_selector = config.auto_select_policy().map(AutoSelector::new);
+        tracker.transit_settings = config.transit_settings();
+        tracker.visibility = config.visibility();
+        let tracker_events = tracker.subscribe();
+
+        ProgramData{
+            tracker,
+            tracker_events,
+            #[cfg(feature = "gui")]
+            gui: None,
+            config,
+            data_receivers: vec![],
+            recording: false,
+            recording_dir: None,
+            #[cfg(feature = "gui")]
+            player: None
+        }
+    }
+
+    /// `source`: label of data source which has received `msg`.
+    pub fn update(&mut self, msg: SbsMessage, source: &str) {
+        self.tracker.update(msg, source);
+        self.process_tracker_events();
+    }
+
+    /// Passes pending tracker events to the GUI (or, in headless mode, reports client changes and transits).
+    pub fn process_tracker_events(&self) {
+        while let Ok(event) = self.tracker_events.try_recv() {
+            #[cfg(feature = "gui")]
+            if let Some(gui) = self.gui.as_ref() {
+                gui::on_tracker_event(gui, &event, &self.tracker.clock);
+                if let tracker::Event::TransitPredicted(_) = event {
+                    if self.config.transit_alert().unwrap_or(true) { gui.drawing_area.error_bell(); }
+                }
+                continue;
+            }
+
+            match event {
+                tracker::Event::DataSendersChanged{ num_clients } => println!("Data sender clients: {}", num_clients),
+                tracker::Event::TransitPredicted(prediction) => println!("Transit: {}", prediction),
+                _ => ()
+            }
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use crate::{csv, data::ModeSTransponderCode};
+use std::{collections::HashMap, error::Error};
+
+/// Accepted header names (case-insensitive) of each column; the first one present is used.
+mod columns {
+    pub const ADDRESS: &[&str] = &["icao24", "icao", "hex", "address"];
+    pub const REGISTRATION: &[&str] = &["registration", "reg", "r"];
+    pub const TYPE_DESIGNATOR: &[&str] = &["typecode", "icaotype", "type", "t"];
+    pub const OPERATOR: &[&str] = &["operator", "owner", "ownop"];
+    pub const CATEGORY: &[&str] = &["category", "icaoaircrafttype", "desc"];
+}
+
+/// Registry data of an aircraft.
+#[derive(Clone, Debug, Default)]
+pub struct Entry {
+    /// E.g., "SP-LRA".
+    pub registration: Option<String>,
+    /// ICAO type designator, e.g., "B788".
+    pub type_designator: Option<String>,
+    pub operator: Option<String>,
+    /// E.g., ICAO aircraft description ("L2J").
+    pub category: Option<String>
+}
+
+/// Offline aircraft registry (e.g., a public registry dump), indexed by ICAO address.
+pub struct Database {
+    entries: HashMap<ModeSTransponderCode, Entry>
+}
+
+impl Database {
+    /// Loads a CSV file with a header line naming the columns (e.g., OpenSky's "aircraftDatabase.csv");
+    /// the ICAO address column is required, the others are optional. Records with an invalid address are skipped.
+    /// May take a while for a large file.
+    pub fn load(path: &std::path::Path) -> Result<Database, Box<dyn Error>> {
+        let mut reader = csv::Reader::open(path)?;
+        let address = reader.columns().find(columns::ADDRESS).ok_or("missing ICAO address column")?;
+        let registration = reader.columns().find(columns::REGISTRATION);
+        let type_designator = reader.columns().find(columns::TYPE_DESIGNATOR);
+        let operator = reader.columns().find(columns::OPERATOR);
+        let category = reader.columns().find(columns::CATEGORY);
+
+        let mut entries = HashMap::new();
+        while let Some(record) = reader.next_record()? {
+            let columns = reader.columns();
+            let value = |column: Option<&str>| column.and_then(|c| columns.get(&record, c)).map(|v| v.to_string());
+
+            let id = match columns.get(&record, address).map(|a| a.to_ascii_uppercase().parse::<ModeSTransponderCode>()) {
+                Some(Ok(id)) => id,
+                _ => continue
+            };
+            let entry = Entry{
+                registration: value(registration),
+                type_designator: value(type_designator),
+                operator: value(operator),
+                category: value(category)
+            };
+            if entry.registration.is_some() || entry.type_designator.is_some() || entry.operator.is_some() {
+                entries.insert(id, entry);
+            }
+        }
+
+        Ok(Database{ entries })
+    }
+
+    pub fn get(&self, id: ModeSTransponderCode) -> Option<&Entry> {
+        self.entries.get(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load() {
+        let path = std::env::temp_dir().join(format!("plane-tracker-test-{}-registry.csv", std::process::id()));
+        std::fs::write(&path, r#""icao24","registration","manufacturername","model","typecode","operator"
+"48af01","SP-LRA","Boeing","787-8","B788","LOT Polish Airlines"
+"4CA2D1","EI-ABC","Boeing","737-8AS","B738","Ryanair, DAC"
+"zzzzzz","N123","","","",""
+"3c6444","","","","",""
+"3C6445","D-ABCD"
+"#).unwrap();
+        let db = Database::load(&path);
+        let _ = std::fs::remove_file(&path);
+        let db = db.unwrap();
+
+        assert_eq!(3, db.len());
+
+        let entry = db.get("48AF01".parse().unwrap()).unwrap();
+        assert_eq!(Some("SP-LRA"), entry.registration.as_deref());
+        assert_eq!(Some("B788"), entry.type_designator.as_deref());
+        assert_eq!(Some("LOT Polish Airlines"), entry.operator.as_deref());
+        assert_eq!(None, entry.category.as_deref());
+
+        assert_eq!(Some("Ryanair, DAC"), db.get("4CA2D1".parse().unwrap()).unwrap().operator.as_deref());
+
+        // short row
+        let entry = db.get("3C6445".parse().unwrap()).unwrap();
+        assert_eq!(Some("D-ABCD"), entry.registration.as_deref());
+        assert_eq!(None, entry.type_designator.as_deref());
+
+        // no data
+        assert!(db.get("3C6444".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn missing_address_column() {
+        let path = std::env::temp_dir().join(format!("plane-tracker-test-{}-registry-no-address.csv", std::process::id()));
+        std::fs::write(&path, "registration,typecode\nSP-LRA,B788\n").unwrap();
+        let db = Database::load(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(db.is_err());
+    }
+}
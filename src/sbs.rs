@@ -0,0 +1,288 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use cgmath::Deg;
+use crate::data;
+use pointing_utils::{LatLon, uom};
+use std::error::Error;
+use uom::{si::f64, si::{length, velocity}};
+
+mod msg_type {
+    pub const ES_IDENTIFICATION_AND_CATEGORY: i32 = 1;
+    pub const ES_SURFACE_POSITION_MESSAGE: i32 = 2;
+    pub const ES_AIRBORNE_POSITION_MESSAGE: i32 = 3;
+    pub const ES_AIRBORNE_VELOCITY_MESSAGE: i32 = 4;
+    pub const SURVEILLANCE_ALT_MESSAGE: i32 = 5;
+    pub const SURVEILLANCE_ID_MESSAGE: i32 = 6;
+    pub const AIR_TO_AIR_MESSAGE: i32 = 7;
+    pub const ALL_CALL_REPLY: i32 = 8;
+}
+
+/// Number of fields in a complete SBS message.
+const NUM_SBS_FIELDS: usize = 22;
+
+mod field {
+    pub const ALTITUDE: usize = 11;
+    pub const GROUND_SPEED: usize = 12;
+    pub const TRACK: usize = 13;
+    pub const LATITUDE: usize = 14;
+    pub const LONGITUDE: usize = 15;
+    pub const VERTICAL_RATE: usize = 16;
+    pub const SQUAWK: usize = 17;
+    pub const ALERT: usize = 18;
+    pub const EMERGENCY: usize = 19;
+    pub const SPI: usize = 20;
+    pub const IS_ON_GROUND: usize = 21;
+}
+
+fn feet(value: f64) -> f64::Length {
+    f64::Length::new::<length::foot>(value)
+}
+
+fn knots(value: f64) -> f64::Velocity {
+    f64::Velocity::new::<velocity::knot>(value)
+}
+
+fn feet_per_minute(value: f64) -> f64::Velocity {
+    f64::Velocity::new::<velocity::foot_per_minute>(value)
+}
+
+/// Returns `None` if `field` is empty.
+fn parse_optional<T: std::str::FromStr>(field: &str) -> Result<Option<T>, Box<dyn Error>>
+where T::Err: Into<Box<dyn Error>> {
+    if field.is_empty() {
+        Ok(None)
+    } else {
+        field.parse::<T>().map(Some).map_err(Into::into)
+    }
+}
+
+/// Parses a flag field ("-1": set, "0": not set); returns `None` if `field` is empty.
+fn parse_flag(field: &str) -> Result<Option<bool>, Box<dyn Error>> {
+    match field {
+        "" => Ok(None),
+        "-1" | "1" => Ok(Some(true)),
+        "0" => Ok(Some(false)),
+        _ => Err(format!("invalid flag value \"{}\"", field).into())
+    }
+}
+
+fn parse_lat_lon(fields: &[&str]) -> Result<Option<LatLon>, Box<dyn Error>> {
+    let lat = fields[field::LATITUDE].parse::<f64>();
+    let lon = fields[field::LONGITUDE].parse::<f64>();
+
+    match (lat, lon) {
+        (Ok(lat), Ok(lon)) => Ok(Some(LatLon{ lat: Deg(lat), lon: Deg(lon) })),
+        (Err(_), Err(_)) => Ok(None),
+        (Ok(_), Err(e)) | (Err(e), Ok(_)) => Err(Box::new(e))
+    }
+}
+
+/// Parses a frame in AVR format: "*<hex>;" or (with 12 MHz timestamp) "@<12 hex digits><hex>;".
+pub fn parse_avr_frame(line: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let line = line.trim();
+    if !line.is_ascii() { return Err("non-ASCII characters".into()); }
+
+    let hex = if let Some(rest) = line.strip_prefix('*') {
+        rest
+    } else if let Some(rest) = line.strip_prefix('@') {
+        rest.get(12..).ok_or("missing timestamp")?
+    } else {
+        return Err("invalid frame start".into());
+    };
+
+    let hex = hex.strip_suffix(';').ok_or("missing frame terminator")?;
+    if hex.len() % 2 != 0 { return Err(format!("odd number of hex digits ({})", hex.len()).into()); }
+
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| Box::new(e) as Box<dyn Error>)).collect()
+}
+
+/// Returns `None` for unsupported message types.
+pub fn parse_sbs_message(msg: &str) -> Result<Option<data::SbsMessage>, Box<dyn Error>> {
+    let fields: Vec<&str> = msg.split(',').collect();
+
+    if fields.is_empty() { return Err("empty message".into()); }
+
+    if fields[0] != "MSG" { return Ok(None); }
+
+    if fields.len() < 5 { return Err(format!("too few fields ({})", fields.len()).into()); }
+
+    let msg_type = fields[1].parse::<i32>();
+
+    if let Err(e) = msg_type { return Err(Box::new(e)); }
+
+    if fields[4].is_empty() {
+        return Err(format!("MSG,{} has empty field 5", fields[1]).into());
+    }
+
+    if fields.len() < NUM_SBS_FIELDS {
+        return Err(format!("MSG,{} has too few fields ({})", fields[1], fields.len()).into());
+    }
+
+    let id = fields[4].parse::<data::ModeSTransponderCode>()?;
+
+    match msg_type.unwrap() {
+        msg_type::ES_IDENTIFICATION_AND_CATEGORY => {
+            if fields[10].is_empty() {
+                return Err(format!("MSG,{} has empty field 10", msg_type::ES_IDENTIFICATION_AND_CATEGORY).into());
+            }
+
+            return Ok(Some(data::SbsMessage::EsIdentificationAndCategory{
+                id,
+                callsign: fields[10].into()
+            }));
+        },
+
+        msg_type::ES_AIRBORNE_POSITION_MESSAGE => {
+            let altitude = match fields[field::ALTITUDE].parse::<u32>() {
+                Ok(value) => Some(feet(value as f64)),
+                _ => None
+            };
+            let lat_lon = parse_lat_lon(&fields)?;
+
+            return Ok(Some(data::SbsMessage::EsAirbornePosition{id, altitude, lat_lon}));
+        },
+
+        msg_type::ES_AIRBORNE_VELOCITY_MESSAGE => {
+            let ground_speed = knots(fields[field::GROUND_SPEED].parse::<f64>()?);
+            let track = Deg(fields[field::TRACK].parse::<f64>()?);
+            let vertical_rate = parse_optional::<f64>(fields[field::VERTICAL_RATE])?.map(feet_per_minute);
+
+            return Ok(Some(data::SbsMessage::EsAirborneVelocity{ id, ground_speed, track, vertical_rate }));
+        },
+
+        msg_type::SURVEILLANCE_ALT_MESSAGE => {
+            let altitude = feet(fields[field::ALTITUDE].parse::<u32>()? as f64);
+            return Ok(Some(data::SbsMessage::SurveillanceAltitude{ id, altitude }));
+        },
+
+        msg_type::ES_SURFACE_POSITION_MESSAGE => {
+            let altitude = parse_optional::<i32>(fields[field::ALTITUDE])?.map(|value| feet(value as f64));
+            let ground_speed = parse_optional::<f64>(fields[field::GROUND_SPEED])?.map(knots);
+            let track = parse_optional::<f64>(fields[field::TRACK])?.map(Deg);
+            let lat_lon = parse_lat_lon(&fields)?;
+
+            return Ok(Some(data::SbsMessage::EsSurfacePosition{ id, altitude, ground_speed, track, lat_lon }));
+        },
+
+        msg_type::SURVEILLANCE_ID_MESSAGE => {
+            let squawk = match parse_optional::<data::Squawk>(fields[field::SQUAWK])? {
+                Some(squawk) => squawk,
+                None => return Err(format!("MSG,{} has empty field {}", msg_type::SURVEILLANCE_ID_MESSAGE, field::SQUAWK).into())
+            };
+
+            return Ok(Some(data::SbsMessage::SurveillanceId{
+                id,
+                altitude: parse_optional::<i32>(fields[field::ALTITUDE])?.map(|value| feet(value as f64)),
+                squawk,
+                alert: parse_flag(fields[field::ALERT])?,
+                emergency: parse_flag(fields[field::EMERGENCY])?,
+                spi: parse_flag(fields[field::SPI])?,
+                on_ground: parse_flag(fields[field::IS_ON_GROUND])?
+            }));
+        },
+
+        msg_type::AIR_TO_AIR_MESSAGE => {
+            return Ok(Some(data::SbsMessage::AirToAir{
+                id,
+                altitude: parse_optional::<i32>(fields[field::ALTITUDE])?.map(|value| feet(value as f64)),
+                on_ground: parse_flag(fields[field::IS_ON_GROUND])?
+            }));
+        },
+
+        msg_type::ALL_CALL_REPLY => {
+            return Ok(Some(data::SbsMessage::AllCallReply{
+                id,
+                on_ground: parse_flag(fields[field::IS_ON_GROUND])?
+            }));
+        },
+
+        _ => ()
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id() -> data::ModeSTransponderCode { data::ModeSTransponderCode::new(0x4CA1D3) }
+
+    fn parse(msg: &str) -> data::SbsMessage {
+        parse_sbs_message(msg).unwrap().unwrap()
+    }
+
+    #[test]
+    fn parses_surface_position() {
+        match parse("MSG,2,1,1,4CA1D3,1,2024/01/01,10:00:00.000,2024/01/01,10:00:00.000,,0,12,270,52.1,21.0,,,,,,-1") {
+            data::SbsMessage::EsSurfacePosition{ id: i, altitude, ground_speed, track, lat_lon } => {
+                assert_eq!(id(), i);
+                assert_eq!(Some(0.0), altitude.map(|a| a.get::<length::foot>()));
+                assert!((ground_speed.unwrap().get::<velocity::knot>() - 12.0).abs() < 1.0e-9);
+                assert_eq!(Some(Deg(270.0)), track);
+                assert_eq!(Some(LatLon{ lat: Deg(52.1), lon: Deg(21.0) }), lat_lon);
+            },
+            other => panic!("unexpected message: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn parses_surveillance_id() {
+        match parse("MSG,6,1,1,4CA1D3,1,2024/01/01,10:00:00.000,2024/01/01,10:00:00.000,,37000,,,,,,7700,-1,-1,0,0") {
+            data::SbsMessage::SurveillanceId{ id: i, altitude, squawk, alert, emergency, spi, on_ground } => {
+                assert_eq!(id(), i);
+                assert_eq!(Some(37000.0), altitude.map(|a| a.get::<length::foot>()));
+                assert_eq!("7700", squawk.to_string());
+                assert_eq!(Some(true), alert);
+                assert_eq!(Some(true), emergency);
+                assert_eq!(Some(false), spi);
+                assert_eq!(Some(false), on_ground);
+            },
+            other => panic!("unexpected message: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn parses_air_to_air_and_all_call_reply() {
+        match parse("MSG,7,1,1,4CA1D3,1,2024/01/01,10:00:00.000,2024/01/01,10:00:00.000,,12000,,,,,,,,,,") {
+            data::SbsMessage::AirToAir{ id: i, altitude, on_ground } => {
+                assert_eq!(id(), i);
+                assert_eq!(Some(12000.0), altitude.map(|a| a.get::<length::foot>()));
+                assert_eq!(None, on_ground);
+            },
+            other => panic!("unexpected message: {:?}", other)
+        }
+
+        match parse("MSG,8,1,1,4CA1D3,1,2024/01/01,10:00:00.000,2024/01/01,10:00:00.000,,,,,,,,,,,,0") {
+            data::SbsMessage::AllCallReply{ id: i, on_ground } => {
+                assert_eq!(id(), i);
+                assert_eq!(Some(false), on_ground);
+            },
+            other => panic!("unexpected message: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_messages() {
+        for msg in ["MSG,1,1,1,4CA1D3,1", "MSG,2,1,1,4CA1D3,1", "MSG,3,1,1,4CA1D3,1", "MSG,4,1,1,4CA1D3,1,,,,,,,450",
+            "MSG,5,1,1,4CA1D3,1", "MSG,6,1,1,4CA1D3,1", "MSG,7,1,1,4CA1D3,1", "MSG,8,1,1,4CA1D3,1"] {
+            assert!(parse_sbs_message(msg).is_err(), "{}", msg);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_flag() {
+        assert!(parse_sbs_message("MSG,8,1,1,4CA1D3,1,2024/01/01,10:00:00.000,2024/01/01,10:00:00.000,,,,,,,,,,,,2").is_err());
+    }
+
+    #[test]
+    fn ignores_other_messages() {
+        assert!(parse_sbs_message("STA,,1,1,4CA1D3,1,2024/01/01,10:00:00.000,2024/01/01,10:00:00.000,RM").unwrap().is_none());
+    }
+}
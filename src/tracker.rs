@@ -0,0 +1,596 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use crate::auto_select::AutoSelector;
+use crate::clock::Clock;
+use crate::data::{
+    Aircraft, ModeSTransponderCode, SbsMessage, State, TrailLimits, aircraft_moved_backwards, get_distance, meters
+};
+use crate::data_sender::{Client, Command, Request, aircraft_line, send_data};
+use crate::{kalman, registry, transit};
+use crate::visibility::Visibility;
+use pointing_utils::{GeoPos, uom};
+use std::{collections::{HashMap, VecDeque}, sync::{Arc, mpsc}};
+use uom::si::f64;
+
+const GC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+const MAX_DURATION_WITHOUT_UPDATE: std::time::Duration = std::time::Duration::from_secs(60);
+const TRANSIT_PREDICTION_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Change of tracker state, delivered to subscribers.
+#[derive(Clone, Debug)]
+pub enum Event {
+    AircraftUpdated(ModeSTransponderCode),
+    AircraftRemoved(ModeSTransponderCode),
+    StatisticsChanged{
+        /// Number of aircraft with known position and track.
+        num_aircraft: usize,
+        max_num_aircraft: usize,
+        /// Maximal (non-interpolated) distance seen so far.
+        max_distance: Option<f64::Length>
+    },
+    DataSendersChanged{ num_clients: usize },
+    /// Aircraft has been newly predicted to pass near the Sun or Moon.
+    TransitPredicted(transit::Prediction)
+}
+
+/// Maintains the table of aircraft based on received messages.
+pub struct Tracker {
+    pub observer_location: GeoPos,
+    pub aircraft: HashMap<ModeSTransponderCode, Aircraft>,
+    /// Time of all updates, interpolation and expiry of aircraft data.
+    pub clock: Clock,
+    /// If true, position messages indicating the aircraft has moved backwards are ignored
+    /// (unless `track_filter` is set; the filter rejects inconsistent positions itself).
+    pub filter_ooo_messages: bool,
+    /// If set, tracks of newly seen aircraft are estimated by a Kalman filter with this motion model.
+    pub track_filter: Option<kalman::Model>,
+    /// Limits of each aircraft's position history.
+    pub trail_limits: TrailLimits,
+    /// Last garbage collection of `aircraft`.
+    t_last_gc: std::time::Instant,
+    /// Maximal (non-interpolated) distance seen so far.
+    pub max_distance: Option<f64::Length>,
+    pub max_num_aircraft: usize,
+    /// Refraction correction and horizon mask.
+    pub visibility: Visibility,
+    /// If set, selects aircraft automatically (see `update_auto_selection`).
+    pub auto_selector: Option<AutoSelector>,
+    /// If set, passages of aircraft near the Sun and Moon are predicted (see `predict_transits`).
+    pub transit_settings: Option<transit::Settings>,
+    /// Current predictions (sorted by time).
+    pub transits: Vec<transit::Prediction>,
+    /// Offline registry; looked up when an aircraft is first seen.
+    registry: Option<registry::Database>,
+    t_last_transit_prediction: std::time::Instant,
+    /// Clients receiving information about the selected aircraft.
+    data_senders: Vec<Client>,
+    next_client_id: u64,
+    request_sender: mpsc::Sender<Request>,
+    requests: mpsc::Receiver<Request>,
+    /// Called (from a client's thread) after a request has been received.
+    on_request: Arc<dyn Fn() + Send + Sync>,
+    subscribers: Vec<mpsc::Sender<Event>>
+}
+
+impl Tracker {
+    pub fn new(observer_location: GeoPos) -> Tracker {
+        let (request_sender, requests) = mpsc::channel();
+
+        Tracker{
+            observer_location,
+            aircraft: HashMap::new(),
+            clock: Clock::default(),
+            filter_ooo_messages: true,
+            track_filter: None,
+            trail_limits: TrailLimits::default(),
+            t_last_gc: std::time::Instant::now(),
+            max_distance: None,
+            max_num_aircraft: 0,
+            visibility: Visibility::default(),
+            auto_selector: None,
+            transit_settings: None,
+            transits: vec![],
+            registry: None,
+            t_last_transit_prediction: std::time::Instant::now(),
+            data_senders: vec![],
+            next_client_id: 0,
+            request_sender,
+            requests,
+            on_request: Arc::new(|| {}),
+            subscribers: vec![]
+        }
+    }
+
+    /// Returns whether `aircraft` is above the horizon mask; `None` if its position is unknown.
+    pub fn is_visible(&self, aircraft: &Aircraft) -> Option<bool> {
+        self.visibility.apparent_position(&self.observer_location, aircraft, true).map(|p| p.visible)
+    }
+
+    /// Returns receiver of subsequent state changes.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    fn notify(&mut self, event: Event) {
+        self.subscribers.retain(|s| s.send(event.clone()).is_ok());
+    }
+
+    /// Sets the registry database (e.g., after it has been loaded in the background) and looks up
+    /// the already tracked aircraft.
+    pub fn set_registry(&mut self, registry: registry::Database) {
+        let mut updated = vec![];
+        for aircraft in self.aircraft.values_mut() {
+            aircraft.registry_entry = registry.get(aircraft.id).cloned();
+            if aircraft.registry_entry.is_some() { updated.push(aircraft.id); }
+        }
+        self.registry = Some(registry);
+        for id in updated { self.notify(Event::AircraftUpdated(id)); }
+    }
+
+    /// Sets function to be called (from another thread) after a data sender client has sent a request;
+    /// the requests are to be handled by calling `process_requests`. Applies to subsequently added clients.
+    pub fn set_request_notifier(&mut self, on_request: impl Fn() + Send + Sync + 'static) {
+        self.on_request = Arc::new(on_request);
+    }
+
+    pub fn add_data_sender(&mut self, stream: std::net::TcpStream) {
+        let id = self.next_client_id;
+        self.next_client_id += 1;
+        self.data_senders.push(Client::new(id, stream, self.request_sender.clone(), Arc::clone(&self.on_request)));
+        self.notify(Event::DataSendersChanged{ num_clients: self.data_senders.len() });
+    }
+
+    pub fn num_data_senders(&self) -> usize {
+        self.data_senders.len()
+    }
+
+    fn on_data_senders_updated(&mut self, num_before: usize) {
+        if self.data_senders.len() != num_before {
+            self.notify(Event::DataSendersChanged{ num_clients: self.data_senders.len() });
+        }
+    }
+
+    /// Handles requests received from data sender clients.
+    pub fn process_requests(&mut self) {
+        while let Ok(request) = self.requests.try_recv() {
+            let response = match request.line.parse::<Command>() {
+                Ok(command) => self.execute(request.client_id, command),
+                Err(e) => format!("ERROR {}\n", e)
+            };
+
+            if let Some(client) = self.data_senders.iter().find(|c| c.id == request.client_id) {
+                client.respond(&response);
+            }
+        }
+    }
+
+    /// Returns response to `command`.
+    fn execute(&mut self, client_id: u64, command: Command) -> String {
+        match command {
+            Command::List => {
+                let mut response: String = self.aircraft.values().map(|a| aircraft_line(a, self.is_visible(a))).collect();
+                response += "END\n";
+                response
+            },
+
+            Command::SelectById(id) => {
+                if self.select(id) { "OK\n".into() } else { "ERROR unknown aircraft\n".into() }
+            },
+
+            Command::SelectByCallsign(callsign) => {
+                let id = self.aircraft
+                    .values()
+                    .find(|a| a.callsign.as_ref().is_some_and(|c| c.trim().eq_ignore_ascii_case(&callsign)))
+                    .map(|a| a.id);
+
+                match id {
+                    Some(id) => { self.select(id); "OK\n".into() },
+                    None => "ERROR unknown aircraft\n".into()
+                }
+            },
+
+            Command::Deselect => {
+                self.deselect();
+                "OK\n".into()
+            },
+
+            Command::Observer => format!(
+                "OBSERVER {:.6} {:.6} {:.1}\n",
+                self.observer_location.lat_lon.lat.0,
+                self.observer_location.lat_lon.lon.0,
+                self.observer_location.elevation.get::<uom::si::length::meter>()
+            ),
+
+            Command::SubscribeAll | Command::Unsubscribe => {
+                if let Some(client) = self.data_senders.iter_mut().find(|c| c.id == client_id) {
+                    client.subscribed_all = command == Command::SubscribeAll;
+                }
+                "OK\n".into()
+            }
+        }
+    }
+
+    /// Returns the selected aircraft.
+    pub fn selected(&self) -> Option<ModeSTransponderCode> {
+        self.aircraft.values().find(|a| a.state == State::Selected).map(|a| a.id)
+    }
+
+    /// Makes `id` the (only) selected aircraft; returns false if it is unknown.
+    pub fn select(&mut self, id: ModeSTransponderCode) -> bool {
+        if !self.aircraft.contains_key(&id) { return false; }
+
+        self.deselect();
+        self.aircraft.get_mut(&id).unwrap().state = State::Selected;
+        self.notify(Event::AircraftUpdated(id));
+
+        true
+    }
+
+    pub fn deselect(&mut self) {
+        if let Some(id) = self.selected() {
+            self.aircraft.get_mut(&id).unwrap().state = State::Normal;
+            self.notify(Event::AircraftUpdated(id));
+        }
+    }
+
+    /// Changes the selection according to `auto_selector` (if set).
+    pub fn update_auto_selection(&mut self) {
+        let selected = self.selected();
+        let chosen = match self.auto_selector.as_mut() {
+            Some(selector) => selector.choose(
+                &self.aircraft, selected, &self.observer_location, &self.visibility, self.clock.now()
+            ),
+            None => return
+        };
+
+        if chosen != selected {
+            match chosen {
+                Some(id) => { self.select(id); },
+                None => self.deselect()
+            }
+        }
+    }
+
+    /// Updates `transits` (at most once per second).
+    pub fn predict_transits(&mut self) {
+        let settings = match &self.transit_settings {
+            Some(settings) => settings,
+            None => { self.transits.clear(); return; }
+        };
+        if self.t_last_transit_prediction.elapsed() < TRANSIT_PREDICTION_INTERVAL { return; }
+
+        let predictions = transit::predict(
+            &self.aircraft,
+            &self.observer_location,
+            self.clock.now(),
+            self.clock.utc(),
+            settings
+        );
+
+        let new_predictions: Vec<transit::Prediction> = predictions
+            .iter()
+            .filter(|p| !self.transits.iter().any(|old| old.aircraft == p.aircraft && old.body == p.body))
+            .cloned()
+            .collect();
+
+        self.transits = predictions;
+        self.t_last_transit_prediction = std::time::Instant::now();
+
+        for prediction in new_predictions {
+            self.notify(Event::TransitPredicted(prediction));
+        }
+    }
+
+    fn notify_statistics(&mut self) {
+        let num_aircraft = self.aircraft
+            .iter()
+            .filter(|(_, aircraft)| { aircraft.lat_lon.is_some() && aircraft.track.is_some() })
+            .count();
+        self.max_num_aircraft = self.max_num_aircraft.max(num_aircraft);
+
+        self.notify(Event::StatisticsChanged{
+            num_aircraft,
+            max_num_aircraft: self.max_num_aircraft,
+            max_distance: self.max_distance
+        });
+    }
+
+    /// `source`: label of data source which has received `msg`.
+    pub fn update(&mut self, msg: SbsMessage, source: &str) {
+        let mut important_data_changed = false;
+        let now = self.clock.now();
+
+        let id = msg.id();
+        let entry = self.aircraft.entry(id).or_insert_with(|| Aircraft{
+            id,
+            state: State::Normal,
+            callsign: None,
+            registry_entry: self.registry.as_ref().and_then(|r| r.get(id)).cloned(),
+            lat_lon: None,
+            estimated_lat_lon: None,
+            altitude: None,
+            estimated_altitude: None,
+            track: None,
+            ground_speed: None,
+            vertical_rate: None,
+            squawk: None,
+            alert: false,
+            emergency: false,
+            spi: false,
+            on_ground: None,
+            rssi: None,
+            sources: HashMap::new(),
+            position_source: None,
+            track_filter: self.track_filter.map(kalman::TrackFilter::new),
+            trail: VecDeque::new(),
+            t_last_update: now
+        });
+
+        entry.sources.insert(source.into(), now);
+
+        match msg {
+            SbsMessage::EsIdentificationAndCategory{ callsign, .. } => {
+                entry.callsign = Some(callsign);
+            },
+
+            SbsMessage::EsAirbornePosition{ lat_lon, altitude, .. } => {
+                if let Some(lat_lon) = lat_lon {
+                    // a rejected position does not discard the rest of the message (e.g., the altitude,
+                    // which the filter checks separately)
+                    let position_accepted = entry.is_fresh_position(&lat_lon, source, self.filter_ooo_messages)
+                        && match &mut entry.track_filter {
+                            Some(filter) => filter.update_position(&lat_lon, now),
+                            None => !(self.filter_ooo_messages
+                                && entry.lat_lon.is_some()
+                                && entry.track.is_some()
+                                && entry.altitude.is_some()
+                                && aircraft_moved_backwards(entry, &lat_lon))
+                        };
+
+                    if position_accepted { entry.set_position(lat_lon, source, &self.trail_limits, now); }
+                }
+
+                entry.set_altitude(altitude, now);
+                entry.on_ground = Some(false);
+                important_data_changed = true;
+            },
+
+            SbsMessage::EsSurfacePosition{ altitude, ground_speed, track, lat_lon, .. } => {
+                if let Some(lat_lon) = lat_lon {
+                    let position_accepted = entry.is_fresh_position(&lat_lon, source, self.filter_ooo_messages)
+                        && match &mut entry.track_filter {
+                            Some(filter) => filter.update_position(&lat_lon, now),
+                            None => true
+                        };
+                    if position_accepted { entry.set_position(lat_lon, source, &self.trail_limits, now); }
+                }
+                if altitude.is_some() { entry.set_altitude(altitude, now); }
+                match (track, ground_speed) {
+                    (Some(track), Some(ground_speed)) => entry.set_velocity(track, ground_speed, None, now),
+                    _ => {
+                        if ground_speed.is_some() { entry.ground_speed = ground_speed; }
+                        if track.is_some() { entry.track = track; }
+                    }
+                }
+                entry.on_ground = Some(true);
+                important_data_changed = true;
+            },
+
+            SbsMessage::EsAirborneVelocity{ ground_speed, track, vertical_rate, .. } => {
+                entry.set_velocity(track, ground_speed, vertical_rate, now);
+                important_data_changed = true;
+            },
+
+            SbsMessage::SurveillanceAltitude{ altitude, .. } => {
+                entry.set_altitude(Some(altitude), now);
+                important_data_changed = true;
+            },
+
+            SbsMessage::SurveillanceId{ altitude, squawk, alert, emergency, spi, on_ground, .. } => {
+                if altitude.is_some() {
+                    entry.set_altitude(altitude, now);
+                    important_data_changed = true;
+                }
+                entry.squawk = Some(squawk);
+                if let Some(alert) = alert { entry.alert = alert; }
+                if let Some(emergency) = emergency { entry.emergency = emergency; }
+                if let Some(spi) = spi { entry.spi = spi; }
+                if on_ground.is_some() { entry.on_ground = on_ground; }
+            },
+
+            SbsMessage::AirToAir{ altitude, on_ground, .. } => {
+                if altitude.is_some() {
+                    entry.set_altitude(altitude, now);
+                    important_data_changed = true;
+                }
+                if on_ground.is_some() { entry.on_ground = on_ground; }
+            },
+
+            SbsMessage::AllCallReply{ on_ground, .. } => {
+                if on_ground.is_some() { entry.on_ground = on_ground; }
+            },
+
+            SbsMessage::SignalLevel{ rssi, .. } => {
+                entry.rssi = Some(rssi);
+            }
+        }
+        entry.t_last_update = now;
+
+        if entry.lat_lon.is_some() && entry.altitude.is_some() {
+            let distance = get_distance(&self.observer_location, entry, false);
+            self.max_distance = Some(self.max_distance.unwrap_or(meters(0.0)).max(distance));
+        }
+
+        let num_data_senders = self.data_senders.len();
+        if important_data_changed && entry.state == State::Selected {
+            send_data(entry, &self.observer_location, &self.visibility, now, &mut self.data_senders);
+        }
+        if self.data_senders.iter().any(|c| c.subscribed_all) {
+            let visible = self.visibility.apparent_position(&self.observer_location, entry, true).map(|p| p.visible);
+            let line = aircraft_line(entry, visible);
+            for client in self.data_senders.iter().filter(|c| c.subscribed_all) {
+                client.send(&line);
+            }
+        }
+        self.on_data_senders_updated(num_data_senders);
+
+        self.notify(Event::AircraftUpdated(id));
+        self.notify_statistics();
+    }
+
+    /// Updates interpolated positions of all aircraft (and sends data of the selected one).
+    pub fn interpolate_positions(&mut self) {
+        let num_data_senders = self.data_senders.len();
+        let now = self.clock.now();
+        for aircraft in self.aircraft.values_mut() {
+            aircraft.update_interpolated_position(now);
+            if aircraft.state == State::Selected {
+                send_data(aircraft, &self.observer_location, &self.visibility, now, &mut self.data_senders);
+            }
+        }
+        self.on_data_senders_updated(num_data_senders);
+    }
+
+    /// Removes aircraft which have not been updated for a while, old trail points and disconnected data sender clients.
+    pub fn garbage_collect(&mut self) {
+        let num_data_senders = self.data_senders.len();
+        self.data_senders.retain(|client| !client.is_closed());
+        self.on_data_senders_updated(num_data_senders);
+
+        let now = self.clock.now();
+        for aircraft in self.aircraft.values_mut() {
+            aircraft.prune_trail(&self.trail_limits, now);
+        }
+
+        if self.t_last_gc.elapsed() < GC_INTERVAL { return; }
+
+        let removed: Vec<ModeSTransponderCode> = self.aircraft
+            .values()
+            .filter(|aircraft| now.saturating_duration_since(aircraft.t_last_update) > MAX_DURATION_WITHOUT_UPDATE)
+            .map(|aircraft| aircraft.id)
+            .collect();
+        for id in removed {
+            self.aircraft.remove(&id);
+            self.notify(Event::AircraftRemoved(id));
+        }
+
+        self.t_last_gc = std::time::Instant::now();
+    }
+
+    /// Removes all aircraft and resets the maximal number of aircraft.
+    pub fn clear(&mut self) {
+        let removed: Vec<ModeSTransponderCode> = self.aircraft.keys().copied().collect();
+        self.aircraft.clear();
+        self.transits.clear();
+        for id in removed {
+            self.notify(Event::AircraftRemoved(id));
+        }
+        self.max_num_aircraft = 0;
+        self.notify_statistics();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Deg;
+    use pointing_utils::LatLon;
+    use uom::si::{length, velocity};
+
+    const ID: u32 = 0x4840D6;
+
+    fn meters(value: f64) -> f64::Length {
+        f64::Length::new::<length::meter>(value)
+    }
+
+    /// Returns a tracker which has received 10 s of messages from an aircraft flying north at 10 km and 250 m/s.
+    fn tracker_with_aircraft(track_filter: Option<kalman::Model>) -> Tracker {
+        let mut tracker = Tracker::new(GeoPos{ lat_lon: LatLon{ lat: Deg(52.0), lon: Deg(21.0) }, elevation: meters(0.0) });
+        tracker.track_filter = track_filter;
+        let id = ModeSTransponderCode::new(ID);
+        let t0 = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(10, 0, 0).unwrap();
+
+        for i in 0..10 {
+            tracker.clock.set_recorded_time(t0 + chrono::Duration::seconds(i), 0.0);
+            tracker.update(SbsMessage::EsAirborneVelocity{
+                id,
+                ground_speed: f64::Velocity::new::<velocity::meter_per_second>(250.0),
+                track: Deg(0.0),
+                vertical_rate: None
+            }, "test");
+            tracker.update(SbsMessage::EsAirbornePosition{
+                id,
+                altitude: Some(meters(10_000.0)),
+                lat_lon: Some(LatLon{ lat: Deg(52.0 + i as f64 * 250.0 / 111_200.0), lon: Deg(21.0) })
+            }, "test");
+        }
+        tracker.clock.set_recorded_time(t0 + chrono::Duration::seconds(10), 0.0);
+
+        tracker
+    }
+
+    #[test]
+    fn rejected_position_keeps_altitude() {
+        for (track_filter, rejected_lat) in [
+            (Some(kalman::Model::ConstantVelocity), 53.0),
+            // out-of-order message (moved backwards)
+            (None, 51.99)
+        ] {
+            let mut tracker = tracker_with_aircraft(track_filter);
+            let id = ModeSTransponderCode::new(ID);
+            let last_position = tracker.aircraft[&id].lat_lon.as_ref().unwrap().0.clone();
+
+            tracker.update(SbsMessage::EsAirbornePosition{
+                id,
+                altitude: Some(meters(10_030.0)),
+                lat_lon: Some(LatLon{ lat: Deg(rejected_lat), lon: Deg(21.0) })
+            }, "test");
+
+            let aircraft = &tracker.aircraft[&id];
+            assert_eq!(last_position.lat, aircraft.lat_lon.as_ref().unwrap().0.lat);
+            assert_eq!(Some(meters(10_030.0)), aircraft.altitude);
+        }
+    }
+
+    #[test]
+    fn stale_position_from_another_source() {
+        for filter_ooo_messages in [true, false] {
+            let mut tracker = tracker_with_aircraft(None);
+            tracker.filter_ooo_messages = filter_ooo_messages;
+            let id = ModeSTransponderCode::new(ID);
+            let last_position = tracker.aircraft[&id].lat_lon.as_ref().unwrap().0.clone();
+
+            let stale_position = LatLon{ lat: Deg(52.01), lon: Deg(21.0) };
+            tracker.update(SbsMessage::EsAirbornePosition{
+                id,
+                altitude: Some(meters(10_030.0)),
+                lat_lon: Some(stale_position.clone())
+            }, "other");
+
+            let aircraft = &tracker.aircraft[&id];
+            let expected_position = if filter_ooo_messages { last_position } else { stale_position };
+            assert_eq!(expected_position, aircraft.lat_lon.as_ref().unwrap().0);
+            assert_eq!(Some(meters(10_030.0)), aircraft.altitude);
+        }
+    }
+
+    #[test]
+    fn rejected_altitude_is_not_stored() {
+        let mut tracker = tracker_with_aircraft(Some(kalman::Model::ConstantVelocity));
+        let id = ModeSTransponderCode::new(ID);
+
+        tracker.update(SbsMessage::SurveillanceAltitude{ id, altitude: meters(13_000.0) }, "test");
+        assert_eq!(Some(meters(10_000.0)), tracker.aircraft[&id].altitude);
+
+        tracker.update(SbsMessage::SurveillanceAltitude{ id, altitude: meters(10_010.0) }, "test");
+        assert_eq!(Some(meters(10_010.0)), tracker.aircraft[&id].altitude);
+    }
+}
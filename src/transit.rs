@@ -0,0 +1,501 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use cgmath::{Deg, InnerSpace, Point3, Rad, Vector3};
+use crate::data::{Aircraft, ModeSTransponderCode, extrapolate_position, global_azimuth_elevation, to_global};
+use crate::geodesy::LocalFrame;
+use pointing_utils::GeoPos;
+use std::collections::HashMap;
+
+const AU_M: f64 = 149_597_870_700.0;
+const SUN_RADIUS_M: f64 = 695_700_000.0;
+const MOON_RADIUS_M: f64 = 1_737_400.0;
+/// Approximate difference between Terrestrial Time (used by the Sun and Moon theories) and UTC.
+const DELTA_T_S: f64 = 69.0;
+const J2000_JD: f64 = 2_451_545.0;
+
+/// Step of the initial search for the closest approach.
+const COARSE_STEP: std::time::Duration = std::time::Duration::from_secs(1);
+const NUM_REFINEMENT_ITERATIONS: usize = 30;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Body {
+    Sun,
+    Moon
+}
+
+impl Body {
+    pub const ALL: [Body; 2] = [Body::Sun, Body::Moon];
+}
+
+impl std::fmt::Display for Body {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Body::Sun => "Sun",
+            Body::Moon => "Moon"
+        })
+    }
+}
+
+/// Position of the Sun or Moon as seen by the observer.
+pub struct BodyPosition {
+    pub azimuth: Deg<f64>,
+    pub elevation: Deg<f64>,
+    /// Apparent angular radius of the disc.
+    pub radius: Deg<f64>
+}
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Aircraft passing within this angular distance of a disc's edge are reported.
+    pub max_separation: Deg<f64>,
+    /// How far ahead aircraft are extrapolated.
+    pub look_ahead: std::time::Duration
+}
+
+/// Predicted close approach of an aircraft to the Sun or Moon.
+#[derive(Clone, Debug)]
+pub struct Prediction {
+    pub aircraft: ModeSTransponderCode,
+    pub body: Body,
+    /// Time from prediction to the closest approach.
+    pub time_to_event: std::time::Duration,
+    /// Angular distance between aircraft and center of the disc at the closest approach.
+    pub separation: Deg<f64>,
+    /// Apparent angular radius of the disc.
+    pub body_radius: Deg<f64>
+}
+
+impl Prediction {
+    /// Returns true if the aircraft is predicted to cross the disc.
+    pub fn is_transit(&self) -> bool {
+        self.separation <= self.body_radius
+    }
+}
+
+impl std::fmt::Display for Prediction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} in {:.0} s, separation {:.2}° (disc radius {:.2}°)",
+            self.aircraft,
+            if self.is_transit() { "transits" } else { "passes near" },
+            self.body,
+            self.time_to_event.as_secs_f64(),
+            self.separation.0,
+            self.body_radius.0
+        )
+    }
+}
+
+fn julian_day(t: &chrono::DateTime<chrono::Utc>) -> f64 {
+    t.timestamp() as f64 / 86400.0 + t.timestamp_subsec_nanos() as f64 / 86400.0e9 + 2_440_587.5
+}
+
+fn sin_deg(value: f64) -> f64 { Rad::from(Deg(value)).0.sin() }
+
+fn cos_deg(value: f64) -> f64 { Rad::from(Deg(value)).0.cos() }
+
+/// Returns unit vector in the equatorial frame of date corresponding to ecliptic coordinates (degrees).
+fn ecliptic_to_equatorial(lon: f64, lat: f64, obliquity: f64) -> Vector3<f64> {
+    Vector3{
+        x: cos_deg(lat) * cos_deg(lon),
+        y: cos_deg(obliquity) * cos_deg(lat) * sin_deg(lon) - sin_deg(obliquity) * sin_deg(lat),
+        z: sin_deg(obliquity) * cos_deg(lat) * sin_deg(lon) + cos_deg(obliquity) * sin_deg(lat)
+    }
+}
+
+/// Returns geocentric position (meters) of the Sun in the equatorial frame of date; accuracy ca. 0.01°.
+/// `d`: days since J2000.0 (TT).
+fn sun_equatorial(d: f64) -> Vector3<f64> {
+    let mean_lon = 280.460 + 0.9856474 * d;
+    let mean_anomaly = 357.528 + 0.9856003 * d;
+    let lon = mean_lon + 1.915 * sin_deg(mean_anomaly) + 0.020 * sin_deg(2.0 * mean_anomaly);
+    let distance = 1.00014 - 0.01671 * cos_deg(mean_anomaly) - 0.00014 * cos_deg(2.0 * mean_anomaly);
+    let obliquity = 23.439 - 0.0000004 * d;
+
+    distance * AU_M * ecliptic_to_equatorial(lon, 0.0, obliquity)
+}
+
+/// Returns geocentric position (meters) of the Moon in the equatorial frame of date; uses the largest terms
+/// of the series from J. Meeus, "Astronomical Algorithms", ch. 47 (accuracy ca. 0.01°).
+/// `d`: days since J2000.0 (TT).
+fn moon_equatorial(d: f64) -> Vector3<f64> {
+    // multiples of D, M, M', F; longitude (10^-6 deg); distance (m)
+    const LON_DIST_TERMS: [(f64, f64, f64, f64, f64, f64); 32] = [
+        (0.0, 0.0, 1.0, 0.0, 6288774.0, -20905355.0),
+        (2.0, 0.0, -1.0, 0.0, 1274027.0, -3699111.0),
+        (2.0, 0.0, 0.0, 0.0, 658314.0, -2955968.0),
+        (0.0, 0.0, 2.0, 0.0, 213618.0, -569925.0),
+        (0.0, 1.0, 0.0, 0.0, -185116.0, 48888.0),
+        (0.0, 0.0, 0.0, 2.0, -114332.0, -3149.0),
+        (2.0, 0.0, -2.0, 0.0, 58793.0, 246158.0),
+        (2.0, -1.0, -1.0, 0.0, 57066.0, -152138.0),
+        (2.0, 0.0, 1.0, 0.0, 53322.0, -170733.0),
+        (2.0, -1.0, 0.0, 0.0, 45758.0, -204586.0),
+        (0.0, 1.0, -1.0, 0.0, -40923.0, -129620.0),
+        (1.0, 0.0, 0.0, 0.0, -34720.0, 108743.0),
+        (0.0, 1.0, 1.0, 0.0, -30383.0, 104755.0),
+        (2.0, 0.0, 0.0, -2.0, 15327.0, 10321.0),
+        (0.0, 0.0, 1.0, 2.0, -12528.0, 0.0),
+        (0.0, 0.0, 1.0, -2.0, 10980.0, 79661.0),
+        (4.0, 0.0, -1.0, 0.0, 10675.0, -34782.0),
+        (0.0, 0.0, 3.0, 0.0, 10034.0, -23210.0),
+        (4.0, 0.0, -2.0, 0.0, 8548.0, -21636.0),
+        (2.0, 1.0, -1.0, 0.0, -7888.0, 24208.0),
+        (2.0, 1.0, 0.0, 0.0, -6766.0, 30824.0),
+        (1.0, 0.0, -1.0, 0.0, -5163.0, -8379.0),
+        (1.0, 1.0, 0.0, 0.0, 4987.0, -16675.0),
+        (2.0, -1.0, 1.0, 0.0, 4036.0, -12831.0),
+        (2.0, 0.0, 2.0, 0.0, 3994.0, -10445.0),
+        (4.0, 0.0, 0.0, 0.0, 3861.0, -11650.0),
+        (2.0, 0.0, -3.0, 0.0, 3665.0, 14403.0),
+        (0.0, 1.0, -2.0, 0.0, -2689.0, -7003.0),
+        (2.0, 0.0, -1.0, 2.0, -2602.0, 0.0),
+        (2.0, -1.0, -2.0, 0.0, 2390.0, 10056.0),
+        (1.0, 0.0, 1.0, 0.0, -2348.0, 6322.0),
+        (2.0, -2.0, 0.0, 0.0, 2236.0, -9884.0)
+    ];
+
+    // multiples of D, M, M', F; latitude (10^-6 deg)
+    const LAT_TERMS: [(f64, f64, f64, f64, f64); 17] = [
+        (0.0, 0.0, 0.0, 1.0, 5128122.0),
+        (0.0, 0.0, 1.0, 1.0, 280602.0),
+        (0.0, 0.0, 1.0, -1.0, 277693.0),
+        (2.0, 0.0, 0.0, -1.0, 173237.0),
+        (2.0, 0.0, -1.0, 1.0, 55413.0),
+        (2.0, 0.0, -1.0, -1.0, 46271.0),
+        (2.0, 0.0, 0.0, 1.0, 32573.0),
+        (0.0, 0.0, 2.0, 1.0, 17198.0),
+        (2.0, 0.0, 1.0, -1.0, 9266.0),
+        (0.0, 0.0, 2.0, -1.0, 8822.0),
+        (2.0, -1.0, 0.0, -1.0, 8216.0),
+        (2.0, 0.0, -2.0, -1.0, 4324.0),
+        (2.0, 0.0, 1.0, 1.0, 4200.0),
+        (2.0, 1.0, 0.0, -1.0, -3359.0),
+        (2.0, -1.0, -1.0, 1.0, 2463.0),
+        (2.0, -1.0, 0.0, 1.0, 2211.0),
+        (2.0, -1.0, -1.0, -1.0, 2065.0)
+    ];
+
+    let t = d / 36525.0;
+
+    let mean_lon = 218.3164477 + 481267.88123421 * t;
+    let elongation = 297.8501921 + 445267.1114034 * t;
+    let sun_anomaly = 357.5291092 + 35999.0502909 * t;
+    let moon_anomaly = 134.9633964 + 477198.8675055 * t;
+    let arg_of_lat = 93.2720950 + 483202.0175233 * t;
+    let e = 1.0 - 0.002516 * t;
+    let a1 = 119.75 + 131.849 * t;
+    let a2 = 53.09 + 479264.290 * t;
+    let a3 = 313.45 + 481266.484 * t;
+
+    let argument = |md: f64, mm: f64, mm1: f64, mf: f64| {
+        md * elongation + mm * sun_anomaly + mm1 * moon_anomaly + mf * arg_of_lat
+    };
+    // terms depending on the Sun's mean anomaly are scaled due to the decreasing eccentricity of Earth's orbit
+    let e_factor = |mm: f64| e.powi(mm.abs() as i32);
+
+    let mut sum_lon = 3958.0 * sin_deg(a1) + 1962.0 * sin_deg(mean_lon - arg_of_lat) + 318.0 * sin_deg(a2);
+    let mut sum_dist = 0.0;
+    for (md, mm, mm1, mf, lon, dist) in LON_DIST_TERMS {
+        let arg = argument(md, mm, mm1, mf);
+        sum_lon += e_factor(mm) * lon * sin_deg(arg);
+        sum_dist += e_factor(mm) * dist * cos_deg(arg);
+    }
+
+    let mut sum_lat = -2235.0 * sin_deg(mean_lon) + 382.0 * sin_deg(a3) + 175.0 * sin_deg(a1 - arg_of_lat)
+        + 175.0 * sin_deg(a1 + arg_of_lat) + 127.0 * sin_deg(mean_lon - moon_anomaly)
+        - 115.0 * sin_deg(mean_lon + moon_anomaly);
+    for (md, mm, mm1, mf, lat) in LAT_TERMS {
+        sum_lat += e_factor(mm) * lat * sin_deg(argument(md, mm, mm1, mf));
+    }
+
+    let lon = mean_lon + sum_lon * 1.0e-6;
+    let lat = sum_lat * 1.0e-6;
+    let distance = 385_000_560.0 + sum_dist;
+    let obliquity = 23.439291 - 0.0130042 * t;
+
+    distance * ecliptic_to_equatorial(lon, lat, obliquity)
+}
+
+/// Returns Greenwich mean sidereal time (degrees, not normalized). `d_ut`: days since J2000.0 (UT).
+fn gmst(d_ut: f64) -> f64 {
+    280.46061837 + 360.98564736629 * d_ut
+}
+
+/// Returns position (meters) of `body` in the global frame (see `data::to_global`) at `t`.
+fn body_global_position(body: Body, t: &chrono::DateTime<chrono::Utc>) -> Point3<f64> {
+    let d_ut = julian_day(t) - J2000_JD;
+    let d_tt = d_ut + DELTA_T_S / 86400.0;
+
+    let equatorial = match body {
+        Body::Sun => sun_equatorial(d_tt),
+        Body::Moon => moon_equatorial(d_tt)
+    };
+
+    let gmst = gmst(d_ut);
+
+    Point3{
+        x: equatorial.x * cos_deg(gmst) + equatorial.y * sin_deg(gmst),
+        y: -equatorial.x * sin_deg(gmst) + equatorial.y * cos_deg(gmst),
+        z: equatorial.z
+    }
+}
+
+fn body_radius_m(body: Body) -> f64 {
+    match body {
+        Body::Sun => SUN_RADIUS_M,
+        Body::Moon => MOON_RADIUS_M
+    }
+}
+
+/// Returns (topocentric) position of `body` as seen by `observer` at `t`.
+pub fn body_position(body: Body, observer: &GeoPos, t: &chrono::DateTime<chrono::Utc>) -> BodyPosition {
+    let position = body_global_position(body, t);
+    let (azimuth, elevation) = global_azimuth_elevation(observer, &position);
+    let distance = (position - to_global(observer)).magnitude();
+
+    BodyPosition{ azimuth, elevation, radius: Deg::from(Rad((body_radius_m(body) / distance).asin())) }
+}
+
+fn angle_between(v1: &Vector3<f64>, v2: &Vector3<f64>) -> Deg<f64> {
+    Deg::from(Rad(v1.cross(*v2).magnitude().atan2(v1.dot(*v2))))
+}
+
+/// Predicts aircraft passing near the Sun or Moon within `settings.look_ahead` from `now`
+/// (`now_utc` being the same moment); results are sorted by time.
+pub fn predict(
+    aircraft: &HashMap<ModeSTransponderCode, Aircraft>,
+    observer: &GeoPos,
+    now: std::time::Instant,
+    now_utc: chrono::DateTime<chrono::Utc>,
+    settings: &Settings
+) -> Vec<Prediction> {
+    let observer_pos = to_global(observer);
+    let up = LocalFrame::new(observer).up;
+    let look_ahead_utc = now_utc + chrono::Duration::from_std(settings.look_ahead).unwrap_or(chrono::Duration::zero());
+
+    let mut predictions = vec![];
+
+    for body in Body::ALL {
+        let position = body_position(body, observer, &now_utc);
+        let dir_start = (body_global_position(body, &now_utc) - observer_pos).normalize();
+        let dir_end = (body_global_position(body, &look_ahead_utc) - observer_pos).normalize();
+        if dir_start.dot(up) < 0.0 && dir_end.dot(up) < 0.0 { continue; }
+
+        // the body moves slowly enough to be interpolated linearly
+        let body_dir = |dt: f64| {
+            let fraction = if settings.look_ahead.is_zero() { 0.0 } else { dt / settings.look_ahead.as_secs_f64() };
+            (dir_start + fraction * (dir_end - dir_start)).normalize()
+        };
+
+        for a in aircraft.values() {
+            if a.on_ground == Some(true) { continue; }
+
+            let aircraft_dir = |dt: f64| {
+                extrapolate_position(a, now + std::time::Duration::from_secs_f64(dt))
+                    .map(|pos| (to_global(&pos) - observer_pos).normalize())
+            };
+            let separation = |dt: f64| aircraft_dir(dt).map(|dir| (angle_between(&dir, &body_dir(dt)), dir));
+
+            let num_steps = (settings.look_ahead.as_secs_f64() / COARSE_STEP.as_secs_f64()) as usize;
+            let mut closest: Option<(usize, Deg<f64>)> = None;
+            for i in 0..=num_steps {
+                let s = match separation(i as f64 * COARSE_STEP.as_secs_f64()) {
+                    Some((s, _)) => s,
+                    None => break
+                };
+                if closest.is_none_or(|c| s < c.1) { closest = Some((i, s)); }
+            }
+            let closest_step = match closest {
+                Some((i, _)) => i,
+                None => continue
+            };
+
+            // ternary search around the closest sample
+            let mut t0 = (closest_step as f64 - 1.0).max(0.0) * COARSE_STEP.as_secs_f64();
+            let mut t1 = (closest_step as f64 + 1.0).min(num_steps as f64) * COARSE_STEP.as_secs_f64();
+            for _ in 0..NUM_REFINEMENT_ITERATIONS {
+                let m0 = t0 + (t1 - t0) / 3.0;
+                let m1 = t1 - (t1 - t0) / 3.0;
+                if separation(m0).unwrap().0 < separation(m1).unwrap().0 { t1 = m1; } else { t0 = m0; }
+            }
+            let dt = 0.5 * (t0 + t1);
+            let (min_separation, dir) = separation(dt).unwrap();
+
+            if dir.dot(up) > 0.0 && min_separation <= position.radius + settings.max_separation {
+                predictions.push(Prediction{
+                    aircraft: a.id,
+                    body,
+                    time_to_event: std::time::Duration::from_secs_f64(dt),
+                    separation: min_separation,
+                    body_radius: position.radius
+                });
+            }
+        }
+    }
+
+    predictions.sort_by_key(|p| p.time_to_event);
+
+    predictions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::State;
+    use crate::geodesy::{ecef_to_geodetic, geodesic_direct, geodetic_to_ecef};
+    use chrono::TimeZone;
+    use pointing_utils::{LatLon, uom::si::{f64, length, velocity}};
+
+    fn position(lat: f64, lon: f64) -> GeoPos {
+        GeoPos{ lat_lon: LatLon{ lat: Deg(lat), lon: Deg(lon) }, elevation: meters(0.0) }
+    }
+
+    /// Returns the UTC time at which the code's TT is 0h of the given day.
+    fn tt_midnight(year: i32, month: u32, day: u32) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc.with_ymd_and_hms(year, month, day, 0, 0, 0).unwrap() - chrono::Duration::seconds(DELTA_T_S as i64)
+    }
+
+    fn days_since_j2000(t: &chrono::DateTime<chrono::Utc>) -> f64 {
+        julian_day(t) - J2000_JD
+    }
+
+    /// Returns days since J2000.0 (TT) at 0h TT of the given day.
+    fn tt_days_since_j2000(year: i32, month: u32, day: u32) -> f64 {
+        days_since_j2000(&chrono::Utc.with_ymd_and_hms(year, month, day, 0, 0, 0).unwrap())
+    }
+
+    fn meters(value: f64) -> f64::Length {
+        f64::Length::new::<length::meter>(value)
+    }
+
+    /// Returns right ascension, declination (degrees) and distance of an equatorial position.
+    fn ra_dec(v: &Vector3<f64>) -> (f64, f64, f64) {
+        let distance = v.magnitude();
+        (Deg::from(Rad(v.y.atan2(v.x))).0.rem_euclid(360.0), Deg::from(Rad((v.z / distance).asin())).0, distance)
+    }
+
+    fn assert_close(expected: f64, actual: f64, tolerance: f64) {
+        assert!((expected - actual).abs() <= tolerance, "expected {}, got {}", expected, actual);
+    }
+
+    // Reference values of the Sun and Moon come from the worked examples of J. Meeus, "Astronomical Algorithms"
+    // (2nd ed.); they are apparent positions, i.e., differ from the mean positions computed here by nutation
+    // (< 0.005°). Topocentric reference azimuths and elevations have been computed from them independently
+    // (WGS84 observer, sidereal time from Meeus eq. 12.4 plus the equation of the equinoxes).
+
+    #[test]
+    fn sidereal_time() {
+        // Meeus, examples 12.a and 12.b
+        let t = chrono::Utc.with_ymd_and_hms(1987, 4, 10, 0, 0, 0).unwrap();
+        assert_close(197.693195, gmst(days_since_j2000(&t)).rem_euclid(360.0), 1.0e-5);
+        let t = chrono::Utc.with_ymd_and_hms(1987, 4, 10, 19, 21, 0).unwrap();
+        assert_close(128.737873, gmst(days_since_j2000(&t)).rem_euclid(360.0), 1.0e-5);
+    }
+
+    #[test]
+    fn sun_geocentric() {
+        // Meeus, example 25.a: 1992 October 13, 0h TT
+        let (ra, dec, distance) = ra_dec(&sun_equatorial(tt_days_since_j2000(1992, 10, 13)));
+        assert_close(198.38083, ra, 0.01);
+        assert_close(-7.78507, dec, 0.01);
+        assert_close(0.99766, distance / AU_M, 1.0e-4);
+    }
+
+    #[test]
+    fn moon_geocentric() {
+        // Meeus, example 47.a: 1992 April 12, 0h TT
+        let (ra, dec, distance) = ra_dec(&moon_equatorial(tt_days_since_j2000(1992, 4, 12)));
+        assert_close(134.688470, ra, 0.02);
+        assert_close(13.768368, dec, 0.02);
+        assert_close(368_409.7, distance / 1000.0, 25.0);
+    }
+
+    #[test]
+    fn sun_topocentric() {
+        let sun = body_position(Body::Sun, &position(-33.87, 151.21), &tt_midnight(1992, 10, 13));
+        assert_close(48.0699, sun.azimuth.0, 0.02);
+        assert_close(54.7894, sun.elevation.0, 0.02);
+        assert_close(Deg::from(Rad((SUN_RADIUS_M / (0.99766 * AU_M)).asin())).0, sun.radius.0, 1.0e-4);
+    }
+
+    #[test]
+    fn moon_topocentric() {
+        // the Moon's parallax (here ca. 0.64°) must be taken into account
+        let moon = body_position(Body::Moon, &position(40.0, -100.0), &tt_midnight(1992, 4, 12));
+        assert_close(121.0878, moon.azimuth.0, 0.03);
+        assert_close(49.3462, moon.elevation.0, 0.03);
+        assert_close(Deg::from(Rad((MOON_RADIUS_M / 363_547_198.0).asin())).0, moon.radius.0, 1.0e-4);
+    }
+
+    #[test]
+    fn transit_prediction() {
+        const SPEED: f64 = 250.0;
+        const TIME_TO_TRANSIT: f64 = 30.0;
+
+        let observer = position(-33.87, 151.21);
+        let now = std::time::Instant::now();
+        let now_utc = tt_midnight(1992, 10, 13);
+
+        // aircraft flying east, crossing the line of sight to the Sun's center (15 km from the observer)
+        // in `TIME_TO_TRANSIT`
+        let t_transit = now_utc + chrono::Duration::seconds(TIME_TO_TRANSIT as i64);
+        let observer_pos = geodetic_to_ecef(&observer);
+        let sun_dir = (body_global_position(Body::Sun, &t_transit) - observer_pos).normalize();
+        let transit_point = ecef_to_geodetic(&(observer_pos + 15_000.0 * sun_dir));
+        let aircraft = |id: u32, north_offset: f64| {
+            let (at_transit, _) = geodesic_direct(&transit_point.lat_lon, Deg(0.0), meters(north_offset));
+            let (start, _) = geodesic_direct(&at_transit, Deg(270.0), meters(SPEED * TIME_TO_TRANSIT));
+            Aircraft{
+                id: ModeSTransponderCode::new(id),
+                state: State::Normal,
+                callsign: None,
+                registry_entry: None,
+                lat_lon: Some((start, now)),
+                estimated_lat_lon: None,
+                track: Some(Deg(90.0)),
+                altitude: Some(transit_point.elevation),
+                estimated_altitude: None,
+                ground_speed: Some(f64::Velocity::new::<velocity::meter_per_second>(SPEED)),
+                vertical_rate: None,
+                squawk: None,
+                alert: false,
+                emergency: false,
+                spi: false,
+                on_ground: None,
+                rssi: None,
+                sources: Default::default(),
+                position_source: None,
+                track_filter: None,
+                trail: Default::default(),
+                t_last_update: now
+            }
+        };
+
+        let mut aircraft_map = HashMap::new();
+        for a in [aircraft(1, 0.0), aircraft(2, 5_000.0)] {
+            aircraft_map.insert(a.id, a);
+        }
+        let settings = Settings{ max_separation: Deg(1.0), look_ahead: std::time::Duration::from_secs(120) };
+
+        let predictions = predict(&aircraft_map, &observer, now, now_utc, &settings);
+        assert_eq!(1, predictions.len());
+        let prediction = &predictions[0];
+        assert_eq!(ModeSTransponderCode::new(1), prediction.aircraft);
+        assert_eq!(Body::Sun, prediction.body);
+        assert!(prediction.is_transit());
+        assert_close(TIME_TO_TRANSIT, prediction.time_to_event.as_secs_f64(), 0.5);
+        assert!(prediction.separation.0 < 0.05, "{}", prediction.separation.0);
+
+        // no prediction beyond the look-ahead time
+        let settings = Settings{ look_ahead: std::time::Duration::from_secs(20), ..settings };
+        assert!(predict(&aircraft_map, &observer, now, now_utc, &settings).is_empty());
+    }
+}
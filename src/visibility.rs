@@ -0,0 +1,243 @@
+//
+// Plane Tracker
+// Copyright (c) 2023-2024 Filip Szczerek <ga.software@yahoo.com>
+//
+// This project is licensed under the terms of the MIT license
+// (see the LICENSE file for details).
+//
+
+use cgmath::{Deg, Rad};
+use crate::data::{Aircraft, azimuth_elevation};
+use pointing_utils::GeoPos;
+use std::error::Error;
+
+/// Conditions used for the refraction correction.
+#[derive(Clone, Debug)]
+pub struct Atmosphere {
+    pub pressure_hpa: f64,
+    pub temperature_c: f64
+}
+
+impl Default for Atmosphere {
+    fn default() -> Atmosphere {
+        Atmosphere{ pressure_hpa: 1010.0, temperature_c: 10.0 }
+    }
+}
+
+impl Atmosphere {
+    /// Returns atmospheric refraction (to be added to the true elevation) using Sæmundsson's formula.
+    /// Treats the target as being outside the atmosphere, which slightly overestimates the refraction
+    /// of nearby aircraft low over the horizon.
+    pub fn refraction(&self, true_elevation: Deg<f64>) -> Deg<f64> {
+        // the formula is not valid below ca. -1°
+        let h = true_elevation.0.max(-1.0);
+        let arcmin = 1.02 / Rad::from(Deg(h + 10.3 / (h + 5.11))).0.tan()
+            * (self.pressure_hpa / 1010.0) * (283.0 / (273.0 + self.temperature_c));
+
+        Deg(arcmin / 60.0)
+    }
+}
+
+/// Minimal elevation at which the sky is unobstructed (e.g., by trees and buildings), as a function of azimuth.
+#[derive(Clone, Debug)]
+pub struct HorizonMask {
+    /// Azimuth and elevation, sorted by azimuth; interpolated linearly (wrapping around North).
+    points: Vec<(Deg<f64>, Deg<f64>)>
+}
+
+impl HorizonMask {
+    /// Loads a file of lines "<azimuth> <elevation>" (degrees; values may also be separated by ',' or ';');
+    /// empty lines and lines starting with '#' are ignored.
+    pub fn load(path: &std::path::Path) -> Result<HorizonMask, Box<dyn Error>> {
+        Ok(std::fs::read_to_string(path)?.parse::<HorizonMask>()?)
+    }
+
+    pub fn points(&self) -> &[(Deg<f64>, Deg<f64>)] {
+        &self.points
+    }
+
+    /// Returns elevation of the mask at `azimuth`.
+    pub fn elevation_at(&self, azimuth: Deg<f64>) -> Deg<f64> {
+        let azimuth = Deg(azimuth.0.rem_euclid(360.0));
+
+        let next = self.points.iter().position(|(az, _)| *az > azimuth).unwrap_or(self.points.len());
+        let (az0, el0) = if next == 0 {
+            let (az, el) = *self.points.last().unwrap();
+            (az - Deg(360.0), el)
+        } else {
+            self.points[next - 1]
+        };
+        let (az1, el1) = if next == self.points.len() {
+            let (az, el) = self.points[0];
+            (az + Deg(360.0), el)
+        } else {
+            self.points[next]
+        };
+
+        if az1 == az0 { return el0; }
+
+        el0 + (el1 - el0) * ((azimuth - az0) / (az1 - az0))
+    }
+}
+
+impl std::str::FromStr for HorizonMask {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut points = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            let values = line
+                .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+                .filter(|v| !v.is_empty())
+                .map(|v| v.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+            if values.len() != 2 { return Err(format!("line {}: expected azimuth and elevation", i + 1)); }
+
+            points.push((Deg(values[0].rem_euclid(360.0)), Deg(values[1])));
+        }
+
+        if points.is_empty() { return Err("horizon mask is empty".into()); }
+
+        points.sort_by(|p1, p2| p1.0.0.total_cmp(&p2.0.0));
+
+        Ok(HorizonMask{ points })
+    }
+}
+
+/// Apparent position of an aircraft as seen by the observer.
+#[derive(Copy, Clone, Debug)]
+pub struct ApparentPosition {
+    pub azimuth: Deg<f64>,
+    /// Elevation corrected for refraction (if enabled).
+    pub elevation: Deg<f64>,
+    /// False if below the horizon mask (or the horizon, if there is no mask).
+    pub visible: bool
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Visibility {
+    /// If set, elevations are corrected for refraction.
+    pub atmosphere: Option<Atmosphere>,
+    pub horizon_mask: Option<HorizonMask>
+}
+
+impl Visibility {
+    /// Returns `true_elevation` corrected for refraction (if enabled).
+    pub fn apparent_elevation(&self, true_elevation: Deg<f64>) -> Deg<f64> {
+        match &self.atmosphere {
+            Some(atmosphere) => true_elevation + atmosphere.refraction(true_elevation),
+            None => true_elevation
+        }
+    }
+
+    pub fn is_visible(&self, azimuth: Deg<f64>, apparent_elevation: Deg<f64>) -> bool {
+        match &self.horizon_mask {
+            Some(mask) => apparent_elevation >= mask.elevation_at(azimuth),
+            None => apparent_elevation >= Deg(0.0)
+        }
+    }
+
+    /// Returns `None` if position or altitude of `aircraft` is unknown.
+    pub fn apparent_position(&self, observer: &GeoPos, aircraft: &Aircraft, interpolated: bool) -> Option<ApparentPosition> {
+        let (azimuth, true_elevation) = azimuth_elevation(observer, aircraft, interpolated)?;
+        let elevation = self.apparent_elevation(true_elevation);
+
+        Some(ApparentPosition{ azimuth, elevation, visible: self.is_visible(azimuth, elevation) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_deg_eq(expected: f64, actual: Deg<f64>, tolerance: f64) {
+        assert!((expected - actual.0).abs() <= tolerance, "expected {}°, got {}°", expected, actual.0);
+    }
+
+    #[test]
+    fn mask_parsing() {
+        let mask = "# az. el.\n\n0 10\n270; 0\n  90,20  \n-10 5\n".parse::<HorizonMask>().unwrap();
+        assert_eq!(
+            vec![(0.0, 10.0), (90.0, 20.0), (270.0, 0.0), (350.0, 5.0)],
+            mask.points().iter().map(|(az, el)| (az.0, el.0)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn mask_parsing_errors() {
+        assert!("".parse::<HorizonMask>().is_err());
+        assert!("# comment only\n".parse::<HorizonMask>().is_err());
+        assert!("0 10\n90 abc\n".parse::<HorizonMask>().unwrap_err().starts_with("line 2:"));
+        assert!("0 10\n90\n".parse::<HorizonMask>().unwrap_err().starts_with("line 2:"));
+        assert!("0 10 20\n".parse::<HorizonMask>().unwrap_err().starts_with("line 1:"));
+    }
+
+    #[test]
+    fn mask_interpolation() {
+        let mask = "0 10\n90 20\n270 0".parse::<HorizonMask>().unwrap();
+        assert_deg_eq(10.0, mask.elevation_at(Deg(0.0)), 1.0e-9);
+        assert_deg_eq(15.0, mask.elevation_at(Deg(45.0)), 1.0e-9);
+        assert_deg_eq(10.0, mask.elevation_at(Deg(180.0)), 1.0e-9);
+        assert_deg_eq(5.0, mask.elevation_at(Deg(315.0)), 1.0e-9);
+        assert_deg_eq(5.0, mask.elevation_at(Deg(-45.0)), 1.0e-9);
+        assert_deg_eq(15.0, mask.elevation_at(Deg(405.0)), 1.0e-9);
+
+        // no point at North
+        let mask = "10 10\n350 30".parse::<HorizonMask>().unwrap();
+        assert_deg_eq(20.0, mask.elevation_at(Deg(0.0)), 1.0e-9);
+        assert_deg_eq(15.0, mask.elevation_at(Deg(5.0)), 1.0e-9);
+        assert_deg_eq(25.0, mask.elevation_at(Deg(355.0)), 1.0e-9);
+        assert_deg_eq(20.0, mask.elevation_at(Deg(180.0)), 1.0e-9);
+
+        let mask = "120 7".parse::<HorizonMask>().unwrap();
+        assert_deg_eq(7.0, mask.elevation_at(Deg(300.0)), 1.0e-9);
+    }
+
+    #[test]
+    fn visibility() {
+        let visibility = Visibility::default();
+        assert!(visibility.is_visible(Deg(123.0), Deg(0.0)));
+        assert!(!visibility.is_visible(Deg(123.0), Deg(-0.1)));
+
+        let visibility = Visibility{ atmosphere: None, horizon_mask: Some("0 10\n180 20".parse().unwrap()) };
+        assert!(visibility.is_visible(Deg(90.0), Deg(15.0)));
+        assert!(!visibility.is_visible(Deg(90.0), Deg(14.9)));
+    }
+
+    #[test]
+    fn refraction() {
+        let atmosphere = Atmosphere::default();
+
+        // refraction (arcminutes) from the standard tables (10°C, 1010 hPa; Bennett's fit, see J. Meeus,
+        // "Astronomical Algorithms", ch. 16) at the resulting apparent elevation
+        for (true_elevation, expected) in [(0.0, 28.9), (10.0, 5.35), (45.0, 0.99)] {
+            assert_deg_eq(expected / 60.0, atmosphere.refraction(Deg(true_elevation)), 0.1 / 60.0);
+        }
+
+        assert_deg_eq(0.0, atmosphere.refraction(Deg(90.0)), 0.01 / 60.0);
+        // not extrapolated below the formula's validity
+        assert_eq!(atmosphere.refraction(Deg(-1.0)), atmosphere.refraction(Deg(-5.0)));
+
+        let visibility = Visibility{ atmosphere: Some(atmosphere.clone()), horizon_mask: None };
+        assert_deg_eq(10.0 + 5.35 / 60.0, visibility.apparent_elevation(Deg(10.0)), 0.1 / 60.0);
+        assert_eq!(Deg(10.0), Visibility::default().apparent_elevation(Deg(10.0)));
+    }
+
+    #[test]
+    fn refraction_pressure_and_temperature() {
+        let standard = Atmosphere::default().refraction(Deg(5.0)).0;
+
+        let low_pressure = Atmosphere{ pressure_hpa: 505.0, temperature_c: 10.0 };
+        assert!((low_pressure.refraction(Deg(5.0)).0 - standard / 2.0).abs() < 1.0e-12);
+
+        let cold = Atmosphere{ pressure_hpa: 1010.0, temperature_c: -10.0 };
+        assert!((cold.refraction(Deg(5.0)).0 - standard * 283.0 / 263.0).abs() < 1.0e-12);
+
+        let hot_high = Atmosphere{ pressure_hpa: 800.0, temperature_c: 30.0 };
+        assert!((hot_high.refraction(Deg(5.0)).0 - standard * (800.0 / 1010.0) * (283.0 / 303.0)).abs() < 1.0e-12);
+    }
+}